@@ -5,6 +5,7 @@ typedef struct {
   const char *window_title;
   const char *bundle_id;
   const char *url;
+  pid_t pid;
 } WindowTitle;
 
 WindowTitle *detect_focused_window(void);
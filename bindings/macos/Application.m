@@ -339,6 +339,7 @@ BOOL isDomain(NSString *str) {
     windowTitleStruct->bundle_id =
         bundleId ? strdup([bundleId UTF8String]) : NULL;
     windowTitleStruct->url = url ? strdup([url UTF8String]) : NULL;
+    windowTitleStruct->pid = self.processIdentifier;
 
     return windowTitleStruct;
   }
@@ -7,6 +7,15 @@
 typedef void (*AppBlockedCallback)(const char **app_names,
                                    const char **bundle_ids, int count);
 
+typedef struct {
+  const char *app_name;
+  const char *bundle_id;
+} RunningApp;
+
+RunningApp *get_running_apps(int *count);
+
+void free_running_apps(RunningApp *apps, int count);
+
 BOOL start_blocking(const char **blocked_urls, int url_count,
                     const char *redirect_url, BOOL blocklist_mode);
 
@@ -14,10 +23,10 @@ void stop_blocking(void);
 
 BOOL handle_enter_key_for_site_blocking(void);
 
-BOOL redirect_to_vibes_page(void);
+BOOL redirect_to_vibes_page(const char *redirect_url);
 
-BOOL is_blocked(const char *external_app_id);
+BOOL is_url_element_focused(void);
 
-BOOL close_app(const char *bundle_id, const bool send_callback);
+BOOL close_app(const char *bundle_id, const bool report_blocked);
 
 void register_app_blocked_callback(AppBlockedCallback callback);
\ No newline at end of file
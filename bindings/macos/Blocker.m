@@ -64,38 +64,37 @@ void add_app_to_blocked_batch(NSString *appName, NSString *bundleId) {
   [batchBundleIds addObject:bundleId];
 }
 
-NSArray *get_running_applications(void) {
+// The regular apps that are running, for the sweep start_blocking does in
+// Rust (src/blocking.rs matches them). Free with free_running_apps.
+RunningApp *get_running_apps(int *count) {
   @autoreleasepool {
     NSArray *runningApps = [[NSWorkspace sharedWorkspace] runningApplications];
-    NSMutableArray *bundleIds = [NSMutableArray array];
+    RunningApp *apps =
+        (RunningApp *)calloc(MAX(runningApps.count, 1), sizeof(RunningApp));
+    int found = 0;
 
     for (NSRunningApplication *app in runningApps) {
-      if (app.bundleIdentifier) {
-        [bundleIds addObject:app.bundleIdentifier];
+      if (!app.bundleIdentifier ||
+          app.activationPolicy != NSApplicationActivationPolicyRegular) {
+        continue;
       }
+      NSString *appName = app.localizedName ?: app.bundleIdentifier;
+      apps[found].app_name = strdup([appName UTF8String]);
+      apps[found].bundle_id = strdup([app.bundleIdentifier UTF8String]);
+      found++;
     }
-    return [bundleIds copy];
+    *count = found;
+    return apps;
   }
 }
 
-void close_blocked_apps(void) {
-  @autoreleasepool {
-
-    NSArray *bundleIds = get_running_applications();
-    if (isBlocklistMode) {
-      for (NSString *blockedAppId in blockedApps) {
-        close_app([blockedAppId UTF8String], NO);
-      }
-
-    } else {
-      for (NSString *runningBundleId in bundleIds) {
-        if (![blockedApps containsObject:runningBundleId]) {
-          close_app([runningBundleId UTF8String], NO);
-        }
-      }
+void free_running_apps(RunningApp *apps, int count) {
+  if (apps) {
+    for (int i = 0; i < count; i++) {
+      free((void *)apps[i].app_name);
+      free((void *)apps[i].bundle_id);
     }
-
-    send_blocked_apps_batch();
+    free(apps);
   }
 }
 
@@ -127,7 +126,6 @@ BOOL start_blocking(const char **blocked_urls, int url_count,
     vibesUrl = [NSString stringWithUTF8String:redirect_url];
     NSLog(@"Redirect URL set to: %@", vibesUrl);
     siteBlockingEnabled = YES;
-    close_blocked_apps();
     NSLog(@"Site blocking enabled with mode: %@",
           blocklist_mode ? @"Blocklist" : @"Allowlist");
     return YES;
@@ -139,8 +137,12 @@ void stop_blocking(void) {
   NSLog(@"Site blocking disabled");
 }
 
-BOOL close_app(const char *external_app_id, const bool send_callback) {
-  if (!external_app_id || !siteBlockingEnabled || blockedApps.count == 0) {
+// report_blocked adds the app to the batch sent through appBlockedCallback.
+// Callers that report the closed app themselves pass NO. Whether the app is
+// blocked is decided in Rust, which also closes apps for budgets and
+// schedules while no blocklist is active.
+BOOL close_app(const char *external_app_id, const bool report_blocked) {
+  if (!external_app_id) {
     return NO;
   }
 
@@ -160,11 +162,8 @@ BOOL close_app(const char *external_app_id, const bool send_callback) {
 
       NSString *appName = blockedApp.localizedName;
       NSString *bundleId = blockedApp.bundleIdentifier;
-      if (appName && bundleId) {
+      if (report_blocked && appName && bundleId) {
         add_app_to_blocked_batch(appName, bundleId);
-        if (send_callback) {
-          send_blocked_apps_batch();
-        }
       }
       return YES;
     }
@@ -172,39 +171,12 @@ BOOL close_app(const char *external_app_id, const bool send_callback) {
   return NO;
 }
 
-BOOL is_blocked(const char *external_app_id) {
-  if (!external_app_id || !siteBlockingEnabled || blockedApps.count == 0) {
-    return NO;
-  }
-
-  FocusedApp *frontApp = [FocusedApp frontmostApp];
-  BOOL isUrlElementFocused = [frontApp isUrlElementFocused];
-  if (isUrlElementFocused) {
-    return NO;
-  }
-
+// Matching happens in Rust (src/blocking.rs). Redirecting is skipped while
+// the user is typing in the address bar.
+BOOL is_url_element_focused(void) {
   @autoreleasepool {
-    NSString *currentAppId = [NSString stringWithUTF8String:external_app_id];
-    NSString *cleanedAppId = [currentAppId componentsSeparatedByString:@"/"][0];
-    NSLog(@"cleanedAppId: %@", cleanedAppId);
-
-    BOOL appInList = NO;
-    for (NSString *appId in blockedApps) {
-      if ([cleanedAppId caseInsensitiveCompare:appId] == NSOrderedSame) {
-        appInList = YES;
-        break;
-      }
-    }
-
-    BOOL shouldBlock = isBlocklistMode ? appInList : !appInList;
-
-    if (shouldBlock) {
-      NSLog(@"App ID %@ is %@", currentAppId,
-            isBlocklistMode ? @"blocked (in blocklist)"
-                            : @"blocked (not in allowlist)");
-    }
-
-    return shouldBlock;
+    FocusedApp *frontApp = [FocusedApp frontmostApp];
+    return [frontApp isUrlElementFocused];
   }
 }
 
@@ -334,10 +306,16 @@ void commandBarRedirect() {
   simulateKeyPress(36, 0);
 }
 
-BOOL redirect_to_vibes_page(void) {
+BOOL redirect_to_vibes_page(const char *redirect_url) {
   @autoreleasepool {
     NSLog(@"Redirecting to vibes page");
 
+    // Budgets have their own block page, which may differ from the one
+    // passed to start_blocking
+    if (redirect_url) {
+      vibesUrl = [NSString stringWithUTF8String:redirect_url];
+    }
+
     FocusedApp *frontApp = [FocusedApp frontmostApp];
     if (!frontApp) {
       NSLog(@"Failed to get frontmost application");
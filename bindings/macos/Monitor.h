@@ -28,8 +28,11 @@ typedef void (*WindowEventCallback)(int32_t windowNumber, int32_t x, int32_t y,
 
 const char *get_app_icon_data(const char *bundle_id);
 void free_icon_data(const char *data);
-void start_monitoring(MouseEventCallback mouseCallback,
+bool start_monitoring(MouseEventCallback mouseCallback,
                       KeyboardEventCallback keyboardCallback);
+void run_monitoring_loop(void);
+void stop_monitoring(void);
+double seconds_since_last_input(void);
 void create_screen_border(double red, double green, double blue, double width,
                           double opacity);
 void remove_screen_border(NSWindow *border_window);
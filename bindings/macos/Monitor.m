@@ -13,6 +13,10 @@ typedef void (*WebsiteVisitCallback)(const char *url);
 @property(nonatomic, assign) WindowEventCallback windowCallback;
 @property(nonatomic, assign) WebsiteVisitCallback websiteCallback;
 @property(nonatomic, strong) id axObserver;
+@property(nonatomic, assign) CFMachPortRef eventTap;
+@property(nonatomic, assign) CFRunLoopSourceRef runLoopSource;
+@property(nonatomic, assign) CFRunLoopRef runLoop;
+@property(atomic, assign) BOOL running;
 @end
 
 @implementation MonitorHolder
@@ -27,14 +31,18 @@ void run_loop_cycle() {
   [[NSRunLoop currentRunLoop] runUntilDate:stopDate];
 }
 
-void start_run_loop() {
+// Runs the current thread's run loop until stop_monitoring is called
+void run_monitoring_loop() {
   @autoreleasepool {
     NSLog(@"Processing events");
     NSLog(@"Thread: %@", [NSThread currentThread]);
 
     NSRunLoop *currentRunLoop = [NSRunLoop currentRunLoop];
 
-    [currentRunLoop run];
+    while (monitorHolder.running &&
+           [currentRunLoop runMode:NSDefaultRunLoopMode
+                        beforeDate:[NSDate distantFuture]]) {
+    }
     NSLog(@"Processing events end");
   }
 }
@@ -104,16 +112,20 @@ CGEventRef eventCallback(CGEventTapProxy proxy, CGEventType type,
   return event;
 }
 
-void start_monitoring(MouseEventCallback mouseCallback,
+bool start_monitoring(MouseEventCallback mouseCallback,
                       KeyboardEventCallback keyboardCallback) {
   if (!has_accessibility_permissions()) {
     NSLog(@"start_monitoring - No accessibility permissions");
-    return;
+    return false;
   }
   NSLog(@"start_monitoring");
   if (!monitorHolder) {
     monitorHolder = [[MonitorHolder alloc] init];
   }
+  if (monitorHolder.running) {
+    NSLog(@"start_monitoring - Already running");
+    return false;
+  }
   monitorHolder.mouseCallback = mouseCallback;
   monitorHolder.keyboardCallback = keyboardCallback;
   // Create event tap for mouse clicks, movements, and key events
@@ -129,7 +141,7 @@ void start_monitoring(MouseEventCallback mouseCallback,
       eventMask, eventCallback, NULL);
   if (!_eventTap) {
     NSLog(@"Failed to create event tap");
-    return;
+    return false;
   }
   CFRunLoopSourceRef _runLoopSource =
       CFMachPortCreateRunLoopSource(kCFAllocatorDefault, _eventTap, 0);
@@ -137,7 +149,34 @@ void start_monitoring(MouseEventCallback mouseCallback,
                      kCFRunLoopCommonModes);
   CGEventTapEnable(_eventTap, true);
 
-  start_run_loop();
+  monitorHolder.eventTap = _eventTap;
+  monitorHolder.runLoopSource = _runLoopSource;
+  monitorHolder.runLoop = CFRunLoopGetCurrent();
+  monitorHolder.running = YES;
+  return true;
+}
+
+void stop_monitoring() {
+  if (!monitorHolder || !monitorHolder.running) {
+    return;
+  }
+  NSLog(@"stop_monitoring");
+  monitorHolder.running = NO;
+
+  CGEventTapEnable(monitorHolder.eventTap, false);
+  CFRunLoopRemoveSource(monitorHolder.runLoop, monitorHolder.runLoopSource,
+                        kCFRunLoopCommonModes);
+  CFMachPortInvalidate(monitorHolder.eventTap);
+  CFRelease(monitorHolder.runLoopSource);
+  CFRelease(monitorHolder.eventTap);
+  monitorHolder.runLoopSource = NULL;
+  monitorHolder.eventTap = NULL;
+
+  // Wakes run_monitoring_loop so it sees running is NO
+  CFRunLoopStop(monitorHolder.runLoop);
+  monitorHolder.runLoop = NULL;
+  monitorHolder.mouseCallback = NULL;
+  monitorHolder.keyboardCallback = NULL;
 }
 
 const char *get_app_icon_data(const char *bundle_id) {
@@ -181,3 +220,8 @@ void free_icon_data(const char *data) {
     free((void *)data);
   }
 }
+
+double seconds_since_last_input(void) {
+  return CGEventSourceSecondsSinceLastEventType(
+      kCGEventSourceStateCombinedSessionState, kCGAnyInputEventType);
+}
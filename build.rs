@@ -109,7 +109,7 @@ fn main() {
             .args(&clang_args)
             // Add all source files as separate arguments
             .args(source_files.iter().map(|p| p.to_str().unwrap()))
-            .args(&[
+            .args([
                 "-I",
                 include_dir.to_str().unwrap(),
                 "-o",
@@ -0,0 +1,9 @@
+#!/bin/sh
+# Refreshes the public suffix list bundled in src/public_suffix_list.dat.
+# The list is MPL-2.0 licensed, see the header of the file.
+set -eu
+
+cd "$(dirname "$0")/.."
+curl -fsSL https://publicsuffix.org/list/public_suffix_list.dat -o src/public_suffix_list.dat.new
+mv src/public_suffix_list.dat.new src/public_suffix_list.dat
+cargo test --lib urls
@@ -0,0 +1,34 @@
+//! Native messaging host started by the browser for the os-monitor
+//! extension. Relays the extension's tab reports to the monitor's
+//! `NativeMessagingBridge` and its redirects back. Logs go to stderr since
+//! stdout carries the messages.
+
+#[cfg(unix)]
+fn main() {
+    use os_monitor::native_messaging::{default_socket_path, relay};
+
+    env_logger::init();
+    // The browser starts the host itself, so its parent owns the windows
+    let browser_pid = std::os::unix::process::parent_id();
+    let socket_path = default_socket_path();
+    log::info!(
+        "Relaying browser {} to {}",
+        browser_pid,
+        socket_path.display()
+    );
+    if let Err(e) = relay(
+        std::io::stdin().lock(),
+        std::io::stdout(),
+        &socket_path,
+        browser_pid,
+    ) {
+        log::error!("Native messaging host failed: {}", e);
+        std::process::exit(1);
+    }
+}
+
+#[cfg(not(unix))]
+fn main() {
+    eprintln!("os-monitor-native-host is only supported on macOS and Linux");
+    std::process::exit(1);
+}
@@ -1,13 +1,17 @@
+#[cfg(any(target_os = "macos", target_os = "windows"))]
 use std::ffi::c_char;
 
+#[cfg(target_os = "macos")]
 #[repr(C)]
 pub struct RawWindowTitle {
     pub app_name: *const c_char,
     pub window_title: *const c_char,
     pub bundle_id: *const c_char,
     pub url: *const c_char,
+    pub pid: i32,
 }
 
+#[cfg(target_os = "macos")]
 impl RawWindowTitle {
     pub fn get_url(&self) -> Option<String> {
         if self.url.is_null() {
@@ -41,6 +45,13 @@ impl RawWindowTitle {
     }
 }
 
+#[cfg(target_os = "macos")]
+#[repr(C)]
+pub struct RawRunningApp {
+    pub app_name: *const c_char,
+    pub bundle_id: *const c_char,
+}
+
 #[cfg(target_os = "macos")]
 #[link(name = "MacMonitor", kind = "dylib")]
 extern "C" {
@@ -53,7 +64,10 @@ extern "C" {
     pub fn start_monitoring(
         mouse_callback: extern "C" fn(f64, f64, i32, i32),
         keyboard_callback: extern "C" fn(i32),
-    );
+    ) -> bool;
+    pub fn run_monitoring_loop();
+    pub fn stop_monitoring();
+    pub fn seconds_since_last_input() -> f64;
     pub fn start_blocking(
         blocked_urls: *const *const c_char,
         url_count: i32,
@@ -61,9 +75,11 @@ extern "C" {
         blocklist_mode: bool,
     ) -> bool;
     pub fn stop_blocking();
-    pub fn is_blocked(external_app_id: *const c_char) -> bool;
-    pub fn close_app(bundle_id: *const c_char, force: bool) -> bool;
-    pub fn redirect_to_vibes_page() -> bool;
+    pub fn is_url_element_focused() -> bool;
+    pub fn close_app(bundle_id: *const c_char, report_blocked: bool) -> bool;
+    pub fn get_running_apps(count: *mut i32) -> *mut RawRunningApp;
+    pub fn free_running_apps(apps: *mut RawRunningApp, count: i32);
+    pub fn redirect_to_vibes_page(redirect_url: *const c_char) -> bool;
     pub fn register_app_blocked_callback(
         callback: extern "C" fn(*const *const c_char, *const *const c_char, i32),
     );
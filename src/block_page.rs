@@ -0,0 +1,815 @@
+use crate::blocking::split_url;
+use crate::{BlockDecision, BlockableItem, NormalizedUrl, RuleKind, RuleSource, UnlockFriction};
+use std::collections::hash_map::RandomState;
+use std::hash::{BuildHasher, Hasher};
+use std::io::{self, BufRead, BufReader, Read, Write};
+use std::net::{Ipv4Addr, SocketAddr, TcpListener, TcpStream};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::thread::JoinHandle;
+use std::time::Duration;
+
+const POLL_INTERVAL: Duration = Duration::from_millis(50);
+const MAX_HEADERS: usize = 16 * 1024;
+const MAX_BODY: usize = 4 * 1024;
+const UNLOCK_PATH: &str = "/__os-monitor/unlock";
+const CONFIRM_PATH: &str = "/__os-monitor/confirm";
+
+/// The page served when no template is set. See `BlockPageServer::template`
+/// for the placeholders.
+pub const DEFAULT_BLOCK_PAGE: &str = r#"<!DOCTYPE html>
+<html>
+<head>
+<meta charset="utf-8">
+<title>{{domain}} is blocked</title>
+<style>
+body { font-family: system-ui, sans-serif; max-width: 36em; margin: 15vh auto; padding: 0 1em; color: #222; }
+p.rule { color: #666; }
+</style>
+</head>
+<body>
+<h1>{{domain}} is blocked</h1>
+<p class="rule">{{rule}}</p>
+<p>{{remaining}}</p>
+{{unlock}}
+</body>
+</html>
+"#;
+
+/// A block page served from a loopback port, so blocking works offline and
+/// the redirect target is under the host app's control. Pass
+/// `BlockPageHandle::url()` to `start_blocking` as the redirect url. It also
+/// answers requests for any host, so a `DnsSinkhole` redirecting to it on
+/// port 80 shows the page for the site that was looked up.
+///
+/// The page names the blocked site (from the `url` query parameter, the
+/// request's host, or else the last redirected site), the rule that blocked
+/// it, how long blocking lasts, and offers a button that requests an unlock.
+#[derive(Clone, Debug)]
+pub struct BlockPageServer {
+    listen: SocketAddr,
+    template: String,
+    unlock: Option<(UnlockFriction, Duration)>,
+}
+
+impl Default for BlockPageServer {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl BlockPageServer {
+    /// A free port on 127.0.0.1, the default page, and unlocks after a five
+    /// minute delay for fifteen minutes
+    pub fn new() -> Self {
+        Self {
+            listen: SocketAddr::from((Ipv4Addr::LOCALHOST, 0)),
+            template: DEFAULT_BLOCK_PAGE.to_string(),
+            unlock: Some((
+                UnlockFriction::Delay(Duration::from_secs(5 * 60)),
+                Duration::from_secs(15 * 60),
+            )),
+        }
+    }
+
+    pub fn listen(mut self, addr: SocketAddr) -> Self {
+        self.listen = addr;
+        self
+    }
+
+    /// HTML for the page. `{{domain}}`, `{{rule}}` and `{{remaining}}` are
+    /// replaced with escaped text, `{{unlock}}` with the unlock button or the
+    /// state of the requested unlock.
+    pub fn template(mut self, html: &str) -> Self {
+        self.template = html.to_string();
+        self
+    }
+
+    /// The friction and length of unlocks requested from the page
+    pub fn unlock(mut self, friction: UnlockFriction, duration: Duration) -> Self {
+        self.unlock = Some((friction, duration));
+        self
+    }
+
+    /// Leaves the unlock button out
+    pub fn without_unlock(mut self) -> Self {
+        self.unlock = None;
+        self
+    }
+
+    /// Binds the port and serves on a background thread until the handle is
+    /// stopped or dropped
+    pub fn start(self) -> io::Result<BlockPageHandle> {
+        let listener = TcpListener::bind(self.listen)?;
+        let local_addr = listener.local_addr()?;
+        listener.set_nonblocking(true)?;
+        log::info!("Block page served on http://{}/", local_addr);
+
+        let stop = Arc::new(AtomicBool::new(false));
+        let server = Arc::new(Server {
+            config: self,
+            local_addr,
+            token: token(),
+        });
+        let thread_stop = stop.clone();
+        let thread = std::thread::Builder::new()
+            .name("block-page".into())
+            .spawn(move || serve(&server, &listener, &thread_stop))?;
+        Ok(BlockPageHandle {
+            local_addr,
+            stop,
+            thread: Some(thread),
+        })
+    }
+}
+
+/// A running `BlockPageServer`, stopped when dropped
+pub struct BlockPageHandle {
+    local_addr: SocketAddr,
+    stop: Arc<AtomicBool>,
+    thread: Option<JoinHandle<()>>,
+}
+
+impl BlockPageHandle {
+    pub fn local_addr(&self) -> SocketAddr {
+        self.local_addr
+    }
+
+    /// The page's url, e.g. `http://127.0.0.1:49152/`
+    pub fn url(&self) -> String {
+        format!("http://{}/", self.local_addr)
+    }
+
+    pub fn stop(mut self) {
+        self.stop_thread();
+    }
+
+    fn stop_thread(&mut self) {
+        self.stop.store(true, Ordering::SeqCst);
+        if let Some(thread) = self.thread.take() {
+            let _ = thread.join();
+        }
+    }
+}
+
+impl Drop for BlockPageHandle {
+    fn drop(&mut self) {
+        self.stop_thread();
+    }
+}
+
+/// Guards the unlock forms against other pages posting to them
+fn token() -> String {
+    let mut hasher = RandomState::new().build_hasher();
+    hasher.write_u128(
+        std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_nanos(),
+    );
+    format!("{:016x}", hasher.finish())
+}
+
+struct Server {
+    config: BlockPageServer,
+    local_addr: SocketAddr,
+    token: String,
+}
+
+struct Request {
+    method: String,
+    path: String,
+    query: Vec<(String, String)>,
+    host: Option<String>,
+    form: Vec<(String, String)>,
+}
+
+impl Request {
+    fn param(&self, name: &str) -> Option<&str> {
+        self.form
+            .iter()
+            .chain(&self.query)
+            .find(|(key, _)| key == name)
+            .map(|(_, value)| value.as_str())
+    }
+}
+
+struct Response {
+    status: &'static str,
+    location: Option<String>,
+    body: String,
+}
+
+impl Response {
+    fn page(body: String) -> Self {
+        Self {
+            status: "200 OK",
+            location: None,
+            body,
+        }
+    }
+
+    fn redirect(location: String) -> Self {
+        Self {
+            status: "303 See Other",
+            location: Some(location),
+            body: String::new(),
+        }
+    }
+
+    fn error(status: &'static str) -> Self {
+        Self {
+            status,
+            location: None,
+            body: format!("<!DOCTYPE html><p>{}</p>\n", status),
+        }
+    }
+}
+
+fn escape_html(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&#39;"),
+            _ => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+fn encode_component(text: &str) -> String {
+    text.bytes()
+        .map(|byte| match byte {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'.' | b'_' | b'~' => {
+                (byte as char).to_string()
+            }
+            _ => format!("%{:02X}", byte),
+        })
+        .collect()
+}
+
+/// Decodes `application/x-www-form-urlencoded` text, also used for queries
+fn decode_component(text: &str) -> String {
+    let bytes = text.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        match bytes[i] {
+            b'+' => decoded.push(b' '),
+            b'%' => {
+                let hex = text
+                    .get(i + 1..i + 3)
+                    .and_then(|hex| u8::from_str_radix(hex, 16).ok());
+                match hex {
+                    Some(byte) => {
+                        decoded.push(byte);
+                        i += 2;
+                    }
+                    None => decoded.push(b'%'),
+                }
+            }
+            byte => decoded.push(byte),
+        }
+        i += 1;
+    }
+    String::from_utf8_lossy(&decoded).into_owned()
+}
+
+fn parse_form(text: &str) -> Vec<(String, String)> {
+    text.split('&')
+        .filter(|pair| !pair.is_empty())
+        .map(|pair| {
+            let (key, value) = pair.split_once('=').unwrap_or((pair, ""));
+            (decode_component(key), decode_component(value))
+        })
+        .collect()
+}
+
+fn read_request(stream: &mut TcpStream) -> io::Result<Request> {
+    let invalid = |reason: &str| io::Error::new(io::ErrorKind::InvalidData, reason.to_string());
+    let mut reader = BufReader::new(stream.take((MAX_HEADERS + MAX_BODY) as u64));
+    let mut line = String::new();
+    reader.read_line(&mut line)?;
+    let mut parts = line.split_whitespace();
+    let (Some(method), Some(target)) = (parts.next(), parts.next()) else {
+        return Err(invalid("malformed request line"));
+    };
+    let (method, target) = (method.to_string(), target.to_string());
+
+    let mut host = None;
+    let mut content_length = 0;
+    let mut header_bytes = line.len();
+    loop {
+        line.clear();
+        header_bytes += reader.read_line(&mut line)?;
+        if header_bytes > MAX_HEADERS {
+            return Err(invalid("headers too long"));
+        }
+        let header = line.trim_end();
+        if header.is_empty() {
+            break;
+        }
+        let Some((name, value)) = header.split_once(':') else {
+            continue;
+        };
+        match name.trim().to_ascii_lowercase().as_str() {
+            "host" => host = Some(value.trim().to_string()),
+            "content-length" => {
+                content_length = value.trim().parse().map_err(|_| invalid("bad length"))?;
+            }
+            _ => {}
+        }
+    }
+    if content_length > MAX_BODY {
+        return Err(invalid("body too long"));
+    }
+    let mut body = vec![0; content_length];
+    reader.read_exact(&mut body)?;
+
+    let (path, query) = target.split_once('?').unwrap_or((&target, ""));
+    Ok(Request {
+        method,
+        path: path.to_string(),
+        query: parse_form(query),
+        host,
+        form: parse_form(&String::from_utf8_lossy(&body)),
+    })
+}
+
+fn write_response(stream: &mut TcpStream, response: &Response) -> io::Result<()> {
+    let mut head = format!(
+        "HTTP/1.1 {}\r\nContent-Type: text/html; charset=utf-8\r\nContent-Length: {}\r\nCache-Control: no-store\r\nConnection: close\r\n",
+        response.status,
+        response.body.len()
+    );
+    if let Some(location) = &response.location {
+        head.push_str(&format!("Location: {}\r\n", location));
+    }
+    head.push_str("\r\n");
+    stream.write_all(head.as_bytes())?;
+    stream.write_all(response.body.as_bytes())?;
+    stream.flush()
+}
+
+fn describe_rule(decision: &BlockDecision) -> String {
+    let Some(matched) = &decision.matched else {
+        return "Only allowed sites can be opened right now".to_string();
+    };
+    let rule = match matched.rule {
+        RuleKind::Exact => "",
+        RuleKind::Domain => " and its subdomains",
+        RuleKind::PathPrefix => " and the pages below it",
+        RuleKind::Regex => " (pattern)",
+        RuleKind::AppIdPattern => " (pattern)",
+    };
+    match matched.source {
+        RuleSource::Budget => format!(
+            "You've used up today's time for {}{}",
+            matched.pattern, rule
+        ),
+        _ => format!(
+            "Blocked by the block list entry {}{}",
+            matched.pattern, rule
+        ),
+    }
+}
+
+fn describe_remaining(remaining: Option<Duration>) -> String {
+    let Some(remaining) = remaining else {
+        return "Blocked until blocking is turned off".to_string();
+    };
+    let minutes = remaining.as_secs().div_ceil(60);
+    match (minutes / 60, minutes % 60) {
+        (0, 0) => "Unblocked in less than a minute".to_string(),
+        (0, 1) => "Unblocked in 1 minute".to_string(),
+        (0, minutes) => format!("Unblocked in {} minutes", minutes),
+        (hours, minutes) => format!("Unblocked in {} h {:02} min", hours, minutes),
+    }
+}
+
+impl Server {
+    /// The blocked url the request is about. A request for a host other than
+    /// the server's own came here through DNS.
+    fn blocked_url(&self, request: &Request) -> Option<String> {
+        if let Some(url) = request.param("url").filter(|url| !url.is_empty()) {
+            return Some(url.to_string());
+        }
+        let host = request.host.as_deref().map(|host| split_url(host).0);
+        let is_own_host = host.as_deref().is_none_or(|host| {
+            host == "localhost"
+                || host
+                    .trim_matches(['[', ']'])
+                    .parse::<std::net::IpAddr>()
+                    .is_ok()
+        });
+        if !is_own_host {
+            return host.map(|host| format!("{}{}", host, request.path));
+        }
+        crate::platform::last_blocked_site()
+    }
+
+    fn hidden(&self, name: &str, value: &str) -> String {
+        format!(
+            "<input type=\"hidden\" name=\"{}\" value=\"{}\">",
+            name,
+            escape_html(value)
+        )
+    }
+
+    fn unlock_html(&self, request: &Request, url: &str, blocked: bool) -> String {
+        if self.config.unlock.is_none() {
+            return String::new();
+        }
+        let requested = request
+            .param("unlock")
+            .and_then(|id| id.parse::<u64>().ok())
+            .and_then(|id| crate::platform::unlocks().into_iter().find(|u| u.id == id));
+        let error = request
+            .param("error")
+            .map(|error| format!("<p class=\"error\">{}</p>", escape_html(error)))
+            .unwrap_or_default();
+        let token = self.hidden("token", &self.token);
+        let url_field = self.hidden("url", url);
+
+        match requested {
+            Some(unlock) if unlock.is_granted() => {
+                let until = unlock
+                    .expires_at()
+                    .map(|at| at.format("%H:%M").to_string())
+                    .unwrap_or_default();
+                // Urls without a host, e.g. `javascript:`, get no link
+                match NormalizedUrl::parse(url) {
+                    Some(target) => format!(
+                        "<p>Unlocked until {}. <a href=\"{}\">Open {}</a></p>",
+                        until,
+                        escape_html(&target.to_string()),
+                        escape_html(&target.host)
+                    ),
+                    None => format!("<p>Unlocked until {}.</p>", until),
+                }
+            }
+            Some(unlock) => match &unlock.friction {
+                UnlockFriction::Delay(delay) => format!(
+                    "<p>Unlock requested, it will be granted in {} minutes.</p>",
+                    delay.as_secs().div_ceil(60)
+                ),
+                UnlockFriction::Phrase(phrase) => format!(
+                    "<form method=\"post\" action=\"{}\">{}{}{}{}<p>Type \"{}\" to unlock:</p><input name=\"phrase\" autocomplete=\"off\"> <button>Unlock</button></form>",
+                    CONFIRM_PATH,
+                    error,
+                    token,
+                    url_field,
+                    self.hidden("id", &unlock.id.to_string()),
+                    escape_html(phrase)
+                ),
+            },
+            None if blocked => format!(
+                "<form method=\"post\" action=\"{}\">{}{}{}<button>Request an unlock</button></form>",
+                UNLOCK_PATH, error, token, url_field
+            ),
+            None => String::new(),
+        }
+    }
+
+    fn page(&self, request: &Request) -> Response {
+        let url = self.blocked_url(request).unwrap_or_default();
+        let status = (!url.is_empty())
+            .then(|| crate::platform::block_status(&url))
+            .flatten();
+        let domain = if url.is_empty() {
+            "This site".to_string()
+        } else {
+            split_url(&url).0
+        };
+        let (rule, remaining) = match &status {
+            Some((decision, remaining)) => {
+                (describe_rule(decision), describe_remaining(*remaining))
+            }
+            None => ("Not blocked right now".to_string(), String::new()),
+        };
+        let body = self
+            .config
+            .template
+            .replace("{{domain}}", &escape_html(&domain))
+            .replace("{{rule}}", &escape_html(&rule))
+            .replace("{{remaining}}", &escape_html(&remaining))
+            .replace(
+                "{{unlock}}",
+                &self.unlock_html(request, &url, status.is_some()),
+            );
+        Response::page(body)
+    }
+
+    fn back(&self, url: &str, extra: &str) -> Response {
+        Response::redirect(format!(
+            "http://{}/?url={}{}",
+            self.local_addr,
+            encode_component(url),
+            extra
+        ))
+    }
+
+    /// Requests an unlock for the rule blocking the url, or the url's domain
+    /// when an allowlist blocks it
+    fn request_unlock(&self, request: &Request) -> Response {
+        let Some((friction, duration)) = self.config.unlock.clone() else {
+            return Response::error("404 Not Found");
+        };
+        let url = request.param("url").unwrap_or_default().to_string();
+        let Some((decision, _)) = crate::platform::block_status(&url) else {
+            return self.back(&url, "");
+        };
+        let item = match decision.matched {
+            Some(matched) => BlockableItem::new(matched.pattern, true).with_rule(matched.rule),
+            None => BlockableItem::new(split_url(&url).0, true).with_rule(RuleKind::Domain),
+        };
+        match crate::platform::request_unlock(item, friction, duration) {
+            Ok(id) => self.back(&url, &format!("&unlock={}", id)),
+            Err(e) => self.back(
+                &url,
+                &format!("&error={}", encode_component(&e.to_string())),
+            ),
+        }
+    }
+
+    fn confirm_unlock(&self, request: &Request) -> Response {
+        let url = request.param("url").unwrap_or_default().to_string();
+        let id = request.param("id").unwrap_or_default();
+        let Ok(parsed_id) = id.parse::<u64>() else {
+            return Response::error("400 Bad Request");
+        };
+        let phrase = request.param("phrase").unwrap_or_default();
+        let mut extra = format!("&unlock={}", parsed_id);
+        if let Err(e) = crate::platform::confirm_unlock(parsed_id, phrase) {
+            extra.push_str(&format!("&error={}", encode_component(&e.to_string())));
+        }
+        self.back(&url, &extra)
+    }
+
+    fn handle(&self, request: &Request) -> Response {
+        let is_form = matches!(request.path.as_str(), UNLOCK_PATH | CONFIRM_PATH);
+        match request.method.as_str() {
+            "POST" if is_form && request.param("token") != Some(self.token.as_str()) => {
+                Response::error("403 Forbidden")
+            }
+            "POST" if request.path == UNLOCK_PATH => self.request_unlock(request),
+            "POST" if request.path == CONFIRM_PATH => self.confirm_unlock(request),
+            "GET" => self.page(request),
+            _ => Response::error("405 Method Not Allowed"),
+        }
+    }
+}
+
+fn serve_client(server: &Server, mut stream: TcpStream) -> io::Result<()> {
+    stream.set_nonblocking(false)?;
+    stream.set_read_timeout(Some(Duration::from_secs(5)))?;
+    let response = match read_request(&mut stream) {
+        Ok(request) => server.handle(&request),
+        Err(e) if e.kind() == io::ErrorKind::InvalidData => Response::error("400 Bad Request"),
+        Err(e) => return Err(e),
+    };
+    write_response(&mut stream, &response)
+}
+
+fn serve(server: &Arc<Server>, listener: &TcpListener, stop: &AtomicBool) {
+    while !stop.load(Ordering::SeqCst) {
+        let stream = match listener.accept() {
+            Ok((stream, _)) => stream,
+            Err(e) if e.kind() == io::ErrorKind::WouldBlock => {
+                std::thread::sleep(POLL_INTERVAL);
+                continue;
+            }
+            Err(e) => {
+                log::warn!("Block page accept failed: {}", e);
+                std::thread::sleep(POLL_INTERVAL);
+                continue;
+            }
+        };
+        let server = server.clone();
+        let spawned = std::thread::Builder::new()
+            .name("block-page-client".into())
+            .spawn(move || {
+                if let Err(e) = serve_client(&server, stream) {
+                    log::debug!("Block page request failed: {}", e);
+                }
+            });
+        if let Err(e) = spawned {
+            log::warn!("Block page client thread failed: {}", e);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::platform::{set_backend, start_blocking, stop_blocking, FakeBackend, TEST_LOCK};
+    use crate::ExceptionPolicy;
+
+    /// Sends a raw request and returns the status line, Location header and
+    /// body
+    fn send(addr: SocketAddr, request: &str) -> (String, Option<String>, String) {
+        let mut stream = TcpStream::connect(addr).unwrap();
+        stream.write_all(request.as_bytes()).unwrap();
+        let mut response = String::new();
+        stream.read_to_string(&mut response).unwrap();
+        let (head, body) = response.split_once("\r\n\r\n").unwrap();
+        let status = head.lines().next().unwrap().to_string();
+        let location = head
+            .lines()
+            .find_map(|line| line.strip_prefix("Location: "))
+            .map(str::to_string);
+        (status, location, body.to_string())
+    }
+
+    fn get(addr: SocketAddr, target: &str) -> (String, Option<String>, String) {
+        send(
+            addr,
+            &format!("GET {} HTTP/1.1\r\nHost: {}\r\n\r\n", target, addr),
+        )
+    }
+
+    fn post(addr: SocketAddr, path: &str, form: &str) -> (String, Option<String>, String) {
+        send(
+            addr,
+            &format!(
+                "POST {} HTTP/1.1\r\nHost: {}\r\nContent-Type: application/x-www-form-urlencoded\r\nContent-Length: {}\r\n\r\n{}",
+                path,
+                addr,
+                form.len(),
+                form
+            ),
+        )
+    }
+
+    /// Each test blocks its own domain, unlocks granted here outlive the test
+    fn block(domain: &str) {
+        set_backend(Arc::new(FakeBackend::new()));
+        let item = BlockableItem::new(domain.to_string(), true).with_rule(RuleKind::Domain);
+        start_blocking(
+            &[item],
+            "https://blocked.example",
+            true,
+            &ExceptionPolicy::none(),
+        )
+        .unwrap();
+    }
+
+    #[test]
+    fn test_form_encoding() {
+        assert_eq!(
+            parse_form("url=www.reddit.com%2Fr%2Frust&phrase=I+am+sure&empty"),
+            vec![
+                ("url".to_string(), "www.reddit.com/r/rust".to_string()),
+                ("phrase".to_string(), "I am sure".to_string()),
+                ("empty".to_string(), String::new()),
+            ]
+        );
+        let url = "https://m.reddit.com/r/a b?x=1&y=%";
+        assert_eq!(decode_component(&encode_component(url)), url);
+        assert_eq!(decode_component("100%"), "100%");
+        assert_eq!(
+            escape_html("<b a=\"1\">&'</b>"),
+            "&lt;b a=&quot;1&quot;&gt;&amp;&#39;&lt;/b&gt;"
+        );
+    }
+
+    #[test]
+    fn test_describe_remaining() {
+        let minutes = |minutes: u64| Some(Duration::from_secs(minutes * 60));
+        assert_eq!(
+            describe_remaining(None),
+            "Blocked until blocking is turned off"
+        );
+        assert_eq!(
+            describe_remaining(Some(Duration::ZERO)),
+            "Unblocked in less than a minute"
+        );
+        assert_eq!(
+            describe_remaining(Some(Duration::from_secs(30))),
+            "Unblocked in 1 minute"
+        );
+        assert_eq!(describe_remaining(minutes(42)), "Unblocked in 42 minutes");
+        assert_eq!(describe_remaining(minutes(125)), "Unblocked in 2 h 05 min");
+    }
+
+    #[test]
+    fn test_block_page() {
+        let _guard = TEST_LOCK.lock().unwrap_or_else(|e| e.into_inner());
+        block("reddit.com");
+        let handle = BlockPageServer::new()
+            .template("<h1>{{domain}}</h1><p>{{rule}}</p><p>{{remaining}}</p>{{unlock}}")
+            .start()
+            .unwrap();
+        let addr = handle.local_addr();
+        assert_eq!(handle.url(), format!("http://{}/", addr));
+
+        let (status, _, body) = get(addr, "/?url=https%3A%2F%2Fold.reddit.com%2Fr%2Frust");
+        assert_eq!(status, "HTTP/1.1 200 OK");
+        assert!(body.starts_with("<h1>old.reddit.com</h1>"), "{}", body);
+        assert!(body.contains("Blocked by the block list entry reddit.com and its subdomains"));
+        assert!(body.contains("Blocked until blocking is turned off"));
+        assert!(body.contains("Request an unlock"));
+
+        // Reached through DNS, the host is the blocked site
+        let (_, _, body) = send(addr, "GET /r/rust HTTP/1.1\r\nHost: www.reddit.com\r\n\r\n");
+        assert!(body.starts_with("<h1>www.reddit.com</h1>"), "{}", body);
+
+        let (_, _, body) = get(addr, "/?url=github.com%2F%3Cscript%3E");
+        assert!(body.contains("<h1>github.com</h1>"));
+        assert!(body.contains("Not blocked right now"));
+        assert!(!body.contains("Request an unlock"));
+
+        let (status, _, body) = get(addr, "/?url=%3Cscript%3E.example");
+        assert_eq!(status, "HTTP/1.1 200 OK");
+        assert!(body.contains("<h1>&lt;script&gt;.example</h1>"));
+        let (status, _, _) = send(addr, "DELETE / HTTP/1.1\r\n\r\n");
+        assert_eq!(status, "HTTP/1.1 405 Method Not Allowed");
+        let (status, _, _) = send(addr, "\r\n\r\n");
+        assert_eq!(status, "HTTP/1.1 400 Bad Request");
+
+        stop_blocking();
+        handle.stop();
+    }
+
+    #[test]
+    fn test_phrase_unlock_from_page() {
+        let _guard = TEST_LOCK.lock().unwrap_or_else(|e| e.into_inner());
+        block("distracting.example");
+        let handle = BlockPageServer::new()
+            .unlock(
+                UnlockFriction::Phrase("let me in".to_string()),
+                Duration::from_secs(60),
+            )
+            .start()
+            .unwrap();
+        let addr = handle.local_addr();
+        let (_, _, body) = get(addr, "/?url=distracting.example");
+        let token = body
+            .split("name=\"token\" value=\"")
+            .nth(1)
+            .and_then(|rest| rest.split('"').next())
+            .unwrap()
+            .to_string();
+
+        // Other pages can't post the form
+        let (status, _, _) = post(addr, UNLOCK_PATH, "url=distracting.example");
+        assert_eq!(status, "HTTP/1.1 403 Forbidden");
+
+        let form = format!("url=distracting.example&token={}", token);
+        let (status, location, _) = post(addr, UNLOCK_PATH, &form);
+        assert_eq!(status, "HTTP/1.1 303 See Other");
+        let location = location.unwrap();
+        let id: u64 = location
+            .rsplit("unlock=")
+            .next()
+            .and_then(|id| id.parse().ok())
+            .unwrap();
+        let target = location.split_once(&addr.to_string()).unwrap().1;
+        let (_, _, body) = get(addr, target);
+        assert!(body.contains("Type \"let me in\" to unlock"), "{}", body);
+
+        let confirm = |phrase: &str| {
+            post(
+                addr,
+                CONFIRM_PATH,
+                &format!(
+                    "url=distracting.example&token={}&id={}&phrase={}",
+                    token, id, phrase
+                ),
+            )
+        };
+        let (_, location, _) = confirm("nope");
+        assert!(location.unwrap().contains("&error="));
+        assert!(crate::platform::block_status("distracting.example").is_some());
+
+        let (_, location, _) = confirm("let+me+in");
+        let location = location.unwrap();
+        assert!(!location.contains("&error="));
+        assert!(crate::platform::block_status("distracting.example").is_none());
+        let (_, _, body) = get(addr, location.split_once(&addr.to_string()).unwrap().1);
+        assert!(body.contains("Unlocked until"), "{}", body);
+        assert!(
+            body.contains("<a href=\"https://distracting.example/\">Open distracting.example</a>"),
+            "{}",
+            body
+        );
+        // Urls that already have a scheme keep it
+        let target = format!(
+            "/?url=http%3A%2F%2Fdistracting.example%2Fpage&unlock={}",
+            id
+        );
+        let (_, _, body) = get(addr, &target);
+        assert!(
+            body.contains("<a href=\"http://distracting.example/page\">"),
+            "{}",
+            body
+        );
+
+        stop_blocking();
+        handle.stop();
+    }
+}
@@ -1,7 +1,30 @@
+use crate::{MonitorError, Platform};
+use regex::{Regex, RegexBuilder};
+use serde::{Deserialize, Serialize};
+
+/// How a `BlockableItem`'s id is matched against urls and app ids
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum RuleKind {
+    /// The url's host or the app id equals the id, ignoring case
+    #[default]
+    Exact,
+    /// The url's host is the domain or one of its subdomains, so
+    /// `facebook.com` (or `*.facebook.com`) also blocks `m.facebook.com`
+    Domain,
+    /// `youtube.com/shorts` blocks that path and everything below it on
+    /// youtube.com and its subdomains, but not the rest of the site
+    PathPrefix,
+    /// A regular expression searched for in the url or app id as reported
+    Regex,
+    /// An app id with `*` wildcards, e.g. `com.jetbrains.*`, ignoring case
+    AppIdPattern,
+}
+
 #[derive(Clone, Debug)]
 pub struct BlockableItem {
     pub app_external_id: String,
     pub is_browser: bool,
+    pub rule: RuleKind,
 }
 
 impl BlockableItem {
@@ -9,6 +32,1020 @@ impl BlockableItem {
         Self {
             app_external_id,
             is_browser,
+            rule: RuleKind::Exact,
+        }
+    }
+
+    pub fn with_rule(mut self, rule: RuleKind) -> Self {
+        self.rule = rule;
+        self
+    }
+}
+
+/// Splits a url (with or without scheme) into its lowercase host, without
+/// credentials or port, and the rest starting at the path. App ids come back
+/// whole as the host.
+pub(crate) fn split_url(url: &str) -> (String, &str) {
+    let rest = url.split_once("://").map_or(url, |(_, rest)| rest);
+    let end = rest.find(['/', '?', '#']).unwrap_or(rest.len());
+    let (authority, path) = rest.split_at(end);
+    let host = authority.rsplit('@').next().unwrap_or_default();
+    let host = match host.rsplit_once(':') {
+        Some((name, port)) if port.chars().all(|c| c.is_ascii_digit()) => name,
+        _ => host,
+    };
+    (host.trim_end_matches('.').to_ascii_lowercase(), path)
+}
+
+fn is_same_or_subdomain(host: &str, domain: &str) -> bool {
+    host == domain
+        || host
+            .strip_suffix(domain)
+            .is_some_and(|prefix| prefix.ends_with('.'))
+}
+
+/// Prefix match that stops at path segment boundaries, so `/shorts` matches
+/// `/shorts/abc` and `/shorts?x` but not `/shortstop`
+fn has_path_prefix(path: &str, prefix: &str) -> bool {
+    let prefix = prefix.trim_end_matches('/');
+    if prefix.is_empty() {
+        return true;
+    }
+    let path = path.to_ascii_lowercase();
+    let prefix = prefix.to_ascii_lowercase();
+    match path.strip_prefix(&prefix) {
+        Some(rest) => rest.is_empty() || rest.starts_with(['/', '?', '#']),
+        None => false,
+    }
+}
+
+/// The host and its `www.` variant, the names a site rule covers when
+/// blocking by name resolution
+fn site_hosts(host: &str) -> impl Iterator<Item = String> {
+    let www = (!host.starts_with("www.")).then(|| format!("www.{}", host));
+    std::iter::once(host.to_string()).chain(www)
+}
+
+fn glob_regex(pattern: &str) -> Result<Regex, regex::Error> {
+    let parts: Vec<String> = pattern.split('*').map(regex::escape).collect();
+    RegexBuilder::new(&format!("^{}$", parts.join(".*")))
+        .case_insensitive(true)
+        .build()
+}
+
+/// A `BlockableItem` prepared for matching
+#[derive(Clone, Debug)]
+enum Matcher {
+    Exact(String),
+    Domain(String),
+    PathPrefix {
+        domain: String,
+        path: String,
+    },
+    Pattern(Regex),
+    /// A regex that failed to compile, never matches
+    Invalid,
+}
+
+impl Matcher {
+    fn compile(item: &BlockableItem) -> Result<Self, MonitorError> {
+        let id = item.app_external_id.as_str();
+        let invalid = |_| MonitorError::InvalidIdentifier(id.to_string());
+        Ok(match item.rule {
+            // Kept as written apart from scheme and case, so an exact id with
+            // a path never matches a host
+            RuleKind::Exact => Matcher::Exact(
+                id.split_once("://")
+                    .map_or(id, |(_, rest)| rest)
+                    .trim_end_matches('/')
+                    .to_ascii_lowercase(),
+            ),
+            RuleKind::Domain => {
+                let domain = split_url(id).0;
+                Matcher::Domain(domain.trim_start_matches("*.").to_string())
+            }
+            RuleKind::PathPrefix => {
+                let (domain, path) = split_url(id);
+                Matcher::PathPrefix {
+                    domain: domain.trim_start_matches("*.").to_string(),
+                    path: path.to_string(),
+                }
+            }
+            RuleKind::Regex => Matcher::Pattern(Regex::new(id).map_err(invalid)?),
+            RuleKind::AppIdPattern => Matcher::Pattern(glob_regex(id).map_err(invalid)?),
+        })
+    }
+
+    fn matches(&self, external_app_id: &str) -> bool {
+        match self {
+            Matcher::Exact(expected) => split_url(external_app_id).0 == *expected,
+            Matcher::Domain(domain) => is_same_or_subdomain(&split_url(external_app_id).0, domain),
+            Matcher::PathPrefix { domain, path } => {
+                let (host, target_path) = split_url(external_app_id);
+                is_same_or_subdomain(&host, domain) && has_path_prefix(target_path, path)
+            }
+            Matcher::Pattern(regex) => regex.is_match(external_app_id),
+            Matcher::Invalid => false,
+        }
+    }
+
+    /// Whether the rule covers every url on the host, or with `any_path`
+    /// some url on it. Regexes and app id patterns have no host part.
+    fn matches_host(&self, host: &str, any_path: bool) -> bool {
+        match self {
+            Matcher::Exact(expected) => site_hosts(expected).any(|name| name == host),
+            Matcher::Domain(domain) => is_same_or_subdomain(host, domain),
+            Matcher::PathPrefix { domain, path } => {
+                (any_path || has_path_prefix("", path)) && is_same_or_subdomain(host, domain)
+            }
+            Matcher::Pattern(_) | Matcher::Invalid => false,
+        }
+    }
+}
+
+/// Where the rule behind a decision came from
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum RuleSource {
+    /// The items passed to `start_blocking`
+    List,
+    /// `ExceptionPolicy::system`, apps that are never blocked in allowlist
+    /// mode
+    SystemException,
+    /// `ExceptionPolicy::browsers`, kept open in allowlist mode when sites
+    /// are allowed
+    BrowserException,
+    /// A daily `Budgets` limit that was used up
+    Budget,
+}
+
+/// The rule that matched a url or app id
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct MatchedRule {
+    pub pattern: String,
+    pub rule: RuleKind,
+    pub source: RuleSource,
+}
+
+/// Why a url or app id was or wasn't blocked. In blocklist mode it is blocked
+/// because `matched` is set, in allowlist mode because it isn't.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct BlockDecision {
+    pub blocked: bool,
+    pub blocklist_mode: bool,
+    pub matched: Option<MatchedRule>,
+}
+
+#[derive(Clone, Debug)]
+struct Entry {
+    item: BlockableItem,
+    source: RuleSource,
+    matcher: Matcher,
+}
+
+impl Entry {
+    /// Items whose pattern doesn't compile never match, `start_blocking`
+    /// rejects them up front
+    fn new(item: &BlockableItem, source: RuleSource) -> Self {
+        let matcher = Matcher::compile(item).unwrap_or_else(|e| {
+            log::warn!("Ignoring block rule: {}", e);
+            Matcher::Invalid
+        });
+        Self {
+            item: item.clone(),
+            source,
+            matcher,
+        }
+    }
+}
+
+/// Active blocking configuration. All matching happens here in Rust, the
+/// backends only close apps and redirect tabs.
+#[derive(Clone, Debug)]
+pub struct BlockList {
+    items: Vec<BlockableItem>,
+    // The items followed by the exceptions
+    entries: Vec<Entry>,
+    redirect_url: String,
+    blocklist_mode: bool,
+}
+
+impl BlockList {
+    pub fn new(items: &[BlockableItem], redirect_url: &str, blocklist_mode: bool) -> Self {
+        Self {
+            items: items.to_vec(),
+            entries: items
+                .iter()
+                .map(|item| Entry::new(item, RuleSource::List))
+                .collect(),
+            redirect_url: redirect_url.to_string(),
+            blocklist_mode,
+        }
+    }
+
+    /// The list plus the exceptions from `policy` that apply to it, which
+    /// show up in decisions as their own source
+    pub fn with_exceptions(
+        items: &[BlockableItem],
+        redirect_url: &str,
+        blocklist_mode: bool,
+        policy: &ExceptionPolicy,
+    ) -> Self {
+        let mut block_list = Self::new(items, redirect_url, blocklist_mode);
+        block_list.entries.extend(
+            policy
+                .entries(has_website_url(items), blocklist_mode)
+                .iter()
+                .map(|(item, source)| Entry::new(item, *source)),
+        );
+        block_list
+    }
+
+    /// The items passed in, without exceptions
+    pub fn items(&self) -> &[BlockableItem] {
+        &self.items
+    }
+
+    pub fn redirect_url(&self) -> &str {
+        &self.redirect_url
+    }
+
+    pub fn blocklist_mode(&self) -> bool {
+        self.blocklist_mode
+    }
+
+    /// The first item or exception matching the url or app id
+    pub fn matching_item(&self, external_app_id: &str) -> Option<&BlockableItem> {
+        self.matching_entry(external_app_id)
+            .map(|entry| &entry.item)
+    }
+
+    fn matching_entry(&self, external_app_id: &str) -> Option<&Entry> {
+        self.entries
+            .iter()
+            .find(|entry| entry.matcher.matches(external_app_id))
+    }
+
+    pub fn decide(&self, external_app_id: &str) -> BlockDecision {
+        let matched = self
+            .matching_entry(external_app_id)
+            .map(|entry| MatchedRule {
+                pattern: entry.item.app_external_id.clone(),
+                rule: entry.item.rule,
+                source: entry.source,
+            });
+        // An empty configuration blocks nothing, even in allowlist mode
+        let blocked = !self.entries.is_empty() && (matched.is_some() == self.blocklist_mode);
+        BlockDecision {
+            blocked,
+            blocklist_mode: self.blocklist_mode,
+            matched,
+        }
+    }
+
+    pub fn is_blocked(&self, external_app_id: &str) -> bool {
+        self.decide(external_app_id).blocked
+    }
+
+    /// Whether a bare host name, e.g. from a DNS query, is blocked. Only the
+    /// host part of rules counts, an exact site with its `www.` variant like
+    /// `blocked_hosts`: a blocklist blocks the host when a rule covers all of
+    /// it, so `youtube.com/shorts` leaves youtube.com alone, and an
+    /// allowlist allows it when a rule allows any url on it, so
+    /// `docs.rs/tokio` allows docs.rs. Regexes and app id patterns are
+    /// skipped.
+    pub fn is_host_blocked(&self, host: &str) -> bool {
+        let matched = self
+            .entries
+            .iter()
+            .any(|entry| entry.matcher.matches_host(host, !self.blocklist_mode));
+        !self.entries.is_empty() && matched == self.blocklist_mode
+    }
+}
+
+pub(crate) fn has_website_url(blocked_apps: &[BlockableItem]) -> bool {
+    blocked_apps.iter().any(|app| app.is_browser)
+}
+
+/// Hosts of the sites that are blocked whole, with their `www.` variants,
+/// for blocking by name resolution. Path prefixes, regexes and app ids can't
+/// be expressed that way and are left to the rule engine.
+pub(crate) fn blocked_hosts(blocked_apps: &[BlockableItem]) -> Vec<String> {
+    let mut hosts: Vec<String> = Vec::new();
+    for item in blocked_apps.iter().filter(|item| item.is_browser) {
+        let (host, path) = split_url(&item.app_external_id);
+        let host = match item.rule {
+            RuleKind::Exact if path.trim_end_matches('/').is_empty() => host,
+            RuleKind::Domain => host.trim_start_matches("*.").to_string(),
+            _ => continue,
+        };
+        let is_hostname = host.contains('.')
+            && host.split('.').all(|label| {
+                !label.is_empty() && label.chars().all(|c| c.is_ascii_alphanumeric() || c == '-')
+            });
+        if !is_hostname || host.parse::<std::net::Ipv4Addr>().is_ok() {
+            continue;
+        }
+        for host in site_hosts(&host) {
+            if !hosts.contains(&host) {
+                hosts.push(host);
+            }
+        }
+    }
+    hosts
+}
+
+fn items(ids: &[&str]) -> Vec<BlockableItem> {
+    ids.iter()
+        .map(|id| BlockableItem::new(id.to_string(), false))
+        .collect()
+}
+
+const MACOS_SYSTEM_APPS: [&str; 7] = [
+    "com.apple.SystemFinder",
+    "com.apple.finder",
+    "com.apple.ActivityMonitor",
+    "com.apple.SystemPreferences",
+    "com.apple.Terminal",
+    "com.apple.Preview",
+    "com.apple.Music",
+];
+
+const MACOS_BROWSERS: [&str; 8] = [
+    "com.google.Chrome",
+    "com.google.Chrome.beta",
+    "com.google.Chrome.dev",
+    "com.google.Chrome.canary",
+    "com.apple.Safari",
+    "com.microsoft.Edge",
+    "com.brave.Browser",
+    "company.thebrowser.Browser",
+];
+
+/// X11 reports the WM_CLASS class and Wayland compositors the app id, so
+/// both forms are listed, along with the executables of the desktop
+/// sessions' own processes. Matching ignores case.
+const LINUX_SYSTEM_APPS: [&str; 48] = [
+    // Terminals
+    "gnome-terminal",
+    "gnome-terminal-server",
+    "org.gnome.Terminal",
+    "org.gnome.Console",
+    "kgx",
+    "org.kde.konsole",
+    "konsole",
+    "xfce4-terminal",
+    "foot",
+    "xterm",
+    // Settings
+    "gnome-control-center",
+    "org.gnome.Settings",
+    "systemsettings",
+    "org.kde.systemsettings",
+    "xfce4-settings-manager",
+    // File manager and system monitor
+    "nautilus",
+    "org.gnome.Nautilus",
+    "gnome-system-monitor",
+    "org.gnome.SystemMonitor",
+    // GNOME session
+    "gnome-shell",
+    "mutter",
+    "gnome-session-binary",
+    "gnome-screensaver",
+    "polkit-gnome-authentication-agent-1",
+    // KDE session
+    "plasmashell",
+    "org.kde.plasmashell",
+    "kwin_x11",
+    "kwin_wayland",
+    "ksmserver",
+    "krunner",
+    "org.kde.krunner",
+    "kscreenlocker_greet",
+    "polkit-kde-authentication-agent-1",
+    "org.kde.polkit-kde-authentication-agent-1",
+    // Xfce session
+    "xfwm4",
+    "xfce4-session",
+    "xfce4-panel",
+    "xfdesktop",
+    // sway and i3 session, bars and lock screens
+    "sway",
+    "swaybar",
+    "swaynag",
+    "swaylock",
+    "swayidle",
+    "i3",
+    "i3bar",
+    "i3-nagbar",
+    "i3lock",
+    "waybar",
+];
+
+const LINUX_BROWSERS: [&str; 7] = [
+    "firefox",
+    "org.mozilla.firefox",
+    "google-chrome",
+    "chromium",
+    "chromium-browser",
+    "brave-browser",
+    "microsoft-edge",
+];
+
+/// Apps that are never blocked in allowlist mode, so a user can't lock
+/// themselves out of their settings or terminal. Passed to `start_blocking`.
+/// `Default` gives the defaults for the platform the crate is built for.
+#[derive(Clone, Debug)]
+pub struct ExceptionPolicy {
+    system: Vec<BlockableItem>,
+    browsers: Vec<BlockableItem>,
+}
+
+impl ExceptionPolicy {
+    /// No exceptions, everything not on an allowlist is blocked
+    pub fn none() -> Self {
+        Self {
+            system: Vec::new(),
+            browsers: Vec::new(),
+        }
+    }
+
+    /// The system apps and browsers of `platform`. There are no Windows
+    /// defaults as there is no Windows backend yet.
+    pub fn for_platform(platform: Platform) -> Self {
+        match platform {
+            Platform::Mac => Self {
+                system: items(&MACOS_SYSTEM_APPS),
+                browsers: items(&MACOS_BROWSERS),
+            },
+            Platform::Linux => Self {
+                system: items(&LINUX_SYSTEM_APPS),
+                browsers: items(&LINUX_BROWSERS),
+            },
+            Platform::Windows => Self::none(),
+        }
+    }
+
+    /// Never block the app, e.g. the embedding app itself
+    pub fn allow(mut self, item: BlockableItem) -> Self {
+        self.system.push(item);
+        self
+    }
+
+    /// A browser kept open in allowlist mode when a site is allowed, so the
+    /// allowed sites can be visited
+    pub fn allow_browser(mut self, item: BlockableItem) -> Self {
+        self.browsers.push(item);
+        self
+    }
+
+    /// Removes the system app or browser with this id, ignoring case
+    pub fn remove(mut self, app_external_id: &str) -> Self {
+        let keep =
+            |item: &BlockableItem| !item.app_external_id.eq_ignore_ascii_case(app_external_id);
+        self.system.retain(keep);
+        self.browsers.retain(keep);
+        self
+    }
+
+    pub fn system(&self) -> &[BlockableItem] {
+        &self.system
+    }
+
+    pub fn browsers(&self) -> &[BlockableItem] {
+        &self.browsers
+    }
+
+    /// The exceptions that apply to a list. Blocklists have none, allowlists
+    /// keep the browsers only when a website is allowed.
+    fn entries(
+        &self,
+        has_website_url: bool,
+        blocklist_mode: bool,
+    ) -> Vec<(BlockableItem, RuleSource)> {
+        let mut exceptions = Vec::new();
+        if blocklist_mode {
+            return exceptions;
+        }
+        if has_website_url {
+            exceptions.extend(
+                self.browsers
+                    .iter()
+                    .map(|item| (item.clone(), RuleSource::BrowserException)),
+            );
+        }
+        exceptions.extend(
+            self.system
+                .iter()
+                .map(|item| (item.clone(), RuleSource::SystemException)),
+        );
+        exceptions
+    }
+
+    pub(crate) fn exceptions(
+        &self,
+        has_website_url: bool,
+        blocklist_mode: bool,
+    ) -> Vec<BlockableItem> {
+        self.entries(has_website_url, blocklist_mode)
+            .into_iter()
+            .map(|(item, _)| item)
+            .collect()
+    }
+}
+
+impl Default for ExceptionPolicy {
+    fn default() -> Self {
+        if cfg!(target_os = "macos") {
+            Self::for_platform(Platform::Mac)
+        } else if cfg!(target_os = "windows") {
+            Self::for_platform(Platform::Windows)
+        } else {
+            Self::for_platform(Platform::Linux)
+        }
+    }
+}
+
+/// Rejects identifiers that can't be matched or passed to native code: empty
+/// ones and ones containing NUL or other control characters
+fn validate_identifier(identifier: &str) -> Result<(), MonitorError> {
+    if identifier.trim().is_empty() || identifier.chars().any(char::is_control) {
+        return Err(MonitorError::InvalidIdentifier(identifier.to_string()));
+    }
+    Ok(())
+}
+
+/// Also compiles the regex and app id patterns so a typo is reported instead
+/// of silently never matching
+pub(crate) fn validate(
+    blocked_apps: &[BlockableItem],
+    redirect_url: &str,
+) -> Result<(), MonitorError> {
+    validate_identifier(redirect_url)?;
+    blocked_apps.iter().try_for_each(validate_item)
+}
+
+pub(crate) fn validate_item(item: &BlockableItem) -> Result<(), MonitorError> {
+    validate_identifier(&item.app_external_id)?;
+    Matcher::compile(item).map(|_| ())
+}
+
+/// The items passed to `start_blocking` plus the apps that must never be
+/// blocked in allowlist mode
+pub(crate) fn with_exceptions(
+    blocked_apps: &[BlockableItem],
+    blocklist_mode: bool,
+    policy: &ExceptionPolicy,
+) -> Vec<BlockableItem> {
+    let mut all_items = blocked_apps.to_vec();
+    all_items.extend(policy.exceptions(has_website_url(blocked_apps), blocklist_mode));
+    all_items
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn mac() -> ExceptionPolicy {
+        ExceptionPolicy::for_platform(Platform::Mac)
+    }
+
+    #[test]
+    fn test_has_website_url() {
+        let blocked_apps = vec![
+            BlockableItem::new("com.example.app".to_string(), false),
+            BlockableItem::new("google.com".to_string(), true),
+        ];
+        assert!(has_website_url(&blocked_apps));
+    }
+
+    #[test]
+    fn test_has_website_url_false() {
+        let blocked_apps = vec![
+            BlockableItem::new("com.example.app".to_string(), false),
+            BlockableItem::new("google.com".to_string(), false),
+        ];
+        assert!(!has_website_url(&blocked_apps));
+    }
+
+    #[test]
+    fn test_browser_exceptions() {
+        let exceptions = mac().browsers().to_vec();
+        let contains_chrome = exceptions
+            .iter()
+            .any(|app| app.app_external_id == "com.google.Chrome");
+        assert!(contains_chrome);
+    }
+
+    #[test]
+    fn test_system_exceptions() {
+        let exceptions = mac().system().to_vec();
+        let contains_finder = exceptions
+            .iter()
+            .any(|app| app.app_external_id == "com.apple.SystemFinder");
+        assert!(contains_finder);
+    }
+
+    #[test]
+    fn test_linux_exceptions() {
+        let linux = ExceptionPolicy::for_platform(Platform::Linux);
+        let allowlist = BlockList::with_exceptions(
+            &[BlockableItem::new("github.com".to_string(), true)],
+            "https://blocked.example",
+            false,
+            &linux,
+        );
+        // X11 reports the WM_CLASS class, which is capitalized
+        assert!(!allowlist.is_blocked("Gnome-terminal"));
+        assert!(!allowlist.is_blocked("org.gnome.Settings"));
+        assert!(!allowlist.is_blocked("gnome-shell"));
+        // Session processes of other desktops
+        assert!(!allowlist.is_blocked("sway"));
+        assert!(!allowlist.is_blocked("i3bar"));
+        assert!(!allowlist.is_blocked("mutter"));
+        assert!(!allowlist.is_blocked("ksmserver"));
+        assert!(!allowlist.is_blocked("firefox"));
+        assert!(allowlist.is_blocked("com.apple.finder"));
+        assert!(ExceptionPolicy::for_platform(Platform::Windows)
+            .system()
+            .is_empty());
+    }
+
+    #[test]
+    fn test_policy_allow_and_remove() {
+        let policy = mac()
+            .allow(BlockableItem::new("com.ebb.app".to_string(), false))
+            .allow_browser(BlockableItem::new("org.mozilla.firefox".to_string(), false))
+            .remove("com.apple.terminal")
+            .remove("com.apple.Safari");
+        let items = [BlockableItem::new("github.com".to_string(), true)];
+        let allowlist =
+            BlockList::with_exceptions(&items, "https://blocked.example", false, &policy);
+
+        assert!(!allowlist.is_blocked("com.ebb.app"));
+        assert!(!allowlist.is_blocked("org.mozilla.firefox"));
+        assert!(allowlist.is_blocked("com.apple.Terminal"));
+        assert!(allowlist.is_blocked("com.apple.Safari"));
+        assert!(!allowlist.is_blocked("com.apple.finder"));
+        assert!(!allowlist.is_blocked("com.apple.Preview"));
+        assert!(!allowlist.is_blocked("com.apple.Music"));
+        // Matching ignores case, one entry covers both spellings
+        assert!(!allowlist.is_blocked("com.apple.systempreferences"));
+
+        // Without exceptions only the list is allowed
+        let strict = BlockList::with_exceptions(
+            &items,
+            "https://blocked.example",
+            false,
+            &ExceptionPolicy::none(),
+        );
+        assert!(strict.is_blocked("com.apple.finder"));
+        assert!(!strict.is_blocked("github.com"));
+    }
+
+    #[test]
+    fn test_exceptions_allowlist_mode_with_browser() {
+        let exceptions = mac().exceptions(true, false);
+        let contains_chrome = exceptions
+            .iter()
+            .any(|app| app.app_external_id == "com.google.Chrome");
+        let contains_finder = exceptions
+            .iter()
+            .any(|app| app.app_external_id == "com.apple.SystemFinder");
+        assert!(contains_chrome);
+        assert!(contains_finder);
+    }
+
+    #[test]
+    fn test_exceptions_allowlist_mode_no_browser() {
+        let exceptions = mac().exceptions(false, false);
+        let contains_chrome = exceptions
+            .iter()
+            .any(|app| app.app_external_id == "com.google.Chrome");
+        let contains_finder = exceptions
+            .iter()
+            .any(|app| app.app_external_id == "com.apple.SystemFinder");
+        assert!(!contains_chrome);
+        assert!(contains_finder);
+    }
+
+    #[test]
+    fn test_exceptions_blocklist_mode_with_browser() {
+        let exceptions = mac().exceptions(true, true);
+        let contains_chrome = exceptions
+            .iter()
+            .any(|app| app.app_external_id == "com.google.Chrome");
+        let contains_finder = exceptions
+            .iter()
+            .any(|app| app.app_external_id == "com.apple.SystemFinder");
+        assert!(!contains_chrome);
+        assert!(!contains_finder);
+    }
+
+    #[test]
+    fn test_exceptions_blocklist_mode_no_browser() {
+        let exceptions = mac().exceptions(false, true);
+        let contains_chrome = exceptions
+            .iter()
+            .any(|app| app.app_external_id == "com.google.Chrome");
+        let contains_finder = exceptions
+            .iter()
+            .any(|app| app.app_external_id == "com.apple.SystemFinder");
+        assert!(!contains_chrome);
+        assert!(!contains_finder);
+    }
+
+    #[test]
+    fn test_with_exceptions() {
+        let blocked_apps = vec![BlockableItem::new("github.com".to_string(), true)];
+
+        let items = with_exceptions(&blocked_apps, true, &mac());
+        assert_eq!(items.len(), 1);
+
+        let items = with_exceptions(&blocked_apps, false, &mac());
+        assert_eq!(items[0].app_external_id, "github.com");
+        assert!(items
+            .iter()
+            .any(|app| app.app_external_id == "com.google.Chrome"));
+        assert!(items
+            .iter()
+            .any(|app| app.app_external_id == "com.apple.finder"));
+    }
+
+    #[test]
+    fn test_block_list_blocklist_mode() {
+        let block_list = BlockList::new(
+            &[
+                BlockableItem::new("facebook.com".to_string(), true),
+                BlockableItem::new("com.tinyspeck.slackmacgap".to_string(), false),
+            ],
+            "https://example.com/blocked",
+            true,
+        );
+        assert!(block_list.is_blocked("facebook.com"));
+        assert!(block_list.is_blocked("Facebook.com/groups/123"));
+        assert!(block_list.is_blocked("com.tinyspeck.slackmacgap"));
+        assert!(!block_list.is_blocked("github.com/rust-lang"));
+        // Exact host comparison only
+        assert!(!block_list.is_blocked("m.facebook.com"));
+        assert_eq!(block_list.redirect_url(), "https://example.com/blocked");
+    }
+
+    #[test]
+    fn test_block_list_allowlist_mode() {
+        let block_list = BlockList::new(
+            &[BlockableItem::new("github.com".to_string(), true)],
+            "https://example.com/blocked",
+            false,
+        );
+        assert!(!block_list.blocklist_mode());
+        assert!(!block_list.is_blocked("github.com/rust-lang/rust"));
+        assert!(block_list.is_blocked("reddit.com"));
+        assert!(block_list.is_blocked("com.valvesoftware.steam"));
+    }
+
+    #[test]
+    fn test_block_list_empty() {
+        let block_list = BlockList::new(&[], "https://example.com/blocked", false);
+        assert!(!block_list.is_blocked("reddit.com"));
+    }
+
+    #[test]
+    fn test_decide_reports_rule_and_source() {
+        let items = vec![
+            BlockableItem::new("github.com".to_string(), true).with_rule(RuleKind::Domain),
+            BlockableItem::new("org.editor".to_string(), false),
+        ];
+
+        let allowlist =
+            BlockList::with_exceptions(&items, "https://blocked.example", false, &mac());
+        let decision = allowlist.decide("gist.github.com/user");
+        assert!(!decision.blocked);
+        assert_eq!(
+            decision.matched,
+            Some(MatchedRule {
+                pattern: "github.com".to_string(),
+                rule: RuleKind::Domain,
+                source: RuleSource::List,
+            })
+        );
+        let finder = allowlist.decide("com.apple.finder");
+        assert!(!finder.blocked);
+        assert_eq!(finder.matched.unwrap().source, RuleSource::SystemException);
+        let chrome = allowlist.decide("com.google.Chrome");
+        assert_eq!(chrome.matched.unwrap().source, RuleSource::BrowserException);
+        let slack = allowlist.decide("com.tinyspeck.slackmacgap");
+        assert!(slack.blocked && !slack.blocklist_mode && slack.matched.is_none());
+
+        // Exceptions don't apply to blocklists
+        let blocklist = BlockList::with_exceptions(&items, "https://blocked.example", true, &mac());
+        let editor = blocklist.decide("org.editor");
+        assert!(editor.blocked && editor.blocklist_mode);
+        assert_eq!(editor.matched.unwrap().rule, RuleKind::Exact);
+        assert!(blocklist.decide("com.apple.finder").matched.is_none());
+    }
+
+    #[test]
+    fn test_validate_identifiers() {
+        let valid = [BlockableItem::new("news.example".to_string(), true)];
+        assert!(validate(&valid, "https://blocked.example").is_ok());
+
+        for invalid in ["", "  ", "bad\0id", "two\nlines"] {
+            let items = [BlockableItem::new(invalid.to_string(), false)];
+            match validate(&items, "https://blocked.example") {
+                Err(MonitorError::InvalidIdentifier(id)) => assert_eq!(id, invalid),
+                other => panic!(
+                    "expected InvalidIdentifier for {:?}, got {:?}",
+                    invalid, other
+                ),
+            }
         }
+        assert!(matches!(
+            validate(&valid, ""),
+            Err(MonitorError::InvalidIdentifier(_))
+        ));
+    }
+
+    fn rule(id: &str, rule: RuleKind) -> BlockList {
+        BlockList::new(
+            &[BlockableItem::new(id.to_string(), true).with_rule(rule)],
+            "https://example.com/blocked",
+            true,
+        )
+    }
+
+    #[test]
+    fn test_split_url() {
+        assert_eq!(
+            split_url("news.example/today"),
+            ("news.example".to_string(), "/today")
+        );
+        assert_eq!(
+            split_url("https://user@M.Example.com:8080/a?b#c"),
+            ("m.example.com".to_string(), "/a?b#c")
+        );
+        assert_eq!(
+            split_url("example.com?q=1"),
+            ("example.com".to_string(), "?q=1")
+        );
+        assert_eq!(
+            split_url("com.apple.Safari"),
+            ("com.apple.safari".to_string(), "")
+        );
+    }
+
+    #[test]
+    fn test_blocked_hosts() {
+        let items = [
+            BlockableItem::new("https://Reddit.com/".to_string(), true),
+            BlockableItem::new("*.facebook.com".to_string(), true).with_rule(RuleKind::Domain),
+            BlockableItem::new("www.reddit.com".to_string(), true),
+            BlockableItem::new("youtube.com/shorts".to_string(), true),
+            BlockableItem::new("youtube.com/shorts".to_string(), true)
+                .with_rule(RuleKind::PathPrefix),
+            BlockableItem::new("news\\..*".to_string(), true).with_rule(RuleKind::Regex),
+            BlockableItem::new("10.0.0.1".to_string(), true),
+            BlockableItem::new("com.slack.Slack".to_string(), false),
+        ];
+        assert_eq!(
+            blocked_hosts(&items),
+            vec![
+                "reddit.com",
+                "www.reddit.com",
+                "facebook.com",
+                "www.facebook.com"
+            ]
+        );
+    }
+
+    #[test]
+    fn test_blocked_hosts_agree_with_is_host_blocked() {
+        // The hosts file and the DNS sinkhole block the same names
+        let items = [BlockableItem::new("news.example".to_string(), true)];
+        let block_list = BlockList::new(&items, "https://example.com/blocked", true);
+        let hosts = blocked_hosts(&items);
+        assert_eq!(hosts, vec!["news.example", "www.news.example"]);
+        for host in &hosts {
+            assert!(block_list.is_host_blocked(host), "{}", host);
+        }
+        for host in ["m.news.example", "www.www.news.example", "example"] {
+            assert!(!hosts.iter().any(|name| name == host), "{}", host);
+            assert!(!block_list.is_host_blocked(host), "{}", host);
+        }
+    }
+
+    #[test]
+    fn test_exact_rule() {
+        let block_list = rule("Facebook.com", RuleKind::Exact);
+        assert!(block_list.is_blocked("facebook.com/feed"));
+        assert!(block_list.is_blocked("https://facebook.com"));
+        assert!(!block_list.is_blocked("m.facebook.com"));
+        assert!(!block_list.is_blocked("facebook.com.evil.example"));
+
+        // An exact id with a path never matches a host
+        assert!(!rule("youtube.com/shorts", RuleKind::Exact).is_blocked("youtube.com/shorts"));
+    }
+
+    #[test]
+    fn test_domain_rule() {
+        for pattern in ["facebook.com", "*.facebook.com", "https://facebook.com/"] {
+            let block_list = rule(pattern, RuleKind::Domain);
+            assert!(block_list.is_blocked("facebook.com"), "{}", pattern);
+            assert!(
+                block_list.is_blocked("https://m.facebook.com/home"),
+                "{}",
+                pattern
+            );
+            assert!(block_list.is_blocked("a.b.facebook.com"), "{}", pattern);
+            assert!(!block_list.is_blocked("notfacebook.com"), "{}", pattern);
+            assert!(
+                !block_list.is_blocked("facebook.com.example"),
+                "{}",
+                pattern
+            );
+        }
+    }
+
+    #[test]
+    fn test_path_prefix_rule() {
+        let block_list = rule("youtube.com/shorts", RuleKind::PathPrefix);
+        assert!(block_list.is_blocked("youtube.com/shorts"));
+        assert!(block_list.is_blocked("https://www.youtube.com/shorts/abc123"));
+        assert!(block_list.is_blocked("m.youtube.com/Shorts?feature=share"));
+        assert!(!block_list.is_blocked("youtube.com/watch?v=abc"));
+        assert!(!block_list.is_blocked("youtube.com/shortstop"));
+        assert!(!block_list.is_blocked("youtube.com"));
+
+        // In allowlist mode the rest of the site stays blocked
+        let allowlist = BlockList::new(
+            &[BlockableItem::new("docs.rs/tokio".to_string(), true)
+                .with_rule(RuleKind::PathPrefix)],
+            "https://example.com/blocked",
+            false,
+        );
+        assert!(!allowlist.is_blocked("docs.rs/tokio/latest/tokio"));
+        assert!(allowlist.is_blocked("docs.rs/serde"));
+    }
+
+    #[test]
+    fn test_regex_rule() {
+        let block_list = rule(r"reddit\.com/r/(all|popular)\b", RuleKind::Regex);
+        assert!(block_list.is_blocked("reddit.com/r/all"));
+        assert!(block_list.is_blocked("https://www.reddit.com/r/popular/top"));
+        assert!(!block_list.is_blocked("reddit.com/r/rust"));
+
+        let invalid =
+            [BlockableItem::new("(unclosed".to_string(), true).with_rule(RuleKind::Regex)];
+        assert!(matches!(
+            validate(&invalid, "https://example.com/blocked"),
+            Err(MonitorError::InvalidIdentifier(_))
+        ));
+        // Invalid patterns never match instead of blocking everything
+        let block_list = BlockList::new(&invalid, "https://example.com/blocked", true);
+        assert!(!block_list.is_blocked("(unclosed"));
+    }
+
+    #[test]
+    fn test_is_host_blocked() {
+        let items = [
+            BlockableItem::new("reddit.com".to_string(), true).with_rule(RuleKind::Domain),
+            BlockableItem::new("youtube.com/shorts".to_string(), true)
+                .with_rule(RuleKind::PathPrefix),
+            BlockableItem::new("news.example".to_string(), true),
+            BlockableItem::new(r"twitter\.com".to_string(), true).with_rule(RuleKind::Regex),
+        ];
+        let block_list = BlockList::new(&items, "https://example.com/blocked", true);
+        assert!(block_list.is_host_blocked("old.reddit.com"));
+        assert!(block_list.is_host_blocked("news.example"));
+        assert!(block_list.is_host_blocked("www.news.example"));
+        assert!(!block_list.is_host_blocked("m.news.example"));
+        assert!(!block_list.is_host_blocked("youtube.com"));
+        assert!(!block_list.is_host_blocked("twitter.com"));
+
+        let allow_list = BlockList::new(&items, "https://example.com/blocked", false);
+        assert!(!allow_list.is_host_blocked("old.reddit.com"));
+        assert!(!allow_list.is_host_blocked("www.youtube.com"));
+        assert!(allow_list.is_host_blocked("twitter.com"));
+        assert!(allow_list.is_host_blocked("github.com"));
+
+        let docs = [
+            BlockableItem::new("docs.rs/tokio".to_string(), true).with_rule(RuleKind::PathPrefix)
+        ];
+        let allow_list = BlockList::new(&docs, "https://example.com/blocked", false);
+        assert!(!allow_list.is_host_blocked("docs.rs"));
+        assert!(!BlockList::new(&[], "", false).is_host_blocked("docs.rs"));
+    }
+
+    #[test]
+    fn test_app_id_pattern_rule() {
+        let block_list = rule("com.jetbrains.*", RuleKind::AppIdPattern);
+        assert!(block_list.is_blocked("com.jetbrains.intellij"));
+        assert!(block_list.is_blocked("COM.JetBrains.pycharm"));
+        assert!(!block_list.is_blocked("com.jetbrainsx.tool"));
+        assert!(!block_list.is_blocked("org.com.jetbrains.intellij"));
+
+        let block_list = rule("org.gnome.*.Devel", RuleKind::AppIdPattern);
+        assert!(block_list.is_blocked("org.gnome.Builder.Devel"));
+        assert!(!block_list.is_blocked("org.gnome.Builder"));
     }
 }
@@ -0,0 +1,320 @@
+use crate::blocking::{BlockDecision, BlockList, MatchedRule, RuleSource};
+use crate::{AppEvent, BlockableItem};
+use chrono::{NaiveDate, NaiveDateTime, NaiveTime};
+use std::time::Duration;
+
+/// Daily time limits for apps and sites, see `set_budgets`. Once an item's
+/// focus time for the day reaches its limit it is blocked like a blocklist
+/// entry until the day ends.
+#[derive(Clone, Debug)]
+pub struct Budgets {
+    limits: Vec<(BlockableItem, Duration)>,
+    warnings: Vec<Duration>,
+    day_start: NaiveTime,
+    redirect_url: String,
+}
+
+impl Budgets {
+    /// No limits or warnings, days starting at midnight
+    pub fn new(redirect_url: &str) -> Self {
+        Self {
+            limits: Vec::new(),
+            warnings: Vec::new(),
+            day_start: NaiveTime::MIN,
+            redirect_url: redirect_url.to_string(),
+        }
+    }
+
+    /// Allows `per_day` of focus time on the item. Its `RuleKind` applies, so
+    /// a `Domain` rule counts every subdomain against the same budget.
+    pub fn limit(mut self, item: BlockableItem, per_day: Duration) -> Self {
+        self.limits.push((item, per_day));
+        self
+    }
+
+    /// Sends `BudgetWarning` once a day when this much time is left
+    pub fn warn_at(mut self, remaining: Duration) -> Self {
+        self.warnings.push(remaining);
+        self
+    }
+
+    /// Local time the budgets reset at, e.g. 04:00 so late nights count
+    /// towards the previous day
+    pub fn day_starts_at(mut self, time: NaiveTime) -> Self {
+        self.day_start = time;
+        self
+    }
+
+    pub fn items(&self) -> impl Iterator<Item = &BlockableItem> {
+        self.limits.iter().map(|(item, _)| item)
+    }
+
+    pub fn redirect_url(&self) -> &str {
+        &self.redirect_url
+    }
+}
+
+/// Today's focus time for one budget
+#[derive(Clone, Debug, PartialEq)]
+pub struct BudgetUsage {
+    pub pattern: String,
+    pub limit: Duration,
+    pub used: Duration,
+}
+
+impl BudgetUsage {
+    pub fn remaining(&self) -> Duration {
+        self.limit.saturating_sub(self.used)
+    }
+
+    pub fn is_exhausted(&self) -> bool {
+        self.used >= self.limit
+    }
+}
+
+struct Tracked {
+    item: BlockableItem,
+    matcher: BlockList,
+    limit: Duration,
+    used: Duration,
+    // Parallel to Budgets::warnings
+    warned: Vec<bool>,
+}
+
+impl Tracked {
+    fn usage(&self) -> BudgetUsage {
+        BudgetUsage {
+            pattern: self.item.app_external_id.clone(),
+            limit: self.limit,
+            used: self.used,
+        }
+    }
+
+    fn matches(&self, ids: &[&str]) -> bool {
+        ids.iter()
+            .any(|id| self.matcher.matching_item(id).is_some())
+    }
+}
+
+/// Accumulates focus time against `Budgets` from the monitor's ticks
+pub(crate) struct BudgetTracker {
+    budgets: Budgets,
+    tracked: Vec<Tracked>,
+    day: Option<NaiveDate>,
+    last_tick: Option<NaiveDateTime>,
+}
+
+impl BudgetTracker {
+    pub fn new(budgets: Budgets) -> Self {
+        let tracked = budgets
+            .limits
+            .iter()
+            .map(|(item, limit)| Tracked {
+                item: item.clone(),
+                matcher: BlockList::new(std::slice::from_ref(item), &budgets.redirect_url, true),
+                limit: *limit,
+                used: Duration::ZERO,
+                warned: vec![false; budgets.warnings.len()],
+            })
+            .collect();
+        Self {
+            budgets,
+            tracked,
+            day: None,
+            last_tick: None,
+        }
+    }
+
+    pub fn redirect_url(&self) -> &str {
+        &self.budgets.redirect_url
+    }
+
+    /// The budget day `now` belongs to
+    fn day_of(&self, now: NaiveDateTime) -> NaiveDate {
+        let since_midnight = self.budgets.day_start - NaiveTime::MIN;
+        (now - since_midnight).date()
+    }
+
+    /// When the budget day `now` belongs to ends and used up budgets reset
+    pub fn resets_at(&self, now: NaiveDateTime) -> NaiveDateTime {
+        (self.day_of(now) + chrono::Days::new(1)).and_time(self.budgets.day_start)
+    }
+
+    /// Counts the time since the last tick against the budgets matching
+    /// `focused`, the url and app id that had focus meanwhile. Nothing is
+    /// counted while `active` is false (the user is idle), and a single gap
+    /// counts for at most `max_gap` so a suspended machine doesn't use up a
+    /// budget. Returns the warning and exhausted events to send.
+    pub fn tick(
+        &mut self,
+        now: NaiveDateTime,
+        focused: &[&str],
+        active: bool,
+        max_gap: Duration,
+    ) -> Vec<AppEvent> {
+        let day = self.day_of(now);
+        if self.day != Some(day) {
+            if self.day.is_some() {
+                log::info!("Budget day {} started, resetting budgets", day);
+            }
+            self.day = Some(day);
+            for tracked in &mut self.tracked {
+                tracked.used = Duration::ZERO;
+                tracked.warned.fill(false);
+            }
+        }
+
+        let elapsed = self
+            .last_tick
+            .replace(now)
+            .and_then(|last| (now - last).to_std().ok())
+            .unwrap_or_default()
+            .min(max_gap);
+        if !active || elapsed.is_zero() {
+            return Vec::new();
+        }
+
+        let mut events = Vec::new();
+        for tracked in self.tracked.iter_mut().filter(|t| t.matches(focused)) {
+            let was_exhausted = tracked.used >= tracked.limit;
+            tracked.used += elapsed;
+            let remaining = tracked.limit.saturating_sub(tracked.used);
+
+            if remaining.is_zero() {
+                tracked.warned.fill(true);
+                if !was_exhausted {
+                    events.push(AppEvent::BudgetExhausted {
+                        pattern: tracked.item.app_external_id.clone(),
+                    });
+                }
+                continue;
+            }
+            // One warning even if several thresholds were crossed at once
+            let mut crossed = false;
+            for (warned, threshold) in tracked.warned.iter_mut().zip(&self.budgets.warnings) {
+                if !*warned && remaining <= *threshold {
+                    *warned = true;
+                    crossed = true;
+                }
+            }
+            if crossed {
+                events.push(AppEvent::BudgetWarning {
+                    pattern: tracked.item.app_external_id.clone(),
+                    remaining_seconds: remaining.as_secs(),
+                });
+            }
+        }
+        events
+    }
+
+    /// Why the url or app id is blocked, if a budget it counts against is
+    /// used up
+    pub fn exhausted_decision(&self, external_app_id: &str) -> Option<BlockDecision> {
+        self.tracked
+            .iter()
+            .find(|tracked| tracked.used >= tracked.limit && tracked.matches(&[external_app_id]))
+            .map(|tracked| BlockDecision {
+                blocked: true,
+                blocklist_mode: true,
+                matched: Some(MatchedRule {
+                    pattern: tracked.item.app_external_id.clone(),
+                    rule: tracked.item.rule,
+                    source: RuleSource::Budget,
+                }),
+            })
+    }
+
+    pub fn usage(&self) -> Vec<BudgetUsage> {
+        self.tracked.iter().map(Tracked::usage).collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::RuleKind;
+
+    const MINUTE: Duration = Duration::from_secs(60);
+
+    fn at(day: u32, hour: u32, minute: u32) -> NaiveDateTime {
+        NaiveDate::from_ymd_opt(2024, 1, day)
+            .unwrap()
+            .and_hms_opt(hour, minute, 0)
+            .unwrap()
+    }
+
+    fn reddit_budget() -> BudgetTracker {
+        BudgetTracker::new(
+            Budgets::new("https://blocked.example")
+                .limit(
+                    BlockableItem::new("reddit.com".to_string(), true).with_rule(RuleKind::Domain),
+                    30 * MINUTE,
+                )
+                .warn_at(10 * MINUTE)
+                .warn_at(5 * MINUTE)
+                .day_starts_at(NaiveTime::from_hms_opt(4, 0, 0).unwrap()),
+        )
+    }
+
+    #[test]
+    fn test_budget_warns_and_exhausts() {
+        let mut tracker = reddit_budget();
+        let reddit = ["old.reddit.com/r/rust", "com.google.Chrome"];
+        assert!(tracker.tick(at(1, 9, 0), &reddit, true, MINUTE).is_empty());
+
+        let mut events = Vec::new();
+        for minute in 1..=30 {
+            events.extend(tracker.tick(at(1, 9, minute), &reddit, true, MINUTE));
+        }
+        let summary: Vec<String> = events
+            .iter()
+            .map(|event| match event {
+                AppEvent::BudgetWarning {
+                    remaining_seconds, ..
+                } => format!("warning {}", remaining_seconds / 60),
+                AppEvent::BudgetExhausted { pattern } => format!("exhausted {}", pattern),
+                other => panic!("unexpected event {:?}", other),
+            })
+            .collect();
+        assert_eq!(
+            summary,
+            vec!["warning 10", "warning 5", "exhausted reddit.com"]
+        );
+
+        let decision = tracker.exhausted_decision("www.reddit.com").unwrap();
+        assert_eq!(decision.matched.unwrap().source, RuleSource::Budget);
+        assert!(tracker.exhausted_decision("github.com").is_none());
+        assert!(tracker.tick(at(1, 9, 31), &reddit, true, MINUTE).is_empty());
+    }
+
+    #[test]
+    fn test_budget_only_counts_active_matching_focus() {
+        let mut tracker = reddit_budget();
+        tracker.tick(at(1, 9, 0), &["github.com"], true, MINUTE);
+        tracker.tick(at(1, 9, 10), &["github.com"], true, MINUTE);
+        // Idle and long gaps don't count
+        tracker.tick(at(1, 9, 20), &["reddit.com"], false, MINUTE);
+        tracker.tick(at(1, 11, 0), &["reddit.com"], true, MINUTE);
+        tracker.tick(at(1, 11, 2), &["reddit.com"], true, 5 * MINUTE);
+        assert_eq!(tracker.usage()[0].used, 3 * MINUTE);
+        assert_eq!(tracker.usage()[0].remaining(), 27 * MINUTE);
+    }
+
+    #[test]
+    fn test_budget_resets_at_day_start() {
+        let mut tracker = reddit_budget();
+        tracker.tick(at(1, 23, 0), &["reddit.com"], true, 30 * MINUTE);
+        tracker.tick(at(1, 23, 30), &["reddit.com"], true, 30 * MINUTE);
+        assert!(tracker.usage()[0].is_exhausted());
+
+        // Past midnight is still the same budget day
+        tracker.tick(at(2, 3, 59), &["reddit.com"], true, MINUTE);
+        assert!(tracker.exhausted_decision("reddit.com").is_some());
+
+        assert_eq!(tracker.resets_at(at(2, 3, 59)), at(2, 4, 0));
+        tracker.tick(at(2, 4, 0), &["reddit.com"], true, MINUTE);
+        assert!(tracker.exhausted_decision("reddit.com").is_none());
+        assert_eq!(tracker.resets_at(at(2, 4, 0)), at(3, 4, 0));
+        assert_eq!(tracker.usage()[0].used, MINUTE);
+    }
+}
@@ -0,0 +1,689 @@
+use std::io::{self, Read, Write};
+use std::net::{IpAddr, Ipv4Addr, SocketAddr, TcpListener, TcpStream, UdpSocket};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{self, Receiver, TrySendError};
+use std::sync::{Arc, Mutex};
+use std::thread::JoinHandle;
+use std::time::Duration;
+
+const HEADER_LEN: usize = 12;
+const TYPE_A: u16 = 1;
+const TYPE_AAAA: u16 = 28;
+const CLASS_IN: u16 = 1;
+const RCODE_SERVFAIL: u16 = 2;
+const RCODE_NXDOMAIN: u16 = 3;
+// Short so names resolve again soon after blocking stops
+const BLOCKED_TTL: u32 = 10;
+// Large enough for EDNS responses
+const MAX_UDP_MESSAGE: usize = 4096;
+const POLL_INTERVAL: Duration = Duration::from_millis(50);
+// Threads forwarding UDP queries, and the queries waiting for them. Queries
+// beyond that are dropped, the client retries.
+const UDP_FORWARD_WORKERS: usize = 8;
+const UDP_FORWARD_QUEUE: usize = 64;
+
+/// What a blocked name resolves to
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SinkholeAnswer {
+    /// The name doesn't exist
+    NxDomain,
+    /// A and AAAA lookups return this address (whichever family it is), e.g.
+    /// a local block page. Other record types get an empty answer.
+    Redirect(IpAddr),
+}
+
+/// A DNS forwarder that fails lookups of blocked sites and passes everything
+/// else to `upstream`. Unlike redirecting the focused tab it works in every
+/// browser and before a page loads, once the system resolver (or browser)
+/// is pointed at `listen`.
+///
+/// Names are checked against the active `start_blocking` configuration,
+/// budgets and unlocks on every query, so it follows blocking being started,
+/// stopped and scheduled. An allowlist only filters names when it allows
+/// some sites, and then fails every other name, including the CDNs allowed
+/// sites load from.
+#[derive(Clone, Debug)]
+pub struct DnsSinkhole {
+    listen: SocketAddr,
+    upstream: SocketAddr,
+    answer: SinkholeAnswer,
+    timeout: Duration,
+}
+
+impl DnsSinkhole {
+    /// Listens on 127.0.0.1:53, which needs root or `CAP_NET_BIND_SERVICE`,
+    /// answering NXDOMAIN for blocked names
+    pub fn new(upstream: SocketAddr) -> Self {
+        Self {
+            listen: SocketAddr::from((Ipv4Addr::LOCALHOST, 53)),
+            upstream,
+            answer: SinkholeAnswer::NxDomain,
+            timeout: Duration::from_secs(3),
+        }
+    }
+
+    /// UDP and TCP address to listen on. Port 0 picks a free port, see
+    /// `DnsSinkholeHandle::local_addr`.
+    pub fn listen(mut self, addr: SocketAddr) -> Self {
+        self.listen = addr;
+        self
+    }
+
+    pub fn answer(mut self, answer: SinkholeAnswer) -> Self {
+        self.answer = answer;
+        self
+    }
+
+    /// How long to wait for the upstream server before answering SERVFAIL
+    pub fn timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = timeout;
+        self
+    }
+
+    /// Binds the sockets and starts serving on background threads until the
+    /// handle is stopped or dropped
+    pub fn start(self) -> io::Result<DnsSinkholeHandle> {
+        let udp = UdpSocket::bind(self.listen)?;
+        let local_addr = udp.local_addr()?;
+        // Same port for TCP, which matters when the port was picked for us
+        let tcp = TcpListener::bind(local_addr)?;
+        udp.set_read_timeout(Some(POLL_INTERVAL))?;
+        tcp.set_nonblocking(true)?;
+        log::info!(
+            "DNS sinkhole listening on {}, forwarding to {}",
+            local_addr,
+            self.upstream
+        );
+
+        let stop = Arc::new(AtomicBool::new(false));
+        let config = Arc::new(self);
+        let udp_thread = {
+            let (config, stop) = (config.clone(), stop.clone());
+            std::thread::Builder::new()
+                .name("dns-sinkhole-udp".into())
+                .spawn(move || serve_udp(&config, &udp, &stop))?
+        };
+        let tcp_thread = {
+            let stop = stop.clone();
+            std::thread::Builder::new()
+                .name("dns-sinkhole-tcp".into())
+                .spawn(move || serve_tcp(&config, &tcp, &stop))
+        };
+        let tcp_thread = match tcp_thread {
+            Ok(thread) => thread,
+            Err(e) => {
+                stop.store(true, Ordering::SeqCst);
+                let _ = udp_thread.join();
+                return Err(e);
+            }
+        };
+
+        Ok(DnsSinkholeHandle {
+            local_addr,
+            stop,
+            threads: vec![udp_thread, tcp_thread],
+        })
+    }
+}
+
+/// A running `DnsSinkhole`, stopped when dropped
+pub struct DnsSinkholeHandle {
+    local_addr: SocketAddr,
+    stop: Arc<AtomicBool>,
+    threads: Vec<JoinHandle<()>>,
+}
+
+impl DnsSinkholeHandle {
+    pub fn local_addr(&self) -> SocketAddr {
+        self.local_addr
+    }
+
+    /// Stops listening. Queries being forwarded are still answered.
+    pub fn stop(mut self) {
+        self.stop_threads();
+    }
+
+    fn stop_threads(&mut self) {
+        self.stop.store(true, Ordering::SeqCst);
+        for thread in self.threads.drain(..) {
+            let _ = thread.join();
+        }
+    }
+}
+
+impl Drop for DnsSinkholeHandle {
+    fn drop(&mut self) {
+        self.stop_threads();
+    }
+}
+
+/// The parts of a query needed to answer it
+#[derive(Debug, PartialEq)]
+struct Question {
+    /// Lowercase, without the trailing dot
+    name: String,
+    qtype: u16,
+    /// Offset just past the question
+    end: usize,
+}
+
+fn read_u16(message: &[u8], offset: usize) -> Option<u16> {
+    message
+        .get(offset..offset + 2)
+        .map(|bytes| u16::from_be_bytes([bytes[0], bytes[1]]))
+}
+
+/// The single question of a standard query. None for anything else, which
+/// is forwarded as is.
+fn parse_question(query: &[u8]) -> Option<Question> {
+    let flags = read_u16(query, 2)?;
+    let is_query = flags & 0x8000 == 0;
+    let opcode = (flags >> 11) & 0xf;
+    if !is_query || opcode != 0 || read_u16(query, 4)? != 1 {
+        return None;
+    }
+
+    let mut labels = Vec::new();
+    let mut offset = HEADER_LEN;
+    loop {
+        let len = *query.get(offset)? as usize;
+        offset += 1;
+        if len == 0 {
+            break;
+        }
+        // Compression pointers and extended label types don't belong in a
+        // query's question
+        if len > 63 {
+            return None;
+        }
+        let label = query.get(offset..offset + len)?;
+        labels.push(String::from_utf8_lossy(label).to_ascii_lowercase());
+        offset += len;
+    }
+    let qtype = read_u16(query, offset)?;
+    let qclass = read_u16(query, offset + 2)?;
+    if qclass != CLASS_IN {
+        return None;
+    }
+    Some(Question {
+        name: labels.join("."),
+        qtype,
+        end: offset + 4,
+    })
+}
+
+/// A response header echoing the query's id, opcode and recursion desired
+fn response_header(query: &[u8], rcode: u16, questions: u16, answers: u16) -> Vec<u8> {
+    let flags = read_u16(query, 2).unwrap_or_default();
+    let flags = 0x8000 | (flags & 0x7900) | 0x0080 | rcode;
+    let mut header = Vec::with_capacity(HEADER_LEN);
+    header.extend_from_slice(&query[..2]);
+    header.extend_from_slice(&flags.to_be_bytes());
+    header.extend_from_slice(&questions.to_be_bytes());
+    header.extend_from_slice(&answers.to_be_bytes());
+    header.extend_from_slice(&[0, 0, 0, 0]);
+    header
+}
+
+/// An error response without the question, None if the query is too short
+/// to answer
+fn error_response(query: &[u8], rcode: u16) -> Option<Vec<u8>> {
+    (query.len() >= HEADER_LEN).then(|| response_header(query, rcode, 0, 0))
+}
+
+fn blocked_response(query: &[u8], question: &Question, answer: SinkholeAnswer) -> Vec<u8> {
+    let rdata = match answer {
+        SinkholeAnswer::NxDomain => {
+            let mut response = response_header(query, RCODE_NXDOMAIN, 1, 0);
+            response.extend_from_slice(&query[HEADER_LEN..question.end]);
+            return response;
+        }
+        SinkholeAnswer::Redirect(IpAddr::V4(ip)) if question.qtype == TYPE_A => {
+            Some((TYPE_A, ip.octets().to_vec()))
+        }
+        SinkholeAnswer::Redirect(IpAddr::V6(ip)) if question.qtype == TYPE_AAAA => {
+            Some((TYPE_AAAA, ip.octets().to_vec()))
+        }
+        SinkholeAnswer::Redirect(_) => None,
+    };
+
+    let mut response = response_header(query, 0, 1, rdata.is_some() as u16);
+    response.extend_from_slice(&query[HEADER_LEN..question.end]);
+    if let Some((rtype, rdata)) = rdata {
+        // The name is a pointer to the question's
+        response.extend_from_slice(&[0xc0, HEADER_LEN as u8]);
+        response.extend_from_slice(&rtype.to_be_bytes());
+        response.extend_from_slice(&CLASS_IN.to_be_bytes());
+        response.extend_from_slice(&BLOCKED_TTL.to_be_bytes());
+        response.extend_from_slice(&(rdata.len() as u16).to_be_bytes());
+        response.extend_from_slice(&rdata);
+    }
+    response
+}
+
+/// Names that are never filtered: reverse lookups and the machine itself
+fn is_local(name: &str) -> bool {
+    name.is_empty()
+        || name == "localhost"
+        || name.ends_with(".localhost")
+        || name.ends_with(".arpa")
+}
+
+/// The response to send without asking upstream, None to forward the query
+fn local_response(query: &[u8], answer: SinkholeAnswer) -> Option<Vec<u8>> {
+    if query.len() < HEADER_LEN {
+        return None;
+    }
+    let question = parse_question(query)?;
+    if is_local(&question.name) || !crate::platform::is_host_blocked(&question.name) {
+        return None;
+    }
+    log::debug!("DNS sinkhole blocked {}", question.name);
+    Some(blocked_response(query, &question, answer))
+}
+
+fn forward_udp(query: &[u8], upstream: SocketAddr, timeout: Duration) -> io::Result<Vec<u8>> {
+    let bind_addr: SocketAddr = match upstream {
+        SocketAddr::V4(_) => (Ipv4Addr::UNSPECIFIED, 0).into(),
+        SocketAddr::V6(_) => (std::net::Ipv6Addr::UNSPECIFIED, 0).into(),
+    };
+    let socket = UdpSocket::bind(bind_addr)?;
+    socket.connect(upstream)?;
+    socket.set_read_timeout(Some(timeout))?;
+    socket.send(query)?;
+    let mut buf = vec![0; MAX_UDP_MESSAGE];
+    loop {
+        let len = socket.recv(&mut buf)?;
+        // Ignore stray datagrams that don't answer this query
+        if len >= 2 && buf[..2] == query[..2] {
+            buf.truncate(len);
+            return Ok(buf);
+        }
+    }
+}
+
+fn read_tcp_message(stream: &mut TcpStream) -> io::Result<Vec<u8>> {
+    let mut len = [0; 2];
+    stream.read_exact(&mut len)?;
+    let mut message = vec![0; u16::from_be_bytes(len) as usize];
+    stream.read_exact(&mut message)?;
+    Ok(message)
+}
+
+fn write_tcp_message(stream: &mut TcpStream, message: &[u8]) -> io::Result<()> {
+    let len = u16::try_from(message.len())
+        .map_err(|_| io::Error::new(io::ErrorKind::InvalidData, "DNS message too long"))?;
+    stream.write_all(&len.to_be_bytes())?;
+    stream.write_all(message)
+}
+
+fn forward_tcp(query: &[u8], upstream: SocketAddr, timeout: Duration) -> io::Result<Vec<u8>> {
+    let mut stream = TcpStream::connect_timeout(&upstream, timeout)?;
+    stream.set_read_timeout(Some(timeout))?;
+    stream.set_write_timeout(Some(timeout))?;
+    write_tcp_message(&mut stream, query)?;
+    read_tcp_message(&mut stream)
+}
+
+/// The local answer or upstream's, SERVFAIL if upstream didn't answer
+fn resolve(
+    config: &DnsSinkhole,
+    query: &[u8],
+    forward: fn(&[u8], SocketAddr, Duration) -> io::Result<Vec<u8>>,
+) -> Option<Vec<u8>> {
+    if let Some(response) = local_response(query, config.answer) {
+        return Some(response);
+    }
+    if query.len() < HEADER_LEN {
+        return None;
+    }
+    forward(query, config.upstream, config.timeout)
+        .map_err(|e| log::warn!("DNS upstream {} failed: {}", config.upstream, e))
+        .ok()
+        .or_else(|| error_response(query, RCODE_SERVFAIL))
+}
+
+fn forward_udp_worker(
+    config: &DnsSinkhole,
+    socket: &UdpSocket,
+    queries: &Mutex<Receiver<(Vec<u8>, SocketAddr)>>,
+) {
+    loop {
+        // Ends once serve_udp drops the sender
+        let Ok((query, client)) = queries.lock().unwrap().recv() else {
+            return;
+        };
+        if let Some(response) = resolve(config, &query, forward_udp) {
+            let _ = socket.send_to(&response, client);
+        }
+    }
+}
+
+fn serve_udp(config: &Arc<DnsSinkhole>, socket: &UdpSocket, stop: &AtomicBool) {
+    // Forwarded on a few workers so a slow upstream doesn't hold up other
+    // lookups
+    let (sender, receiver) = mpsc::sync_channel(UDP_FORWARD_QUEUE);
+    let receiver = Arc::new(Mutex::new(receiver));
+    let mut workers = Vec::new();
+    for _ in 0..UDP_FORWARD_WORKERS {
+        let socket = match socket.try_clone() {
+            Ok(socket) => socket,
+            Err(e) => {
+                log::warn!("DNS sinkhole socket clone failed: {}", e);
+                break;
+            }
+        };
+        let (config, receiver) = (config.clone(), receiver.clone());
+        let spawned = std::thread::Builder::new()
+            .name("dns-sinkhole-forward".into())
+            .spawn(move || forward_udp_worker(&config, &socket, &receiver));
+        match spawned {
+            Ok(worker) => workers.push(worker),
+            Err(e) => log::warn!("DNS sinkhole forward thread failed: {}", e),
+        }
+    }
+
+    let mut buf = vec![0; MAX_UDP_MESSAGE];
+    while !stop.load(Ordering::SeqCst) {
+        let (len, client) = match socket.recv_from(&mut buf) {
+            Ok(received) => received,
+            Err(e)
+                if matches!(
+                    e.kind(),
+                    io::ErrorKind::WouldBlock | io::ErrorKind::TimedOut
+                ) =>
+            {
+                continue;
+            }
+            Err(e) => {
+                log::warn!("DNS sinkhole receive failed: {}", e);
+                continue;
+            }
+        };
+        let query = buf[..len].to_vec();
+        if let Some(response) = local_response(&query, config.answer) {
+            let _ = socket.send_to(&response, client);
+            continue;
+        }
+        if workers.is_empty() {
+            if let Some(response) = resolve(config, &query, forward_udp) {
+                let _ = socket.send_to(&response, client);
+            }
+            continue;
+        }
+        if let Err(TrySendError::Full(_)) = sender.try_send((query, client)) {
+            log::debug!("DNS sinkhole dropped a query from {}, queue full", client);
+        }
+    }
+    drop(sender);
+    for worker in workers {
+        let _ = worker.join();
+    }
+}
+
+fn serve_tcp_client(config: &DnsSinkhole, mut stream: TcpStream) -> io::Result<()> {
+    stream.set_nonblocking(false)?;
+    stream.set_read_timeout(Some(config.timeout * 3))?;
+    loop {
+        let query = match read_tcp_message(&mut stream) {
+            Ok(query) => query,
+            // The client closed the connection or went quiet
+            Err(_) => return Ok(()),
+        };
+        match resolve(config, &query, forward_tcp) {
+            Some(response) => write_tcp_message(&mut stream, &response)?,
+            None => return Ok(()),
+        }
+    }
+}
+
+fn serve_tcp(config: &Arc<DnsSinkhole>, listener: &TcpListener, stop: &AtomicBool) {
+    while !stop.load(Ordering::SeqCst) {
+        let stream = match listener.accept() {
+            Ok((stream, _)) => stream,
+            Err(e) if e.kind() == io::ErrorKind::WouldBlock => {
+                std::thread::sleep(POLL_INTERVAL);
+                continue;
+            }
+            Err(e) => {
+                log::warn!("DNS sinkhole accept failed: {}", e);
+                std::thread::sleep(POLL_INTERVAL);
+                continue;
+            }
+        };
+        let config = config.clone();
+        let spawned = std::thread::Builder::new()
+            .name("dns-sinkhole-tcp-client".into())
+            .spawn(move || {
+                if let Err(e) = serve_tcp_client(&config, stream) {
+                    log::debug!("DNS sinkhole TCP client failed: {}", e);
+                }
+            });
+        if let Err(e) = spawned {
+            log::warn!("DNS sinkhole client thread failed: {}", e);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::platform::{set_backend, start_blocking, stop_blocking, FakeBackend, TEST_LOCK};
+    use crate::{BlockableItem, ExceptionPolicy, RuleKind};
+    use std::net::Ipv6Addr;
+
+    fn query(id: u16, name: &str, qtype: u16) -> Vec<u8> {
+        let mut query = id.to_be_bytes().to_vec();
+        // Recursion desired, one question
+        query.extend_from_slice(&[0x01, 0x00, 0, 1, 0, 0, 0, 0, 0, 0]);
+        for label in name.split('.') {
+            query.push(label.len() as u8);
+            query.extend_from_slice(label.as_bytes());
+        }
+        query.push(0);
+        query.extend_from_slice(&qtype.to_be_bytes());
+        query.extend_from_slice(&CLASS_IN.to_be_bytes());
+        query
+    }
+
+    fn rcode(response: &[u8]) -> u16 {
+        read_u16(response, 2).unwrap() & 0xf
+    }
+
+    fn answers(response: &[u8]) -> u16 {
+        read_u16(response, 6).unwrap()
+    }
+
+    /// Answers every query with 192.0.2.1 and counts them
+    fn stub_upstream() -> (SocketAddr, Arc<std::sync::atomic::AtomicUsize>) {
+        let socket = UdpSocket::bind("127.0.0.1:0").unwrap();
+        let listener = TcpListener::bind(socket.local_addr().unwrap()).unwrap();
+        let addr = socket.local_addr().unwrap();
+        let count = Arc::new(std::sync::atomic::AtomicUsize::new(0));
+        let respond = |query: &[u8]| {
+            let question = parse_question(query).unwrap();
+            let mut response = response_header(query, 0, 1, 1);
+            response.extend_from_slice(&query[HEADER_LEN..question.end]);
+            response.extend_from_slice(&[0xc0, 12, 0, 1, 0, 1, 0, 0, 0, 60, 0, 4, 192, 0, 2, 1]);
+            response
+        };
+        let udp_count = count.clone();
+        std::thread::spawn(move || {
+            let mut buf = [0; 512];
+            while let Ok((len, client)) = socket.recv_from(&mut buf) {
+                udp_count.fetch_add(1, Ordering::SeqCst);
+                socket.send_to(&respond(&buf[..len]), client).unwrap();
+            }
+        });
+        let tcp_count = count.clone();
+        std::thread::spawn(move || {
+            for mut stream in listener.incoming().flatten() {
+                let query = read_tcp_message(&mut stream).unwrap();
+                tcp_count.fetch_add(1, Ordering::SeqCst);
+                write_tcp_message(&mut stream, &respond(&query)).unwrap();
+            }
+        });
+        (addr, count)
+    }
+
+    fn ask(sinkhole: SocketAddr, query: &[u8]) -> Vec<u8> {
+        let socket = UdpSocket::bind("127.0.0.1:0").unwrap();
+        socket
+            .set_read_timeout(Some(Duration::from_secs(5)))
+            .unwrap();
+        socket.send_to(query, sinkhole).unwrap();
+        let mut buf = [0; 512];
+        let len = socket.recv(&mut buf).unwrap();
+        buf[..len].to_vec()
+    }
+
+    fn sinkhole(upstream: SocketAddr, answer: SinkholeAnswer) -> DnsSinkholeHandle {
+        DnsSinkhole::new(upstream)
+            .listen("127.0.0.1:0".parse().unwrap())
+            .answer(answer)
+            .start()
+            .unwrap()
+    }
+
+    #[test]
+    fn test_parse_question() {
+        let question = parse_question(&query(7, "WWW.Reddit.com", TYPE_AAAA)).unwrap();
+        assert_eq!(question.name, "www.reddit.com");
+        assert_eq!(question.qtype, TYPE_AAAA);
+        assert_eq!(question.end, HEADER_LEN + 16 + 4);
+
+        // Responses, truncated queries and compression pointers
+        let mut response = query(7, "reddit.com", TYPE_A);
+        response[2] |= 0x80;
+        assert!(parse_question(&response).is_none());
+        assert!(parse_question(&query(7, "reddit.com", TYPE_A)[..20]).is_none());
+        let mut pointer = query(7, "reddit.com", TYPE_A);
+        pointer[HEADER_LEN] = 0xc0;
+        assert!(parse_question(&pointer).is_none());
+    }
+
+    #[test]
+    fn test_blocked_names_are_answered_locally() {
+        let _guard = TEST_LOCK.lock().unwrap_or_else(|e| e.into_inner());
+        set_backend(Arc::new(FakeBackend::new()));
+        let (upstream, forwarded) = stub_upstream();
+        let handle = sinkhole(upstream, SinkholeAnswer::NxDomain);
+        let addr = handle.local_addr();
+
+        // Nothing is blocked yet
+        let response = ask(addr, &query(1, "reddit.com", TYPE_A));
+        assert_eq!((rcode(&response), answers(&response)), (0, 1));
+        assert_eq!(forwarded.load(Ordering::SeqCst), 1);
+
+        let reddit = BlockableItem::new("reddit.com".to_string(), true).with_rule(RuleKind::Domain);
+        let redirect = "https://blocked.example";
+        start_blocking(
+            std::slice::from_ref(&reddit),
+            redirect,
+            true,
+            &ExceptionPolicy::none(),
+        )
+        .unwrap();
+        let response = ask(addr, &query(2, "old.reddit.com", TYPE_A));
+        assert_eq!(response[..2], [0, 2]);
+        assert_eq!(rcode(&response), RCODE_NXDOMAIN);
+        assert_eq!(read_u16(&response, 4), Some(1));
+        assert_eq!(rcode(&ask(addr, &query(3, "github.com", TYPE_A))), 0);
+        assert_eq!(forwarded.load(Ordering::SeqCst), 2);
+
+        // Allowlist mode fails everything but the allowed sites
+        start_blocking(
+            std::slice::from_ref(&reddit),
+            redirect,
+            false,
+            &ExceptionPolicy::none(),
+        )
+        .unwrap();
+        assert_eq!(rcode(&ask(addr, &query(4, "reddit.com", TYPE_A))), 0);
+        assert_eq!(
+            rcode(&ask(addr, &query(5, "github.com", TYPE_A))),
+            RCODE_NXDOMAIN
+        );
+        assert_eq!(
+            rcode(&ask(addr, &query(6, "1.0.0.127.in-addr.arpa", 12))),
+            0
+        );
+
+        // A site allowed by a path resolves
+        let tokio_docs =
+            BlockableItem::new("docs.rs/tokio".to_string(), true).with_rule(RuleKind::PathPrefix);
+        start_blocking(&[tokio_docs], redirect, false, &ExceptionPolicy::none()).unwrap();
+        assert_eq!(rcode(&ask(addr, &query(10, "docs.rs", TYPE_A))), 0);
+
+        // An allowlist of apps only doesn't touch names
+        let slack = BlockableItem::new("com.slack.Slack".to_string(), false);
+        start_blocking(&[slack], redirect, false, &ExceptionPolicy::none()).unwrap();
+        assert_eq!(rcode(&ask(addr, &query(7, "github.com", TYPE_A))), 0);
+
+        // Over TCP as well
+        start_blocking(&[reddit], redirect, true, &ExceptionPolicy::none()).unwrap();
+        let mut stream = TcpStream::connect(addr).unwrap();
+        write_tcp_message(&mut stream, &query(8, "reddit.com", TYPE_A)).unwrap();
+        assert_eq!(
+            rcode(&read_tcp_message(&mut stream).unwrap()),
+            RCODE_NXDOMAIN
+        );
+        write_tcp_message(&mut stream, &query(9, "github.com", TYPE_A)).unwrap();
+        assert_eq!(answers(&read_tcp_message(&mut stream).unwrap()), 1);
+
+        stop_blocking();
+        handle.stop();
+    }
+
+    #[test]
+    fn test_redirect_answer() {
+        let _guard = TEST_LOCK.lock().unwrap_or_else(|e| e.into_inner());
+        set_backend(Arc::new(FakeBackend::new()));
+        let reddit = BlockableItem::new("reddit.com".to_string(), true);
+        start_blocking(
+            &[reddit],
+            "https://blocked.example",
+            true,
+            &ExceptionPolicy::none(),
+        )
+        .unwrap();
+        let (upstream, _) = stub_upstream();
+        let handle = sinkhole(
+            upstream,
+            SinkholeAnswer::Redirect(Ipv4Addr::LOCALHOST.into()),
+        );
+
+        let response = ask(handle.local_addr(), &query(1, "reddit.com", TYPE_A));
+        assert_eq!((rcode(&response), answers(&response)), (0, 1));
+        assert_eq!(response[response.len() - 4..], [127, 0, 0, 1]);
+        // No IPv6 address to give out
+        let response = ask(handle.local_addr(), &query(2, "reddit.com", TYPE_AAAA));
+        assert_eq!((rcode(&response), answers(&response)), (0, 0));
+
+        let v6 = blocked_response(
+            &query(3, "reddit.com", TYPE_AAAA),
+            &parse_question(&query(3, "reddit.com", TYPE_AAAA)).unwrap(),
+            SinkholeAnswer::Redirect(Ipv6Addr::LOCALHOST.into()),
+        );
+        assert_eq!(answers(&v6), 1);
+        assert_eq!(v6[v6.len() - 16..], Ipv6Addr::LOCALHOST.octets());
+        stop_blocking();
+    }
+
+    #[test]
+    fn test_unreachable_upstream() {
+        let _guard = TEST_LOCK.lock().unwrap_or_else(|e| e.into_inner());
+        set_backend(Arc::new(FakeBackend::new()));
+        stop_blocking();
+        // Bound but never answers
+        let silent = UdpSocket::bind("127.0.0.1:0").unwrap();
+        let handle = DnsSinkhole::new(silent.local_addr().unwrap())
+            .listen("127.0.0.1:0".parse().unwrap())
+            .timeout(Duration::from_millis(100))
+            .start()
+            .unwrap();
+        let response = ask(handle.local_addr(), &query(1, "github.com", TYPE_A));
+        assert_eq!(rcode(&response), RCODE_SERVFAIL);
+        assert_eq!(error_response(&[0; 4], RCODE_SERVFAIL), None);
+    }
+}
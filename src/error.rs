@@ -1,22 +1,44 @@
-#[derive(Debug)]
+#[derive(Debug, thiserror::Error)]
 pub enum MonitorError {
+    #[error("Monitor is already running")]
     AlreadyRunning,
+    #[error("Monitor is not running")]
     NotRunning,
+    /// The OS refused access, e.g. macOS accessibility permissions
+    #[error("Permission denied: {0}")]
+    PermissionDenied(String),
+    /// The backend's display server, socket or service can't be reached
+    #[error("{backend} backend unavailable: {reason}")]
+    BackendUnavailable {
+        backend: &'static str,
+        reason: String,
+    },
+    /// The backend doesn't implement the operation
+    #[error("{0} is not supported by this backend")]
+    Unsupported(&'static str),
+    /// An app id, bundle id or url that can't be passed to the OS
+    #[error("Invalid identifier {0:?}")]
+    InvalidIdentifier(String),
+    #[error("No icon found for {0:?}")]
+    IconNotFound(String),
+    /// A string from native code was not valid UTF-8
+    #[error("Invalid UTF-8 in {field} from native code")]
+    FfiStringDecode {
+        field: &'static str,
+        #[source]
+        source: std::str::Utf8Error,
+    },
+    /// Input or focus hooks could not be installed
+    #[error("Failed to install {hook}: {reason}")]
+    HookInstall { hook: &'static str, reason: String },
+    /// An unlock request that doesn't exist, was already granted or got the
+    /// wrong phrase
+    #[error("Unlock rejected: {0}")]
+    UnlockRejected(String),
+    #[error("Platform error: {0}")]
     PlatformError(String),
+    #[error("Foreign exception: {0}")]
     ForeignException(String),
+    #[error("Other error: {0}")]
     Other(String),
 }
-
-impl std::fmt::Display for MonitorError {
-    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        match self {
-            MonitorError::AlreadyRunning => write!(f, "Monitor is already running"),
-            MonitorError::NotRunning => write!(f, "Monitor is not running"),
-            MonitorError::PlatformError(msg) => write!(f, "Platform error: {}", msg),
-            MonitorError::ForeignException(msg) => write!(f, "Foreign exception: {}", msg),
-            MonitorError::Other(msg) => write!(f, "Other error: {}", msg),
-        }
-    }
-}
-
-impl std::error::Error for MonitorError {}
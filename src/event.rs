@@ -1,4 +1,10 @@
+use crate::blocking::{BlockDecision, BlockList};
+use crate::schedule::{Clock, SystemClock};
+use crate::urls::UrlPolicy;
 use serde::{Deserialize, Serialize};
+use std::collections::VecDeque;
+use std::sync::{Arc, Mutex, RwLock};
+use std::time::{Duration, Instant, SystemTime};
 use tokio::sync::broadcast::{self, Receiver, Sender};
 
 #[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
@@ -44,11 +50,40 @@ pub enum WindowEventType {
     TitleChanged,
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
-pub struct MouseEvent {}
+/// Mouse activity aggregated over one flush interval
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct MouseEvent {
+    pub left_clicks: u32,
+    pub right_clicks: u32,
+    pub middle_clicks: u32,
+    /// Sum of the absolute scroll deltas, in lines (or wheel clicks on Linux)
+    pub scroll_distance: u32,
+    /// Distance the pointer moved, in screen points
+    pub travel_distance: f64,
+    /// Number of distinct seconds with any mouse input
+    pub active_seconds: u32,
+}
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
-pub struct KeyboardEvent {}
+impl MouseEvent {
+    pub fn has_activity(&self) -> bool {
+        self.active_seconds > 0
+    }
+}
+
+/// Keyboard activity aggregated over one flush interval. Key identities are
+/// never recorded.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct KeyboardEvent {
+    pub keypresses: u32,
+    /// Number of distinct seconds with any key press
+    pub active_seconds: u32,
+}
+
+impl KeyboardEvent {
+    pub fn has_activity(&self) -> bool {
+        self.active_seconds > 0
+    }
+}
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct WindowEvent {
@@ -64,6 +99,10 @@ pub struct BlockedApp {
     pub app_name: String,
     pub app_external_id: String,
     pub is_site: bool,
+    /// Why it was blocked. Missing in recordings made before decisions were
+    /// reported.
+    #[serde(default)]
+    pub decision: Option<BlockDecision>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -73,45 +112,502 @@ pub struct BlockedAppEvent {
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub enum AppEvent {
-    Mouse(bool),
-    Keyboard(bool),
+    Mouse(MouseEvent),
+    Keyboard(KeyboardEvent),
     Window(WindowEvent),
     AppBlocked(BlockedAppEvent),
+    /// No input for the idle threshold. `since` is the time of the last input.
+    Idle {
+        #[serde(with = "unix_millis")]
+        since: SystemTime,
+    },
+    /// Input resumed after an `Idle` event
+    Active,
+    /// A scheduled `BlockProfile` turned blocking on
+    BlockingStarted {
+        profile: String,
+    },
+    /// A scheduled `BlockProfile` turned blocking off
+    BlockingStopped {
+        profile: String,
+    },
+    /// A daily budget crossed one of its warning thresholds
+    BudgetWarning {
+        pattern: String,
+        remaining_seconds: u64,
+    },
+    /// A daily budget was used up, the item is blocked until the day ends
+    BudgetExhausted {
+        pattern: String,
+    },
+    /// `request_unlock` was called
+    UnlockRequested {
+        id: u64,
+        pattern: String,
+    },
+    /// An unlock's delay passed or its phrase was typed, the item is exempt
+    /// from blocking for `duration_seconds`
+    UnlockGranted {
+        id: u64,
+        pattern: String,
+        duration_seconds: u64,
+    },
+    /// A granted unlock ran out, the item is blocked again
+    UnlockExpired {
+        id: u64,
+        pattern: String,
+    },
 }
 
-pub struct Monitor {
-    event_sender: Sender<AppEvent>,
+/// The kind of an `AppEvent`, used to enable or disable event types
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum EventKind {
+    Mouse,
+    Keyboard,
+    Window,
+    AppBlocked,
+    /// Both `Idle` and `Active` transitions
+    Idle,
+    /// Blocking turned on or off automatically
+    Blocking,
+    /// Budget warnings and exhaustion
+    Budget,
+    /// Unlock requests, grants and expiries
+    Unlock,
 }
 
-impl Monitor {
-    pub fn new() -> Self {
-        // A capacity of 100 should be more than enough for most use cases
-        let (sender, _) = broadcast::channel(100);
+impl EventKind {
+    pub const ALL: [EventKind; 8] = [
+        EventKind::Mouse,
+        EventKind::Keyboard,
+        EventKind::Window,
+        EventKind::AppBlocked,
+        EventKind::Idle,
+        EventKind::Blocking,
+        EventKind::Budget,
+        EventKind::Unlock,
+    ];
+}
+
+impl AppEvent {
+    pub fn kind(&self) -> EventKind {
+        match self {
+            AppEvent::Mouse(_) => EventKind::Mouse,
+            AppEvent::Keyboard(_) => EventKind::Keyboard,
+            AppEvent::Window(_) => EventKind::Window,
+            AppEvent::AppBlocked(_) => EventKind::AppBlocked,
+            AppEvent::Idle { .. } | AppEvent::Active => EventKind::Idle,
+            AppEvent::BlockingStarted { .. } | AppEvent::BlockingStopped { .. } => {
+                EventKind::Blocking
+            }
+            AppEvent::BudgetWarning { .. } | AppEvent::BudgetExhausted { .. } => EventKind::Budget,
+            AppEvent::UnlockRequested { .. }
+            | AppEvent::UnlockGranted { .. }
+            | AppEvent::UnlockExpired { .. } => EventKind::Unlock,
+        }
+    }
+}
+
+/// An event together with when and where it was produced
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct EventEnvelope {
+    /// Position in the sending monitor's stream, starting at 0. A jump of more
+    /// than one between consecutive envelopes means events were missed.
+    pub sequence: u64,
+    /// Wall clock time the event was sent, serialized as unix milliseconds
+    #[serde(with = "unix_millis")]
+    pub timestamp: SystemTime,
+    /// Monotonic time the event was sent. Not serialized, deserialized
+    /// envelopes get the time they were read.
+    #[serde(skip, default = "Instant::now")]
+    pub instant: Instant,
+    /// Name of the backend that produced the event, see
+    /// `PlatformBackend::name`
+    pub source: String,
+    pub event: AppEvent,
+}
+
+mod unix_millis {
+    use serde::{Deserialize, Deserializer, Serializer};
+    use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+    pub fn serialize<S: Serializer>(time: &SystemTime, serializer: S) -> Result<S::Ok, S::Error> {
+        let millis = time
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_millis() as u64;
+        serializer.serialize_u64(millis)
+    }
 
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<SystemTime, D::Error> {
+        let millis = u64::deserialize(deserializer)?;
+        Ok(UNIX_EPOCH + Duration::from_millis(millis))
+    }
+}
+
+/// Settings a `Monitor` is built with, see `MonitorBuilder`
+#[derive(Debug, Clone)]
+pub struct MonitorConfig {
+    /// How often the aggregated mouse and keyboard events are sent
+    pub flush_interval: Duration,
+    /// How often the `MonitorHandle` polling thread calls `detect_changes`
+    pub poll_interval: Duration,
+    /// Whether `MonitorHandle` polls in the background. Turn off to call
+    /// `detect_changes` yourself, e.g. from a UI thread or in tests.
+    pub background_polling: bool,
+    /// Events a subscriber can fall behind by before it starts missing them
+    pub channel_capacity: usize,
+    /// Recent events kept for subscribers that ask for a replay, see
+    /// `StreamOptions::replay`
+    pub replay_capacity: usize,
+    /// Time without input after which the user is considered idle
+    pub idle_threshold: Duration,
+    pub enabled_events: Vec<EventKind>,
+    /// Local time that block schedules are evaluated against
+    pub clock: Arc<dyn Clock>,
+    /// How urls are reduced in window events
+    pub url_policy: UrlPolicy,
+}
+
+impl Default for MonitorConfig {
+    fn default() -> Self {
         Self {
+            flush_interval: Duration::from_secs(30),
+            poll_interval: Duration::from_secs(1),
+            background_polling: true,
+            // A capacity of 100 should be more than enough for most use cases
+            channel_capacity: 100,
+            replay_capacity: 0,
+            idle_threshold: Duration::from_secs(300),
+            enabled_events: EventKind::ALL.to_vec(),
+            clock: Arc::new(SystemClock),
+            url_policy: UrlPolicy::default(),
+        }
+    }
+}
+
+impl MonitorConfig {
+    pub fn is_enabled(&self, kind: EventKind) -> bool {
+        self.enabled_events.contains(&kind)
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct MonitorBuilder {
+    config: MonitorConfig,
+}
+
+impl MonitorBuilder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn flush_interval(mut self, interval: Duration) -> Self {
+        self.config.flush_interval = interval;
+        self
+    }
+
+    pub fn poll_interval(mut self, interval: Duration) -> Self {
+        self.config.poll_interval = interval;
+        self
+    }
+
+    pub fn background_polling(mut self, enabled: bool) -> Self {
+        self.config.background_polling = enabled;
+        self
+    }
+
+    /// Capacity of the broadcast channel. Values below 1 are raised to 1.
+    pub fn channel_capacity(mut self, capacity: usize) -> Self {
+        self.config.channel_capacity = capacity.max(1);
+        self
+    }
+
+    /// Number of recent events to keep for late subscribers
+    pub fn replay_capacity(mut self, capacity: usize) -> Self {
+        self.config.replay_capacity = capacity;
+        self
+    }
+
+    pub fn idle_threshold(mut self, threshold: Duration) -> Self {
+        self.config.idle_threshold = threshold;
+        self
+    }
+
+    /// Clock for block schedules, e.g. a `ManualClock` in tests
+    pub fn clock(mut self, clock: Arc<dyn Clock>) -> Self {
+        self.config.clock = clock;
+        self
+    }
+
+    /// Granularity and query parameters stripped from urls in window
+    /// events, see `UrlPolicy`
+    pub fn url_policy(mut self, policy: UrlPolicy) -> Self {
+        self.config.url_policy = policy;
+        self
+    }
+
+    /// Only send events of the given kinds
+    pub fn events(mut self, kinds: &[EventKind]) -> Self {
+        self.config.enabled_events = kinds.to_vec();
+        self
+    }
+
+    pub fn enable(mut self, kind: EventKind) -> Self {
+        if !self.config.is_enabled(kind) {
+            self.config.enabled_events.push(kind);
+        }
+        self
+    }
+
+    pub fn disable(mut self, kind: EventKind) -> Self {
+        self.config
+            .enabled_events
+            .retain(|enabled| *enabled != kind);
+        self
+    }
+
+    pub fn build(self) -> Monitor {
+        let (sender, _) = broadcast::channel(self.config.channel_capacity);
+
+        Monitor {
             event_sender: sender,
+            next_sequence: Mutex::new(0),
+            history: Mutex::new(VecDeque::with_capacity(self.config.replay_capacity)),
+            source: RwLock::new("unknown".to_string()),
+            last_flush: Mutex::new(Instant::now()),
+            idle_since: Mutex::new(None),
+            state: Mutex::new(MonitorSnapshot::default()),
+            focused_url: Mutex::new(None),
+            config: self.config,
         }
     }
+}
+
+/// What the monitor knows right now, for UIs that start after the events
+/// describing it were sent
+#[derive(Debug, Clone, Default)]
+pub struct MonitorSnapshot {
+    /// The last window reported, whether or not window events are enabled
+    pub focused_window: Option<WindowEvent>,
+    pub last_window_change: Option<SystemTime>,
+    /// Set while the user is idle, see `AppEvent::Idle`
+    pub idle_since: Option<SystemTime>,
+    /// The list passed to `start_blocking`, without the built-in exceptions.
+    /// None when blocking is off.
+    pub blocking: Option<BlockList>,
+    pub last_mouse_input: Option<SystemTime>,
+    pub last_keyboard_input: Option<SystemTime>,
+}
+
+pub struct Monitor {
+    event_sender: Sender<EventEnvelope>,
+    // Held while sending so envelopes reach the channel in sequence order
+    next_sequence: Mutex<u64>,
+    // Last `replay_capacity` envelopes sent, only changed under next_sequence
+    history: Mutex<VecDeque<EventEnvelope>>,
+    source: RwLock<String>,
+    last_flush: Mutex<Instant>,
+    idle_since: Mutex<Option<SystemTime>>,
+    // Everything in the snapshot except idle_since
+    state: Mutex<MonitorSnapshot>,
+    // The focused window's url before `UrlPolicy`, for budgets. Only changed
+    // under state.
+    focused_url: Mutex<Option<String>>,
+    config: MonitorConfig,
+}
+
+/// The transition to report given the current idle state and how long it has
+/// been since the last input
+fn idle_transition(
+    idle_since: Option<SystemTime>,
+    idle_for: Duration,
+    threshold: Duration,
+    now: SystemTime,
+) -> Option<AppEvent> {
+    match idle_since {
+        None if idle_for >= threshold => Some(AppEvent::Idle {
+            since: now.checked_sub(idle_for).unwrap_or(now),
+        }),
+        Some(_) if idle_for < threshold => Some(AppEvent::Active),
+        _ => None,
+    }
+}
+
+impl Default for Monitor {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Monitor {
+    /// A monitor with the default configuration
+    pub fn new() -> Self {
+        MonitorBuilder::new().build()
+    }
+
+    pub fn builder() -> MonitorBuilder {
+        MonitorBuilder::new()
+    }
+
+    pub fn config(&self) -> &MonitorConfig {
+        &self.config
+    }
 
     /// Get a new receiver to subscribe to events
-    pub fn subscribe(&self) -> Receiver<AppEvent> {
+    pub fn subscribe(&self) -> Receiver<EventEnvelope> {
         self.event_sender.subscribe()
     }
 
-    pub fn send_mouse_event(&self, has_activity: bool) {
-        let _ = self.event_sender.send(AppEvent::Mouse(has_activity));
+    /// Subscribes and returns up to `replay` of the most recent events, with
+    /// no gap or overlap between them and the receiver
+    pub(crate) fn subscribe_with_replay(
+        &self,
+        replay: usize,
+    ) -> (Vec<EventEnvelope>, Receiver<EventEnvelope>) {
+        let _next_sequence = self.next_sequence.lock().unwrap();
+        let history = self.history.lock().unwrap();
+        let skip = history.len().saturating_sub(replay);
+        let recent = history.iter().skip(skip).cloned().collect();
+        (recent, self.event_sender.subscribe())
+    }
+
+    /// Must be called with next_sequence held
+    fn broadcast(&self, envelope: EventEnvelope) {
+        if self.config.replay_capacity > 0 {
+            let mut history = self.history.lock().unwrap();
+            if history.len() == self.config.replay_capacity {
+                history.pop_front();
+            }
+            history.push_back(envelope.clone());
+        }
+        let _ = self.event_sender.send(envelope);
+    }
+
+    /// Sets the source recorded on envelopes sent from now on.
+    /// `start_monitoring` sets it to the active backend's name.
+    pub fn set_source(&self, source: &str) {
+        *self.source.write().unwrap() = source.to_string();
+    }
+
+    /// Sends an already built event, stamped with the current time. Events of
+    /// a disabled kind are dropped without using a sequence number.
+    pub fn send_event(&self, event: AppEvent) {
+        if !self.config.is_enabled(event.kind()) {
+            return;
+        }
+        let mut next_sequence = self.next_sequence.lock().unwrap();
+        let envelope = EventEnvelope {
+            sequence: *next_sequence,
+            timestamp: SystemTime::now(),
+            instant: Instant::now(),
+            source: self.source.read().unwrap().clone(),
+            event,
+        };
+        *next_sequence += 1;
+        self.broadcast(envelope);
+    }
+
+    /// Sends an envelope unchanged, e.g. one read back from a recording. Its
+    /// sequence number is not checked against this monitor's.
+    pub fn forward(&self, envelope: EventEnvelope) {
+        if !self.config.is_enabled(envelope.event.kind()) {
+            return;
+        }
+        let _next_sequence = self.next_sequence.lock().unwrap();
+        self.broadcast(envelope);
     }
 
-    pub fn send_keyboard_event(&self, has_activity: bool) {
-        let _ = self.event_sender.send(AppEvent::Keyboard(has_activity));
+    /// Whether the flush interval has passed since the activity events were
+    /// last sent
+    pub(crate) fn flush_due(&self) -> bool {
+        self.last_flush.lock().unwrap().elapsed() >= self.config.flush_interval
     }
 
-    pub fn send_window_event(&self, event: WindowEvent) {
-        let _ = self.event_sender.send(AppEvent::Window(event));
+    pub(crate) fn mark_flushed(&self) {
+        *self.last_flush.lock().unwrap() = Instant::now();
     }
 
-    pub fn send_app_blocked_event(&self, event: BlockedAppEvent) {
-        let _ = self.event_sender.send(AppEvent::AppBlocked(event));
+    /// Time of the last input if the user is currently idle
+    pub fn idle_since(&self) -> Option<SystemTime> {
+        *self.idle_since.lock().unwrap()
+    }
+
+    /// Current focus, idle, blocking and activity state, kept up to date by
+    /// the platform layer while monitoring
+    pub fn snapshot(&self) -> MonitorSnapshot {
+        let mut snapshot = self.state.lock().unwrap().clone();
+        snapshot.idle_since = self.idle_since();
+        snapshot
+    }
+
+    /// The focused window's url as the browser reported it. The snapshot
+    /// has it reduced by the `UrlPolicy`.
+    pub(crate) fn focused_url(&self) -> Option<String> {
+        self.focused_url.lock().unwrap().clone()
+    }
+
+    pub(crate) fn set_blocking(&self, blocking: Option<BlockList>) {
+        self.state.lock().unwrap().blocking = blocking;
+    }
+
+    pub(crate) fn mark_mouse_input(&self, at: SystemTime) {
+        self.state.lock().unwrap().last_mouse_input = Some(at);
+    }
+
+    pub(crate) fn mark_keyboard_input(&self, at: SystemTime) {
+        self.state.lock().unwrap().last_keyboard_input = Some(at);
+    }
+
+    /// Sends `Idle` or `Active` if the time since the last input crossed the
+    /// idle threshold
+    pub(crate) fn update_idle(&self, idle_for: Duration) {
+        let mut idle_since = self.idle_since.lock().unwrap();
+        let transition = idle_transition(
+            *idle_since,
+            idle_for,
+            self.config.idle_threshold,
+            SystemTime::now(),
+        );
+        match transition {
+            Some(AppEvent::Idle { since }) => *idle_since = Some(since),
+            Some(AppEvent::Active) => *idle_since = None,
+            _ => return,
+        }
+        self.send_event(transition.unwrap());
+    }
+
+    pub fn send_mouse_event(&self, event: MouseEvent) {
+        self.send_event(AppEvent::Mouse(event));
+    }
+
+    pub fn send_keyboard_event(&self, event: KeyboardEvent) {
+        self.send_event(AppEvent::Keyboard(event));
+    }
+
+    pub fn send_window_event(&self, mut event: WindowEvent) {
+        let url = event.url.take();
+        event.url = url
+            .as_deref()
+            .and_then(|url| self.config.url_policy.apply(url));
+        {
+            let mut state = self.state.lock().unwrap();
+            *self.focused_url.lock().unwrap() = url;
+            state.focused_window = Some(event.clone());
+            state.last_window_change = Some(SystemTime::now());
+        }
+        self.send_event(AppEvent::Window(event));
+    }
+
+    /// Blocked sites are reported with urls reduced by the `UrlPolicy`, like
+    /// window events
+    pub fn send_app_blocked_event(&self, mut event: BlockedAppEvent) {
+        for app in event.blocked_apps.iter_mut().filter(|app| app.is_site) {
+            if let Some(url) = self.config.url_policy.apply(&app.app_external_id) {
+                app.app_external_id = url;
+            }
+        }
+        self.send_event(AppEvent::AppBlocked(event));
     }
 }
 
@@ -120,6 +616,21 @@ mod tests {
     use super::*;
     use std::thread;
 
+    fn mouse_activity() -> MouseEvent {
+        MouseEvent {
+            left_clicks: 1,
+            active_seconds: 1,
+            ..Default::default()
+        }
+    }
+
+    fn keyboard_activity() -> KeyboardEvent {
+        KeyboardEvent {
+            keypresses: 1,
+            active_seconds: 1,
+        }
+    }
+
     #[test]
     fn test_multiple_receivers() {
         // Create a monitor
@@ -131,21 +642,21 @@ mod tests {
         let mut receiver3 = monitor.subscribe();
 
         // Send a mouse event
-        monitor.send_mouse_event(true);
+        monitor.send_mouse_event(mouse_activity());
 
         // Test that all receivers get the event
-        match receiver1.blocking_recv() {
-            Ok(AppEvent::Mouse(activity)) => assert!(activity),
+        match receiver1.blocking_recv().map(|e| e.event) {
+            Ok(AppEvent::Mouse(event)) => assert!(event.has_activity()),
             _ => panic!("Receiver 1 did not get the correct event"),
         }
 
-        match receiver2.blocking_recv() {
-            Ok(AppEvent::Mouse(activity)) => assert!(activity),
+        match receiver2.blocking_recv().map(|e| e.event) {
+            Ok(AppEvent::Mouse(event)) => assert!(event.has_activity()),
             _ => panic!("Receiver 2 did not get the correct event"),
         }
 
-        match receiver3.blocking_recv() {
-            Ok(AppEvent::Mouse(activity)) => assert!(activity),
+        match receiver3.blocking_recv().map(|e| e.event) {
+            Ok(AppEvent::Mouse(event)) => assert!(event.has_activity()),
             _ => panic!("Receiver 3 did not get the correct event"),
         }
     }
@@ -159,28 +670,28 @@ mod tests {
         let mut receiver1 = monitor.subscribe();
 
         // Send first event
-        monitor.send_mouse_event(true);
+        monitor.send_mouse_event(mouse_activity());
 
         // Create a late subscriber
         let mut receiver2 = monitor.subscribe();
 
         // Send second event
-        monitor.send_keyboard_event(true);
+        monitor.send_keyboard_event(keyboard_activity());
 
         // First receiver gets both events
-        match receiver1.blocking_recv() {
-            Ok(AppEvent::Mouse(activity)) => assert!(activity),
+        match receiver1.blocking_recv().map(|e| e.event) {
+            Ok(AppEvent::Mouse(event)) => assert!(event.has_activity()),
             _ => panic!("Receiver 1 did not get the mouse event"),
         }
 
-        match receiver1.blocking_recv() {
-            Ok(AppEvent::Keyboard(activity)) => assert!(activity),
+        match receiver1.blocking_recv().map(|e| e.event) {
+            Ok(AppEvent::Keyboard(event)) => assert!(event.has_activity()),
             _ => panic!("Receiver 1 did not get the keyboard event"),
         }
 
         // Second receiver only gets the keyboard event (missed the mouse event)
-        match receiver2.blocking_recv() {
-            Ok(AppEvent::Keyboard(activity)) => assert!(activity),
+        match receiver2.blocking_recv().map(|e| e.event) {
+            Ok(AppEvent::Keyboard(event)) => assert!(event.has_activity()),
             _ => panic!("Receiver 2 did not get the keyboard event"),
         }
     }
@@ -196,18 +707,18 @@ mod tests {
         let mut receiver3 = monitor.subscribe();
 
         // Spawn threads to listen for events
-        let handle1 = thread::spawn(move || match receiver1.blocking_recv() {
-            Ok(AppEvent::Mouse(activity)) => activity,
+        let handle1 = thread::spawn(move || match receiver1.blocking_recv().map(|e| e.event) {
+            Ok(AppEvent::Mouse(event)) => event.has_activity(),
             _ => false,
         });
 
-        let handle2 = thread::spawn(move || match receiver2.blocking_recv() {
-            Ok(AppEvent::Mouse(activity)) => activity,
+        let handle2 = thread::spawn(move || match receiver2.blocking_recv().map(|e| e.event) {
+            Ok(AppEvent::Mouse(event)) => event.has_activity(),
             _ => false,
         });
 
-        let handle3 = thread::spawn(move || match receiver3.blocking_recv() {
-            Ok(AppEvent::Mouse(activity)) => activity,
+        let handle3 = thread::spawn(move || match receiver3.blocking_recv().map(|e| e.event) {
+            Ok(AppEvent::Mouse(event)) => event.has_activity(),
             _ => false,
         });
 
@@ -215,7 +726,7 @@ mod tests {
         thread::sleep(std::time::Duration::from_millis(100));
 
         // Send event
-        monitor.send_mouse_event(true);
+        monitor.send_mouse_event(mouse_activity());
 
         // Check results from all threads
         assert!(handle1.join().unwrap());
@@ -233,8 +744,8 @@ mod tests {
         let mut receiver2 = monitor.subscribe();
 
         // Send different types of events
-        monitor.send_mouse_event(true);
-        monitor.send_keyboard_event(false);
+        monitor.send_mouse_event(mouse_activity());
+        monitor.send_keyboard_event(KeyboardEvent::default());
 
         let window_event = WindowEvent {
             app_name: "Test App".to_string(),
@@ -249,23 +760,24 @@ mod tests {
             app_name: "Block Test".to_string(),
             app_external_id: "com.block.test".to_string(),
             is_site: false,
+            decision: None,
         };
         monitor.send_app_blocked_event(BlockedAppEvent {
             blocked_apps: vec![blocked_app.clone()],
         });
 
         // Test first receiver gets all events
-        match receiver1.blocking_recv() {
-            Ok(AppEvent::Mouse(activity)) => assert!(activity),
+        match receiver1.blocking_recv().map(|e| e.event) {
+            Ok(AppEvent::Mouse(event)) => assert!(event.has_activity()),
             _ => panic!("Receiver 1 did not get the mouse event"),
         }
 
-        match receiver1.blocking_recv() {
-            Ok(AppEvent::Keyboard(activity)) => assert!(!activity),
+        match receiver1.blocking_recv().map(|e| e.event) {
+            Ok(AppEvent::Keyboard(event)) => assert!(!event.has_activity()),
             _ => panic!("Receiver 1 did not get the keyboard event"),
         }
 
-        match receiver1.blocking_recv() {
+        match receiver1.blocking_recv().map(|e| e.event) {
             Ok(AppEvent::Window(event)) => {
                 assert_eq!(event.app_name, "Test App");
                 assert_eq!(event.window_title, "Test Window");
@@ -273,7 +785,7 @@ mod tests {
             _ => panic!("Receiver 1 did not get the window event"),
         }
 
-        match receiver1.blocking_recv() {
+        match receiver1.blocking_recv().map(|e| e.event) {
             Ok(AppEvent::AppBlocked(event)) => {
                 assert_eq!(event.blocked_apps.len(), 1);
                 assert_eq!(event.blocked_apps[0].app_name, "Block Test");
@@ -282,17 +794,17 @@ mod tests {
         }
 
         // Test second receiver also gets all events
-        match receiver2.blocking_recv() {
-            Ok(AppEvent::Mouse(activity)) => assert!(activity),
+        match receiver2.blocking_recv().map(|e| e.event) {
+            Ok(AppEvent::Mouse(event)) => assert!(event.has_activity()),
             _ => panic!("Receiver 2 did not get the mouse event"),
         }
 
-        match receiver2.blocking_recv() {
-            Ok(AppEvent::Keyboard(activity)) => assert!(!activity),
+        match receiver2.blocking_recv().map(|e| e.event) {
+            Ok(AppEvent::Keyboard(event)) => assert!(!event.has_activity()),
             _ => panic!("Receiver 2 did not get the keyboard event"),
         }
 
-        match receiver2.blocking_recv() {
+        match receiver2.blocking_recv().map(|e| e.event) {
             Ok(AppEvent::Window(event)) => {
                 assert_eq!(event.app_name, "Test App");
                 assert_eq!(event.window_title, "Test Window");
@@ -300,7 +812,7 @@ mod tests {
             _ => panic!("Receiver 2 did not get the window event"),
         }
 
-        match receiver2.blocking_recv() {
+        match receiver2.blocking_recv().map(|e| e.event) {
             Ok(AppEvent::AppBlocked(event)) => {
                 assert_eq!(event.blocked_apps.len(), 1);
                 assert_eq!(event.blocked_apps[0].app_name, "Block Test");
@@ -308,4 +820,270 @@ mod tests {
             _ => panic!("Receiver 2 did not get the app blocked event"),
         }
     }
+
+    #[test]
+    fn test_envelope_sequence_and_source() {
+        let monitor = Monitor::new();
+        let mut receiver = monitor.subscribe();
+
+        monitor.send_mouse_event(mouse_activity());
+        monitor.set_source("x11");
+        monitor.send_keyboard_event(keyboard_activity());
+
+        let first = receiver.blocking_recv().unwrap();
+        let second = receiver.blocking_recv().unwrap();
+        assert_eq!((first.sequence, second.sequence), (0, 1));
+        assert_eq!(first.source, "unknown");
+        assert_eq!(second.source, "x11");
+        assert!(first.instant <= second.instant);
+        assert!(first.timestamp <= second.timestamp);
+
+        // Sequence numbers are per monitor
+        let other = Monitor::new();
+        let mut other_receiver = other.subscribe();
+        other.send_mouse_event(MouseEvent::default());
+        assert_eq!(other_receiver.blocking_recv().unwrap().sequence, 0);
+    }
+
+    #[test]
+    fn test_envelope_sequence_across_threads() {
+        let monitor = std::sync::Arc::new(Monitor::new());
+        let mut receiver = monitor.subscribe();
+
+        let handles: Vec<_> = (0..4)
+            .map(|_| {
+                let monitor = monitor.clone();
+                thread::spawn(move || {
+                    for _ in 0..10 {
+                        monitor.send_mouse_event(mouse_activity());
+                    }
+                })
+            })
+            .collect();
+        for handle in handles {
+            handle.join().unwrap();
+        }
+
+        let sequences: Vec<u64> = (0..40)
+            .map(|_| receiver.blocking_recv().unwrap().sequence)
+            .collect();
+        assert_eq!(sequences, (0..40).collect::<Vec<u64>>());
+    }
+
+    #[test]
+    fn test_envelope_serializes_timestamp_as_millis() {
+        let envelope = EventEnvelope {
+            sequence: 7,
+            timestamp: std::time::UNIX_EPOCH + std::time::Duration::from_millis(1_700_000_000_123),
+            instant: Instant::now(),
+            source: "sway".to_string(),
+            event: AppEvent::Keyboard(KeyboardEvent::default()),
+        };
+        let json = serde_json::to_value(&envelope).unwrap();
+        assert_eq!(json["timestamp"], 1_700_000_000_123u64);
+        assert_eq!(json["sequence"], 7);
+        assert!(json.get("instant").is_none());
+
+        let parsed: EventEnvelope = serde_json::from_value(json).unwrap();
+        assert_eq!(parsed.timestamp, envelope.timestamp);
+        assert_eq!(parsed.source, "sway");
+    }
+
+    #[test]
+    fn test_builder_config() {
+        let monitor = Monitor::new();
+        assert_eq!(monitor.config().flush_interval, Duration::from_secs(30));
+        assert_eq!(monitor.config().poll_interval, Duration::from_secs(1));
+        assert_eq!(monitor.config().channel_capacity, 100);
+        assert!(monitor.config().background_polling);
+        assert!(EventKind::ALL
+            .iter()
+            .all(|kind| monitor.config().is_enabled(*kind)));
+
+        let monitor = Monitor::builder()
+            .flush_interval(Duration::from_secs(5))
+            .poll_interval(Duration::from_millis(250))
+            .channel_capacity(0)
+            .disable(EventKind::Mouse)
+            .build();
+        assert_eq!(monitor.config().flush_interval, Duration::from_secs(5));
+        assert_eq!(monitor.config().poll_interval, Duration::from_millis(250));
+        assert_eq!(monitor.config().channel_capacity, 1);
+        assert!(!monitor.config().is_enabled(EventKind::Mouse));
+        assert!(monitor.config().is_enabled(EventKind::Keyboard));
+    }
+
+    #[test]
+    fn test_window_event_urls_follow_policy() {
+        let window_event = |url: &str| WindowEvent {
+            app_name: "Firefox".to_string(),
+            window_title: "Inbox".to_string(),
+            bundle_id: None,
+            url: Some(url.to_string()),
+            platform: Platform::Linux,
+        };
+        let url = "mail.example.com/inbox?utm_source=newsletter&folder=work";
+
+        let monitor = Monitor::new();
+        let mut receiver = monitor.subscribe();
+        monitor.send_window_event(window_event(url));
+        match receiver.try_recv().map(|e| e.event) {
+            Ok(AppEvent::Window(event)) => assert_eq!(
+                event.url.as_deref(),
+                Some("https://mail.example.com/inbox?folder=work")
+            ),
+            other => panic!("unexpected event {:?}", other),
+        }
+
+        let monitor = Monitor::builder()
+            .url_policy(UrlPolicy::new().with_granularity(crate::UrlGranularity::Domain))
+            .build();
+        let mut receiver = monitor.subscribe();
+        monitor.send_window_event(window_event(url));
+        monitor.send_window_event(window_event("about:blank"));
+        let urls: Vec<Option<String>> = std::iter::from_fn(|| receiver.try_recv().ok())
+            .map(|envelope| match envelope.event {
+                AppEvent::Window(event) => event.url,
+                other => panic!("unexpected event {:?}", other),
+            })
+            .collect();
+        assert_eq!(urls, vec![Some("example.com".to_string()), None]);
+        assert_eq!(monitor.snapshot().focused_window.unwrap().url, None);
+    }
+
+    #[test]
+    fn test_blocked_site_urls_follow_policy() {
+        let monitor = Monitor::builder()
+            .url_policy(UrlPolicy::new().with_granularity(crate::UrlGranularity::Origin))
+            .build();
+        let mut receiver = monitor.subscribe();
+        let blocked = |app_external_id: &str, is_site: bool| BlockedApp {
+            app_name: "Firefox".to_string(),
+            app_external_id: app_external_id.to_string(),
+            is_site,
+            decision: None,
+        };
+        monitor.send_app_blocked_event(BlockedAppEvent {
+            blocked_apps: vec![
+                blocked("https://reddit.com/r/rust?token=secret", true),
+                blocked("org.mozilla.firefox", false),
+            ],
+        });
+        let ids: Vec<String> = match receiver.try_recv().unwrap().event {
+            AppEvent::AppBlocked(event) => event
+                .blocked_apps
+                .into_iter()
+                .map(|app| app.app_external_id)
+                .collect(),
+            other => panic!("unexpected event {:?}", other),
+        };
+        assert_eq!(ids, vec!["https://reddit.com", "org.mozilla.firefox"]);
+    }
+
+    #[test]
+    fn test_disabled_events_are_dropped() {
+        let monitor = Monitor::builder()
+            .events(&[EventKind::Window])
+            .enable(EventKind::Keyboard)
+            .build();
+        let mut receiver = monitor.subscribe();
+
+        monitor.send_mouse_event(mouse_activity());
+        monitor.send_keyboard_event(keyboard_activity());
+        monitor.send_app_blocked_event(BlockedAppEvent {
+            blocked_apps: vec![],
+        });
+
+        let envelope = receiver.try_recv().unwrap();
+        assert_eq!(envelope.event.kind(), EventKind::Keyboard);
+        // Dropped events don't leave gaps in the sequence
+        assert_eq!(envelope.sequence, 0);
+        assert!(receiver.try_recv().is_err());
+    }
+
+    #[test]
+    fn test_channel_capacity() {
+        let monitor = Monitor::builder().channel_capacity(2).build();
+        let mut receiver = monitor.subscribe();
+        for _ in 0..3 {
+            monitor.send_mouse_event(mouse_activity());
+        }
+        assert!(matches!(
+            receiver.try_recv(),
+            Err(broadcast::error::TryRecvError::Lagged(1))
+        ));
+        assert_eq!(receiver.try_recv().unwrap().sequence, 1);
+    }
+
+    #[test]
+    fn test_flush_due() {
+        let monitor = Monitor::builder().flush_interval(Duration::ZERO).build();
+        assert!(monitor.flush_due());
+
+        let monitor = Monitor::builder()
+            .flush_interval(Duration::from_secs(3600))
+            .build();
+        assert!(!monitor.flush_due());
+        monitor.mark_flushed();
+        assert!(!monitor.flush_due());
+    }
+
+    #[test]
+    fn test_idle_transition() {
+        let threshold = Duration::from_secs(300);
+        let now = std::time::UNIX_EPOCH + Duration::from_secs(10_000);
+
+        assert!(idle_transition(None, Duration::from_secs(299), threshold, now).is_none());
+        match idle_transition(None, Duration::from_secs(400), threshold, now) {
+            Some(AppEvent::Idle { since }) => {
+                assert_eq!(since, now - Duration::from_secs(400))
+            }
+            other => panic!("expected idle, got {:?}", other),
+        }
+        // Still idle, no repeat
+        assert!(idle_transition(Some(now), Duration::from_secs(500), threshold, now).is_none());
+        assert!(matches!(
+            idle_transition(Some(now), Duration::from_secs(1), threshold, now),
+            Some(AppEvent::Active)
+        ));
+    }
+
+    #[test]
+    fn test_update_idle_sends_transitions() {
+        let monitor = Monitor::builder()
+            .idle_threshold(Duration::from_secs(60))
+            .build();
+        let mut receiver = monitor.subscribe();
+
+        monitor.update_idle(Duration::from_secs(10));
+        assert!(monitor.idle_since().is_none());
+        monitor.update_idle(Duration::from_secs(61));
+        monitor.update_idle(Duration::from_secs(120));
+        assert!(monitor.idle_since().is_some());
+        monitor.update_idle(Duration::from_secs(0));
+        assert!(monitor.idle_since().is_none());
+
+        assert!(matches!(
+            receiver.try_recv().unwrap().event,
+            AppEvent::Idle { .. }
+        ));
+        assert!(matches!(
+            receiver.try_recv().unwrap().event,
+            AppEvent::Active
+        ));
+        assert!(receiver.try_recv().is_err());
+    }
+
+    #[test]
+    fn test_idle_event_serialization() {
+        let event = AppEvent::Idle {
+            since: std::time::UNIX_EPOCH + Duration::from_millis(42),
+        };
+        let json = serde_json::to_string(&event).unwrap();
+        assert_eq!(json, r#"{"Idle":{"since":42}}"#);
+        assert_eq!(
+            serde_json::to_string(&AppEvent::Active).unwrap(),
+            r#""Active""#
+        );
+    }
 }
@@ -1,17 +1,45 @@
 mod bindings;
+mod block_page;
 mod blocking;
+mod budget;
+mod dns;
 mod error;
 pub mod event;
+pub mod native_messaging;
 mod platform;
+mod recording;
+mod schedule;
+mod stream;
+mod unlock;
+mod urls;
 
-pub use blocking::BlockableItem;
+pub use block_page::{BlockPageHandle, BlockPageServer, DEFAULT_BLOCK_PAGE};
+pub use blocking::{
+    BlockDecision, BlockList, BlockableItem, ExceptionPolicy, MatchedRule, RuleKind, RuleSource,
+};
+pub use budget::{BudgetUsage, Budgets};
+pub use dns::{DnsSinkhole, DnsSinkholeHandle, SinkholeAnswer};
 pub use error::MonitorError;
 pub use event::{
-    AppEvent, BlockedApp, BlockedAppEvent, KeyboardEvent, Monitor, MouseEvent, MouseEventType,
-    Platform, WindowEvent, WindowEventType,
+    AppEvent, BlockedApp, BlockedAppEvent, EventEnvelope, EventKind, KeyboardEvent, Monitor,
+    MonitorBuilder, MonitorConfig, MonitorSnapshot, MouseEvent, MouseEventType, Platform,
+    WindowEvent, WindowEventType,
 };
+#[cfg(unix)]
+pub use native_messaging::{NativeMessagingBridge, NativeMessagingHandle};
 pub use platform::{
-    create_typewriter_window, detect_changes, get_application_icon_data,
-    has_accessibility_permissions, remove_typewriter_window, request_accessibility_permissions,
-    run_loop_cycle, start_blocking, start_monitoring, stop_blocking, sync_typewriter_window_order,
+    budget_usage, clear_block_schedule, clear_budgets, confirm_unlock, create_typewriter_window,
+    default_backend, detect_changes, get_application_icon_data, handle_focused_window,
+    has_accessibility_permissions, record_keyboard_event, record_mouse_event,
+    remove_typewriter_window, request_accessibility_permissions, request_unlock, run_loop_cycle,
+    schedule_blocking, set_backend, set_budgets, start_blocking, start_monitoring, stop_blocking,
+    sync_typewriter_window_order, unlocks, FocusedWindow, MonitorHandle, PlatformBackend,
 };
+pub use recording::{read_recording, replay, replay_file, Recorder, RecorderHandle, ReplaySpeed};
+pub use schedule::{BlockProfile, Clock, ManualClock, Schedule, ScheduleRule, SystemClock};
+pub use stream::{EventStream, StreamItem, StreamOptions};
+pub use unlock::{Unlock, UnlockFriction};
+pub use urls::{NormalizedUrl, UrlGranularity, UrlPolicy, DEFAULT_STRIPPED_PARAMS};
+
+#[cfg(feature = "testing")]
+pub use platform::FakeBackend;
@@ -1,10 +1,10 @@
 use std::sync::Arc;
 
 use os_monitor::{
-    create_typewriter_window, detect_changes, get_application_icon_data,
-    has_accessibility_permissions, remove_typewriter_window, request_accessibility_permissions,
-    run_loop_cycle, start_blocking, start_monitoring, sync_typewriter_window_order, AppEvent,
-    BlockableItem, Monitor,
+    create_typewriter_window, get_application_icon_data, has_accessibility_permissions,
+    remove_typewriter_window, request_accessibility_permissions, run_loop_cycle, start_blocking,
+    start_monitoring, sync_typewriter_window_order, AppEvent, BlockableItem, ExceptionPolicy,
+    Monitor,
 };
 
 fn main() {
@@ -21,30 +21,31 @@ fn main() {
     create_typewriter_window(0.5);
     run_loop_cycle();
 
-    let icon_data = get_application_icon_data("md.obsidian");
-    if let Some(data) = icon_data {
-        log::trace!("icon_data: {}", data.len());
+    match get_application_icon_data("md.obsidian") {
+        Ok(data) => log::trace!("icon_data: {}", data.len()),
+        Err(e) => log::warn!("icon_data: {}", e),
     }
 
-    let monitor = Monitor::new();
+    let monitor = Monitor::builder()
+        .flush_interval(std::time::Duration::from_secs(30))
+        .poll_interval(std::time::Duration::from_secs(1))
+        .build();
 
     let mut main_receiver = monitor.subscribe();
 
-    let monitor_arc = Arc::new(monitor);
-    std::thread::spawn(move || {
-        start_monitoring(monitor_arc);
-        println!("started_monitoring");
-    });
+    // Polls for changes every poll_interval until stopped
+    let mut handle = start_monitoring(Arc::new(monitor)).expect("Failed to start monitoring");
+    println!("started_monitoring");
 
     std::thread::spawn(move || {
         println!("Main event processor thread started");
-        while let Ok(event) = main_receiver.blocking_recv() {
-            match event {
-                AppEvent::Mouse(has_activity) => {
-                    log::warn!("Mouse event: {}", has_activity);
+        while let Ok(envelope) = main_receiver.blocking_recv() {
+            match envelope.event {
+                AppEvent::Mouse(event) => {
+                    log::warn!("Mouse event: {:?}", event);
                 }
-                AppEvent::Keyboard(has_activity) => {
-                    log::warn!("Keyboard event: {}", has_activity);
+                AppEvent::Keyboard(event) => {
+                    log::warn!("Keyboard event: {:?}", event);
                 }
                 AppEvent::Window(event) => {
                     log::warn!("Window event: {:?}", event);
@@ -56,6 +57,36 @@ fn main() {
                         log::warn!("  - {} ({})", app.app_name, app.app_external_id);
                     }
                 }
+                AppEvent::Idle { since } => {
+                    log::warn!("Idle since: {:?}", since);
+                }
+                AppEvent::Active => {
+                    log::warn!("Active again");
+                }
+                AppEvent::BlockingStarted { profile } => {
+                    log::warn!("Blocking started by {}", profile);
+                }
+                AppEvent::BlockingStopped { profile } => {
+                    log::warn!("Blocking stopped by {}", profile);
+                }
+                AppEvent::BudgetWarning {
+                    pattern,
+                    remaining_seconds,
+                } => {
+                    log::warn!("{}s left for {}", remaining_seconds, pattern);
+                }
+                AppEvent::BudgetExhausted { pattern } => {
+                    log::warn!("Budget used up for {}", pattern);
+                }
+                AppEvent::UnlockRequested { id, pattern } => {
+                    log::warn!("Unlock {} requested for {}", id, pattern);
+                }
+                AppEvent::UnlockGranted { id, pattern, .. } => {
+                    log::warn!("Unlock {} granted for {}", id, pattern);
+                }
+                AppEvent::UnlockExpired { id, pattern } => {
+                    log::warn!("Unlock {} expired for {}", id, pattern);
+                }
             }
         }
         log::warn!("Main event receiver channel closed");
@@ -73,23 +104,28 @@ fn main() {
             BlockableItem::new("com.google.Chrome".to_string(), false),
         ];
 
-        start_blocking(&blocked_apps, "https://ebb.cool/vibes", false);
-        println!("started_blocking");
-    });
+        // Never block ourselves on top of the platform's system apps
+        let exceptions = ExceptionPolicy::default()
+            .allow(BlockableItem::new("com.ebb.app".to_string(), false))
+            .allow(BlockableItem::new("ebb.cool".to_string(), true));
 
-    // Detect changes thread
-    std::thread::spawn(move || loop {
-        log::trace!("detect_changes start");
-        detect_changes().expect("Failed to detect changes");
-        log::trace!("detect_changes end");
-        std::thread::sleep(std::time::Duration::from_secs(1));
+        match start_blocking(&blocked_apps, "https://ebb.cool/vibes", false, &exceptions) {
+            Ok(()) => println!("started_blocking"),
+            Err(e) => log::warn!("Failed to start blocking: {}", e),
+        }
     });
 
+    let (stop_sender, stop_receiver) = std::sync::mpsc::channel();
+    ctrlc::set_handler(move || {
+        let _ = stop_sender.send(());
+    })
+    .expect("Failed to set Ctrl-C handler");
+
     std::thread::sleep(std::time::Duration::from_secs(5));
     remove_typewriter_window();
     run_loop_cycle();
 
-    loop {
-        std::thread::sleep(std::time::Duration::from_secs(1));
-    }
+    let _ = stop_receiver.recv();
+    handle.stop().expect("Failed to stop monitoring");
+    println!("stopped_monitoring");
 }
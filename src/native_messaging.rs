@@ -0,0 +1,671 @@
+//! Browser extensions report their tabs through a native messaging host, the
+//! `os-monitor-native-host` binary the browser starts and talks to over
+//! stdin/stdout. The host relays the messages to the monitor process, where a
+//! `NativeMessagingBridge` fills in the url of focused browser windows and
+//! sends redirects for blocked tabs back the same way.
+//!
+//! Every message is JSON preceded by its length as a native-endian `u32`,
+//! both between the browser and the host and between the host and the bridge.
+
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use std::io::{self, Read, Write};
+use std::path::PathBuf;
+
+/// Chrome's limit for messages to the browser, also applied to messages
+/// from it since tab reports are small
+pub const MAX_MESSAGE_LEN: usize = 1024 * 1024;
+
+/// Sent by the extension, and the host, to the monitor
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum BrowserMessage {
+    /// Sent by the host when it connects: the browser process that started
+    /// it, which owns the browser's windows
+    Hello { browser_pid: u32 },
+    /// The active tab of the focused browser window changed or navigated
+    ActiveTab {
+        tab_id: i64,
+        url: String,
+        title: String,
+        /// Reported as the app name of blocked tabs, e.g. "Firefox"
+        #[serde(default)]
+        browser: Option<String>,
+    },
+    /// A tab in the background navigated
+    TabUpdated {
+        tab_id: i64,
+        url: String,
+        title: String,
+    },
+    /// No window of the browser has focus any more
+    FocusLost,
+}
+
+/// Sent by the monitor to the extension
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum HostMessage {
+    /// Navigate the tab to the block page
+    Redirect { tab_id: i64, url: String },
+}
+
+/// Reads one length-prefixed message. None if the stream ended before it.
+pub fn read_frame<R: Read>(reader: &mut R) -> io::Result<Option<Vec<u8>>> {
+    let mut len = [0; 4];
+    match reader.read_exact(&mut len) {
+        Ok(()) => {}
+        Err(e) if e.kind() == io::ErrorKind::UnexpectedEof => return Ok(None),
+        Err(e) => return Err(e),
+    }
+    let len = u32::from_ne_bytes(len) as usize;
+    if len > MAX_MESSAGE_LEN {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("message of {} bytes is too long", len),
+        ));
+    }
+    let mut message = vec![0; len];
+    reader.read_exact(&mut message)?;
+    Ok(Some(message))
+}
+
+pub fn write_frame<W: Write>(writer: &mut W, message: &[u8]) -> io::Result<()> {
+    let len = u32::try_from(message.len())
+        .ok()
+        .filter(|len| *len as usize <= MAX_MESSAGE_LEN)
+        .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "message is too long"))?;
+    writer.write_all(&len.to_ne_bytes())?;
+    writer.write_all(message)?;
+    writer.flush()
+}
+
+/// Reads and parses one message. None if the stream ended before it.
+pub fn read_message<R: Read, T: DeserializeOwned>(reader: &mut R) -> io::Result<Option<T>> {
+    match read_frame(reader)? {
+        Some(message) => serde_json::from_slice(&message)
+            .map(Some)
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e)),
+        None => Ok(None),
+    }
+}
+
+pub fn write_message<W: Write, T: Serialize>(writer: &mut W, message: &T) -> io::Result<()> {
+    let message = serde_json::to_vec(message)?;
+    write_frame(writer, &message)
+}
+
+/// `OS_MONITOR_NATIVE_HOST_SOCKET`, otherwise a socket in `$XDG_RUNTIME_DIR`
+/// or a per-user directory in the temp directory
+pub fn default_socket_path() -> PathBuf {
+    if let Some(path) = std::env::var_os("OS_MONITOR_NATIVE_HOST_SOCKET") {
+        return PathBuf::from(path);
+    }
+    match std::env::var_os("XDG_RUNTIME_DIR") {
+        Some(dir) => PathBuf::from(dir).join("os-monitor-native-host.sock"),
+        None => std::env::temp_dir()
+            .join(format!(
+                "os-monitor-{}",
+                std::env::var("USER").unwrap_or_default()
+            ))
+            .join("native-host.sock"),
+    }
+}
+
+#[cfg(unix)]
+pub use bridge::{relay, NativeMessagingBridge, NativeMessagingHandle};
+
+#[cfg(unix)]
+mod bridge {
+    use super::*;
+    use crate::platform::browser_tabs::{self, Tab};
+    use crate::platform::handle_browser_tab;
+    use std::os::unix::fs::{DirBuilderExt, MetadataExt, PermissionsExt};
+    use std::os::unix::net::{UnixListener, UnixStream};
+    use std::path::Path;
+    use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+    use std::sync::{Arc, Mutex};
+    use std::thread::JoinHandle;
+    use std::time::Duration;
+
+    const POLL_INTERVAL: Duration = Duration::from_millis(50);
+
+    static NEXT_CONNECTION: AtomicU64 = AtomicU64::new(0);
+
+    /// Accepts connections from native messaging hosts in the monitor's
+    /// process. Focused windows of a connected browser get the url of its
+    /// active tab in window events, budgets and blocking, and blocked tabs
+    /// are redirected through the extension instead of the backend.
+    #[derive(Clone, Debug)]
+    pub struct NativeMessagingBridge {
+        socket_path: PathBuf,
+    }
+
+    impl Default for NativeMessagingBridge {
+        fn default() -> Self {
+            Self::new()
+        }
+    }
+
+    impl NativeMessagingBridge {
+        /// Listens on `default_socket_path()`
+        pub fn new() -> Self {
+            Self {
+                socket_path: default_socket_path(),
+            }
+        }
+
+        pub fn socket_path(mut self, path: impl Into<PathBuf>) -> Self {
+            self.socket_path = path.into();
+            self
+        }
+
+        /// Binds the socket, replacing one left behind by a monitor that
+        /// exited, and accepts hosts on a background thread until the handle
+        /// is stopped or dropped. Fails with `AddrInUse` if another monitor
+        /// is listening. The socket is only accessible to the current user:
+        /// a missing parent directory is created for them with mode 0700, and
+        /// fails with `PermissionDenied` if it exists but isn't theirs or
+        /// others can access it.
+        pub fn start(self) -> io::Result<NativeMessagingHandle> {
+            if UnixStream::connect(&self.socket_path).is_ok() {
+                return Err(io::Error::new(
+                    io::ErrorKind::AddrInUse,
+                    format!("{} is in use", self.socket_path.display()),
+                ));
+            }
+            let dir = match self.socket_path.parent() {
+                Some(dir) if !dir.as_os_str().is_empty() => dir,
+                _ => Path::new("."),
+            };
+            private_dir(dir)?;
+            let _ = std::fs::remove_file(&self.socket_path);
+            let listener = UnixListener::bind(&self.socket_path)?;
+            std::fs::set_permissions(&self.socket_path, std::fs::Permissions::from_mode(0o600))?;
+            listener.set_nonblocking(true)?;
+            log::info!(
+                "Native messaging bridge listening on {}",
+                self.socket_path.display()
+            );
+
+            let stop = Arc::new(AtomicBool::new(false));
+            let connections = Arc::new(Mutex::new(Vec::new()));
+            let thread = {
+                let (stop, connections) = (stop.clone(), connections.clone());
+                std::thread::Builder::new()
+                    .name("native-messaging-bridge".into())
+                    .spawn(move || accept(&listener, &stop, &connections))?
+            };
+            Ok(NativeMessagingHandle {
+                socket_path: self.socket_path,
+                stop,
+                connections,
+                thread: Some(thread),
+            })
+        }
+    }
+
+    /// Creates `dir` for the current user only, or checks that it is theirs
+    /// with mode 0700, so no one else can connect to the socket before its
+    /// permissions are set
+    fn private_dir(dir: &Path) -> io::Result<()> {
+        if !dir.exists() {
+            std::fs::DirBuilder::new()
+                .recursive(true)
+                .mode(0o700)
+                .create(dir)?;
+        }
+        let metadata = std::fs::metadata(dir)?;
+        // SAFETY: geteuid has no memory safety requirements
+        let uid = unsafe { libc::geteuid() };
+        if metadata.uid() != uid || metadata.mode() & 0o777 != 0o700 {
+            return Err(io::Error::new(
+                io::ErrorKind::PermissionDenied,
+                format!(
+                    "{} must belong to the current user with mode 0700",
+                    dir.display()
+                ),
+            ));
+        }
+        Ok(())
+    }
+
+    /// A running `NativeMessagingBridge`, stopped when dropped
+    pub struct NativeMessagingHandle {
+        socket_path: PathBuf,
+        stop: Arc<AtomicBool>,
+        // Shut down on stop so connection threads see the end of the stream
+        connections: Arc<Mutex<Vec<UnixStream>>>,
+        thread: Option<JoinHandle<()>>,
+    }
+
+    impl NativeMessagingHandle {
+        pub fn socket_path(&self) -> &Path {
+            &self.socket_path
+        }
+
+        /// Stops accepting hosts, disconnects the connected ones and removes
+        /// the socket
+        pub fn stop(mut self) {
+            self.stop_thread();
+        }
+
+        fn stop_thread(&mut self) {
+            let Some(thread) = self.thread.take() else {
+                return;
+            };
+            self.stop.store(true, Ordering::SeqCst);
+            let _ = thread.join();
+            for connection in self.connections.lock().unwrap().drain(..) {
+                let _ = connection.shutdown(std::net::Shutdown::Both);
+            }
+            let _ = std::fs::remove_file(&self.socket_path);
+        }
+    }
+
+    impl Drop for NativeMessagingHandle {
+        fn drop(&mut self) {
+            self.stop_thread();
+        }
+    }
+
+    fn accept(listener: &UnixListener, stop: &AtomicBool, connections: &Mutex<Vec<UnixStream>>) {
+        while !stop.load(Ordering::SeqCst) {
+            let stream = match listener.accept() {
+                Ok((stream, _)) => stream,
+                Err(e) if e.kind() == io::ErrorKind::WouldBlock => {
+                    std::thread::sleep(POLL_INTERVAL);
+                    continue;
+                }
+                Err(e) => {
+                    log::warn!("Native messaging accept failed: {}", e);
+                    std::thread::sleep(POLL_INTERVAL);
+                    continue;
+                }
+            };
+            if let Ok(clone) = stream.try_clone() {
+                connections.lock().unwrap().push(clone);
+            }
+            let spawned = std::thread::Builder::new()
+                .name("native-messaging-host".into())
+                .spawn(move || {
+                    if let Err(e) = serve_host(&stream) {
+                        log::warn!("Native messaging host failed: {}", e);
+                    }
+                    let _ = stream.shutdown(std::net::Shutdown::Both);
+                });
+            if let Err(e) = spawned {
+                log::warn!("Native messaging host thread failed: {}", e);
+            }
+        }
+    }
+
+    fn serve_host(stream: &UnixStream) -> io::Result<()> {
+        stream.set_nonblocking(false)?;
+        let mut reader = stream;
+        let browser_pid = match read_message(&mut reader)? {
+            Some(BrowserMessage::Hello { browser_pid }) => browser_pid,
+            Some(other) => {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    format!("expected hello, got {:?}", other),
+                ))
+            }
+            None => return Ok(()),
+        };
+        let connection = NEXT_CONNECTION.fetch_add(1, Ordering::SeqCst);
+        log::info!("Browser {} connected", browser_pid);
+        browser_tabs::connect(browser_pid, connection, Box::new(stream.try_clone()?));
+
+        let result = serve_messages(&mut reader, browser_pid);
+        browser_tabs::disconnect(browser_pid, connection);
+        log::info!("Browser {} disconnected", browser_pid);
+        result
+    }
+
+    fn serve_messages(reader: &mut &UnixStream, browser_pid: u32) -> io::Result<()> {
+        while let Some(frame) = read_frame(reader)? {
+            let message = match serde_json::from_slice::<BrowserMessage>(&frame) {
+                Ok(message) => message,
+                // Newer extensions may send messages we don't know yet
+                Err(e) => {
+                    log::debug!("Ignoring native message: {}", e);
+                    continue;
+                }
+            };
+            match message {
+                BrowserMessage::Hello { .. } => {}
+                BrowserMessage::ActiveTab {
+                    tab_id,
+                    url,
+                    title,
+                    browser,
+                } => {
+                    let tab = Tab {
+                        id: tab_id,
+                        url: url.clone(),
+                        title,
+                    };
+                    browser_tabs::set_focused_tab(browser_pid, Some(tab), browser);
+                    handle_browser_tab(browser_pid, tab_id, &url);
+                }
+                BrowserMessage::TabUpdated { tab_id, url, .. } => {
+                    handle_browser_tab(browser_pid, tab_id, &url);
+                }
+                BrowserMessage::FocusLost => {
+                    browser_tabs::set_focused_tab(browser_pid, None, None);
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// What the `os-monitor-native-host` binary runs: relays the browser's
+    /// messages from `stdin` to the bridge at `socket_path`, announcing
+    /// `browser_pid` first, and the bridge's messages to `stdout`. Returns
+    /// once the browser closed `stdin` and the bridge has hung up.
+    pub fn relay<R: Read, W: Write + Send + 'static>(
+        mut stdin: R,
+        mut stdout: W,
+        socket_path: &Path,
+        browser_pid: u32,
+    ) -> io::Result<()> {
+        let mut stream = UnixStream::connect(socket_path).map_err(|e| {
+            io::Error::new(
+                e.kind(),
+                format!("monitor not reachable at {}: {}", socket_path.display(), e),
+            )
+        })?;
+        write_message(&mut stream, &BrowserMessage::Hello { browser_pid })?;
+
+        let mut from_bridge = stream.try_clone()?;
+        let to_browser = std::thread::Builder::new()
+            .name("native-host-relay".into())
+            .spawn(move || -> io::Result<()> {
+                while let Some(frame) = read_frame(&mut from_bridge)? {
+                    write_frame(&mut stdout, &frame)?;
+                }
+                Ok(())
+            })?;
+
+        let to_bridge = (|| {
+            while let Some(frame) = read_frame(&mut stdin)? {
+                write_frame(&mut stream, &frame)?;
+            }
+            Ok(())
+        })();
+        // Lets the bridge finish up and hang up
+        let _ = stream.shutdown(std::net::Shutdown::Write);
+        let from_bridge = to_browser
+            .join()
+            .unwrap_or_else(|_| Err(io::Error::other("relay thread panicked")));
+        to_bridge.and(from_bridge)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::platform::{
+        detect_changes, set_backend, start_blocking, start_monitoring, stop_blocking, FakeBackend,
+        FocusedWindow, TEST_LOCK,
+    };
+    use crate::{AppEvent, BlockableItem, ExceptionPolicy, Monitor, RuleKind};
+    use std::io::Cursor;
+    use std::sync::{Arc, Mutex};
+
+    const REDIRECT: &str = "https://blocked.example";
+
+    /// In a directory of its own, removed by the test
+    fn socket_path(name: &str) -> PathBuf {
+        std::env::temp_dir()
+            .join(format!("os-monitor-{}-{}", name, std::process::id()))
+            .join("native-host.sock")
+    }
+
+    fn active_tab(tab_id: i64, url: &str) -> BrowserMessage {
+        BrowserMessage::ActiveTab {
+            tab_id,
+            url: url.to_string(),
+            title: "Tab".to_string(),
+            browser: Some("Firefox".to_string()),
+        }
+    }
+
+    fn block_reddit() {
+        let reddit = BlockableItem::new("reddit.com".to_string(), true).with_rule(RuleKind::Domain);
+        start_blocking(&[reddit], REDIRECT, true, &ExceptionPolicy::none()).unwrap();
+    }
+
+    fn connect_host(path: &std::path::Path) -> std::os::unix::net::UnixStream {
+        let stream = std::os::unix::net::UnixStream::connect(path).unwrap();
+        stream
+            .set_read_timeout(Some(std::time::Duration::from_secs(5)))
+            .unwrap();
+        stream
+    }
+
+    #[derive(Clone, Default)]
+    struct SharedBuffer(Arc<Mutex<Vec<u8>>>);
+
+    impl Write for SharedBuffer {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.0.lock().unwrap().extend_from_slice(buf);
+            Ok(buf.len())
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn test_framing() {
+        let mut buffer = Vec::new();
+        write_message(&mut buffer, &active_tab(1, "https://github.com")).unwrap();
+        write_message(&mut buffer, &BrowserMessage::FocusLost).unwrap();
+        let json = serde_json::to_vec(&active_tab(1, "https://github.com")).unwrap();
+        assert_eq!(buffer[..4], (json.len() as u32).to_ne_bytes());
+        assert_eq!(buffer[4..4 + json.len()], json);
+
+        let mut reader = Cursor::new(buffer.clone());
+        assert_eq!(
+            read_message::<_, BrowserMessage>(&mut reader).unwrap(),
+            Some(active_tab(1, "https://github.com"))
+        );
+        assert_eq!(
+            read_message::<_, BrowserMessage>(&mut reader).unwrap(),
+            Some(BrowserMessage::FocusLost)
+        );
+        assert_eq!(
+            read_message::<_, BrowserMessage>(&mut reader).unwrap(),
+            None
+        );
+
+        // Cut off in the middle, too long, and what an extension sends
+        let mut reader = Cursor::new(&buffer[..10]);
+        assert!(read_frame(&mut reader).is_err());
+        let mut reader = Cursor::new(((MAX_MESSAGE_LEN + 1) as u32).to_ne_bytes());
+        assert!(read_frame(&mut reader).is_err());
+        let json = br#"{"type":"tab_updated","tab_id":3,"url":"reddit.com","title":"r"}"#;
+        let mut frame = (json.len() as u32).to_ne_bytes().to_vec();
+        frame.extend_from_slice(json);
+        assert!(matches!(
+            read_message(&mut Cursor::new(frame)).unwrap(),
+            Some(BrowserMessage::TabUpdated { tab_id: 3, .. })
+        ));
+    }
+
+    #[test]
+    fn test_bridge_fills_url_and_redirects_tabs() {
+        let _guard = TEST_LOCK.lock().unwrap_or_else(|e| e.into_inner());
+        let fake = Arc::new(FakeBackend::new());
+        set_backend(fake.clone());
+        let monitor = Monitor::builder().background_polling(false).build();
+        let mut receiver = monitor.subscribe();
+        let _monitor_handle = start_monitoring(Arc::new(monitor)).unwrap();
+        block_reddit();
+
+        let bridge = NativeMessagingBridge::new()
+            .socket_path(socket_path("bridge"))
+            .start()
+            .unwrap();
+        assert!(NativeMessagingBridge::new()
+            .socket_path(bridge.socket_path())
+            .start()
+            .is_err());
+        let mut host = connect_host(bridge.socket_path());
+        write_message(&mut host, &BrowserMessage::Hello { browser_pid: 4242 }).unwrap();
+
+        // A blocked tab is redirected as soon as it is reported
+        write_message(&mut host, &active_tab(7, "https://old.reddit.com/r/rust")).unwrap();
+        assert_eq!(
+            read_message::<_, HostMessage>(&mut host).unwrap(),
+            Some(HostMessage::Redirect {
+                tab_id: 7,
+                url: REDIRECT.to_string()
+            })
+        );
+        let github = BrowserMessage::ActiveTab {
+            tab_id: 7,
+            url: "https://github.com".to_string(),
+            title: "GitHub".to_string(),
+            browser: Some("Firefox".to_string()),
+        };
+        write_message(&mut host, &github).unwrap();
+        // Blocked background tabs too
+        write_message(
+            &mut host,
+            &BrowserMessage::TabUpdated {
+                tab_id: 9,
+                url: "reddit.com".to_string(),
+                title: "reddit".to_string(),
+            },
+        )
+        .unwrap();
+        assert!(matches!(
+            read_message::<_, HostMessage>(&mut host).unwrap(),
+            Some(HostMessage::Redirect { tab_id: 9, .. })
+        ));
+
+        // The browser's focused window gets its active tab's url and title
+        fake.focus(FocusedWindow {
+            app_name: "Firefox".to_string(),
+            title: "Mozilla Firefox".to_string(),
+            bundle_id: Some("firefox".to_string()),
+            url: None,
+            pid: Some(4242),
+        });
+        detect_changes().unwrap();
+        let events: Vec<AppEvent> = std::iter::from_fn(|| receiver.try_recv().ok())
+            .map(|envelope| envelope.event)
+            .collect();
+        let blocked: Vec<(&str, &str)> = events
+            .iter()
+            .filter_map(|event| match event {
+                AppEvent::AppBlocked(event) => Some((
+                    event.blocked_apps[0].app_name.as_str(),
+                    event.blocked_apps[0].app_external_id.as_str(),
+                )),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(
+            blocked,
+            vec![
+                ("Firefox", "https://old.reddit.com/r/rust"),
+                ("Firefox", "https://reddit.com/")
+            ]
+        );
+        let window = events.iter().find_map(|event| match event {
+            AppEvent::Window(window) => Some((window.window_title.as_str(), window.url.as_deref())),
+            _ => None,
+        });
+        assert_eq!(window, Some(("GitHub", Some("https://github.com/"))));
+        assert!(fake.redirected_urls().is_empty());
+
+        write_message(&mut host, &BrowserMessage::FocusLost).unwrap();
+        drop(host);
+        bridge.stop();
+        stop_blocking();
+        let _ = std::fs::remove_dir_all(socket_path("bridge").parent().unwrap());
+    }
+
+    #[test]
+    fn test_socket_is_private() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let dir = std::env::temp_dir().join(format!("os-monitor-private-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        let bridge = NativeMessagingBridge::new()
+            .socket_path(dir.join("native-host.sock"))
+            .start()
+            .unwrap();
+        let mode =
+            |path: &std::path::Path| std::fs::metadata(path).unwrap().permissions().mode() & 0o777;
+        assert_eq!(mode(&dir), 0o700);
+        assert_eq!(mode(bridge.socket_path()), 0o600);
+        bridge.stop();
+
+        // An existing directory is reused only while others can't access it
+        let bridge = NativeMessagingBridge::new()
+            .socket_path(dir.join("native-host.sock"))
+            .start()
+            .unwrap();
+        bridge.stop();
+        std::fs::set_permissions(&dir, std::fs::Permissions::from_mode(0o755)).unwrap();
+        let error = NativeMessagingBridge::new()
+            .socket_path(dir.join("native-host.sock"))
+            .start()
+            .err()
+            .unwrap();
+        assert_eq!(error.kind(), io::ErrorKind::PermissionDenied);
+        assert!(!dir.join("native-host.sock").exists());
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn test_relay() {
+        let _guard = TEST_LOCK.lock().unwrap_or_else(|e| e.into_inner());
+        set_backend(Arc::new(FakeBackend::new()));
+        block_reddit();
+        let bridge = NativeMessagingBridge::new()
+            .socket_path(socket_path("relay"))
+            .start()
+            .unwrap();
+
+        let mut stdin = Vec::new();
+        write_message(&mut stdin, &active_tab(1, "https://www.reddit.com")).unwrap();
+        write_message(&mut stdin, &BrowserMessage::FocusLost).unwrap();
+        let stdout = SharedBuffer::default();
+        relay(
+            Cursor::new(stdin),
+            stdout.clone(),
+            bridge.socket_path(),
+            5150,
+        )
+        .unwrap();
+
+        let output = stdout.0.lock().unwrap().clone();
+        let mut reader = Cursor::new(output);
+        assert_eq!(
+            read_message::<_, HostMessage>(&mut reader).unwrap(),
+            Some(HostMessage::Redirect {
+                tab_id: 1,
+                url: REDIRECT.to_string()
+            })
+        );
+        assert_eq!(read_message::<_, HostMessage>(&mut reader).unwrap(), None);
+
+        bridge.stop();
+        assert!(relay(
+            Cursor::new(Vec::new()),
+            SharedBuffer::default(),
+            &socket_path("relay"),
+            1
+        )
+        .is_err());
+        stop_blocking();
+        let _ = std::fs::remove_dir_all(socket_path("relay").parent().unwrap());
+    }
+}
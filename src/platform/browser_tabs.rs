@@ -0,0 +1,95 @@
+use crate::native_messaging::{write_message, HostMessage};
+use once_cell::sync::Lazy;
+use std::collections::HashMap;
+use std::io::Write;
+use std::sync::Mutex;
+
+/// The active tab of a browser's focused window
+#[derive(Clone, Debug, PartialEq)]
+pub struct Tab {
+    pub id: i64,
+    pub url: String,
+    pub title: String,
+}
+
+/// A browser connected through `NativeMessagingBridge`
+struct Browser {
+    /// Tells a reconnected host apart from the one it replaced
+    connection: u64,
+    writer: Box<dyn Write + Send>,
+    name: Option<String>,
+    focused_tab: Option<Tab>,
+}
+
+// Keyed by the browser's pid, which owns its windows
+static BROWSERS: Lazy<Mutex<HashMap<u32, Browser>>> = Lazy::new(|| Mutex::new(HashMap::new()));
+
+pub fn connect(pid: u32, connection: u64, writer: Box<dyn Write + Send>) {
+    BROWSERS.lock().unwrap().insert(
+        pid,
+        Browser {
+            connection,
+            writer,
+            name: None,
+            focused_tab: None,
+        },
+    );
+}
+
+pub fn disconnect(pid: u32, connection: u64) {
+    let mut browsers = BROWSERS.lock().unwrap();
+    if browsers
+        .get(&pid)
+        .is_some_and(|browser| browser.connection == connection)
+    {
+        browsers.remove(&pid);
+    }
+}
+
+/// Sets the active tab of the browser's focused window, None when no window
+/// of it has focus
+pub fn set_focused_tab(pid: u32, tab: Option<Tab>, name: Option<String>) {
+    if let Some(browser) = BROWSERS.lock().unwrap().get_mut(&pid) {
+        browser.focused_tab = tab;
+        if name.is_some() {
+            browser.name = name;
+        }
+    }
+}
+
+pub fn focused_tab(pid: u32) -> Option<Tab> {
+    BROWSERS
+        .lock()
+        .unwrap()
+        .get(&pid)
+        .and_then(|browser| browser.focused_tab.clone())
+}
+
+/// The name the extension reported for the browser, e.g. "Firefox"
+pub fn name(pid: u32) -> Option<String> {
+    BROWSERS
+        .lock()
+        .unwrap()
+        .get(&pid)
+        .and_then(|browser| browser.name.clone())
+}
+
+/// Asks the browser's extension to navigate the tab to `url`. Returns false
+/// if the browser isn't connected or the message couldn't be sent.
+pub fn redirect(pid: u32, tab_id: i64, url: &str) -> bool {
+    let mut browsers = BROWSERS.lock().unwrap();
+    let Some(browser) = browsers.get_mut(&pid) else {
+        return false;
+    };
+    let message = HostMessage::Redirect {
+        tab_id,
+        url: url.to_string(),
+    };
+    match write_message(&mut browser.writer, &message) {
+        Ok(()) => true,
+        Err(e) => {
+            log::warn!("Failed to redirect tab {} of {}: {}", tab_id, pid, e);
+            false
+        }
+    }
+}
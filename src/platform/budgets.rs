@@ -0,0 +1,66 @@
+use crate::budget::{BudgetTracker, BudgetUsage, Budgets};
+use crate::{BlockDecision, Monitor};
+use chrono::NaiveDateTime;
+use std::sync::Mutex;
+
+/// The budgets from `set_budgets`
+static BUDGETS: Mutex<Option<BudgetTracker>> = Mutex::new(None);
+
+pub fn set_budgets(budgets: Option<Budgets>) {
+    *BUDGETS.lock().unwrap() = budgets.map(BudgetTracker::new);
+}
+
+/// Counts the time since the last tick against the budgets matching the
+/// focused window, by its url before `UrlPolicy` and its bundle id
+pub fn track(monitor: &Monitor) {
+    let snapshot = monitor.snapshot();
+    // Budgets match the url as reported, like blocking
+    let url = monitor.focused_url();
+    let bundle_id = snapshot
+        .focused_window
+        .as_ref()
+        .and_then(|window| window.bundle_id.as_deref());
+    let focused: Vec<&str> = [url.as_deref(), bundle_id].into_iter().flatten().collect();
+    let events = match BUDGETS.lock().unwrap().as_mut() {
+        Some(tracker) => tracker.tick(
+            monitor.config().clock.now(),
+            &focused,
+            snapshot.idle_since.is_none(),
+            monitor.config().idle_threshold,
+        ),
+        None => return,
+    };
+    if !events.is_empty() {
+        super::monitoring::blocking_changed();
+    }
+    for event in events {
+        monitor.send_event(event);
+    }
+}
+
+/// The decision and block page for a url or app id whose budget is used up
+pub fn exhausted(external_app_id: &str) -> Option<(BlockDecision, String)> {
+    let budgets = BUDGETS.lock().unwrap();
+    let tracker = budgets.as_ref()?;
+    tracker
+        .exhausted_decision(external_app_id)
+        .map(|decision| (decision, tracker.redirect_url().to_string()))
+}
+
+/// When used up budgets are reset, None without budgets
+pub fn resets_at(now: NaiveDateTime) -> Option<NaiveDateTime> {
+    BUDGETS
+        .lock()
+        .unwrap()
+        .as_ref()
+        .map(|tracker| tracker.resets_at(now))
+}
+
+pub fn usage() -> Vec<BudgetUsage> {
+    BUDGETS
+        .lock()
+        .unwrap()
+        .as_ref()
+        .map(BudgetTracker::usage)
+        .unwrap_or_default()
+}
@@ -0,0 +1,186 @@
+use super::monitoring::MONITOR;
+use crate::{KeyboardEvent, Monitor, MouseEvent, MouseEventType};
+use once_cell::sync::Lazy;
+use std::sync::Mutex;
+use std::time::{Instant, SystemTime};
+
+/// Input statistics accumulated since the last flush
+#[derive(Default)]
+struct ActivityStats {
+    mouse: MouseEvent,
+    keyboard: KeyboardEvent,
+    // Kept across flushes so travel is measured from the last known position
+    last_position: Option<(f64, f64)>,
+    last_mouse_second: Option<u64>,
+    last_keyboard_second: Option<u64>,
+}
+
+impl ActivityStats {
+    fn record_mouse(&mut self, x: f64, y: f64, event_type: MouseEventType, scroll_delta: i32) {
+        match event_type {
+            MouseEventType::Move => {
+                if let Some((last_x, last_y)) = self.last_position {
+                    self.mouse.travel_distance += (x - last_x).hypot(y - last_y);
+                }
+                self.last_position = Some((x, y));
+            }
+            MouseEventType::LeftDown => self.mouse.left_clicks += 1,
+            MouseEventType::RightDown => self.mouse.right_clicks += 1,
+            MouseEventType::MiddleDown => self.mouse.middle_clicks += 1,
+            MouseEventType::Scroll => self.mouse.scroll_distance += scroll_delta.unsigned_abs(),
+            MouseEventType::LeftUp | MouseEventType::RightUp | MouseEventType::MiddleUp => {}
+        }
+        if mark_second(&mut self.last_mouse_second, current_second()) {
+            self.mouse.active_seconds += 1;
+        }
+    }
+
+    fn record_keyboard(&mut self) {
+        self.keyboard.keypresses += 1;
+        if mark_second(&mut self.last_keyboard_second, current_second()) {
+            self.keyboard.active_seconds += 1;
+        }
+    }
+
+    fn take(&mut self) -> (MouseEvent, KeyboardEvent) {
+        let mouse = std::mem::take(&mut self.mouse);
+        let keyboard = std::mem::take(&mut self.keyboard);
+        self.last_mouse_second = None;
+        self.last_keyboard_second = None;
+        (mouse, keyboard)
+    }
+}
+
+static START: Lazy<Instant> = Lazy::new(Instant::now);
+
+fn current_second() -> u64 {
+    START.elapsed().as_secs()
+}
+
+/// Records the second as active, returning true if it wasn't already
+fn mark_second(last_second: &mut Option<u64>, second: u64) -> bool {
+    if *last_second == Some(second) {
+        return false;
+    }
+    *last_second = Some(second);
+    true
+}
+
+// Input hooks have no context to pass through, so activity is global
+static ACTIVITY: Lazy<Mutex<ActivityStats>> = Lazy::new(|| Mutex::new(ActivityStats::default()));
+static LAST_INPUT: Lazy<Mutex<Instant>> = Lazy::new(|| Mutex::new(Instant::now()));
+
+/// Time since the last input callback, or since monitoring started
+pub fn time_since_input() -> std::time::Duration {
+    LAST_INPUT.lock().unwrap().elapsed()
+}
+
+/// Called by backends from their input hooks for every mouse event
+pub fn record_mouse_event(x: f64, y: f64, event_type: MouseEventType, scroll_delta: i32) {
+    *LAST_INPUT.lock().unwrap() = Instant::now();
+    ACTIVITY
+        .lock()
+        .unwrap()
+        .record_mouse(x, y, event_type, scroll_delta);
+    if let Some(monitor) = MONITOR.lock().unwrap().as_ref() {
+        monitor.mark_mouse_input(SystemTime::now());
+    }
+}
+
+/// Called by backends from their input hooks for every key press. Only the
+/// press is counted, the key code is discarded.
+pub fn record_keyboard_event(_key_code: i32) {
+    *LAST_INPUT.lock().unwrap() = Instant::now();
+    ACTIVITY.lock().unwrap().record_keyboard();
+    if let Some(monitor) = MONITOR.lock().unwrap().as_ref() {
+        monitor.mark_keyboard_input(SystemTime::now());
+    }
+}
+
+/// Sends the activity gathered since the last call and resets it
+pub fn send_buffered_events(monitor: &Monitor) {
+    let (mouse, keyboard) = ACTIVITY.lock().unwrap().take();
+    monitor.send_keyboard_event(keyboard);
+    monitor.send_mouse_event(mouse);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::platform::TEST_LOCK;
+
+    #[test]
+    fn test_mouse_event_callback() {
+        let _guard = TEST_LOCK.lock().unwrap_or_else(|e| e.into_inner());
+        ACTIVITY.lock().unwrap().take();
+
+        record_mouse_event(0.0, 0.0, MouseEventType::Move, 0);
+
+        let (mouse, _) = ACTIVITY.lock().unwrap().take();
+        assert!(mouse.has_activity(), "Mouse activity should be recorded");
+    }
+
+    #[test]
+    fn test_keyboard_event_callback() {
+        let _guard = TEST_LOCK.lock().unwrap_or_else(|e| e.into_inner());
+        // Ensure there is no activity yet
+        ACTIVITY.lock().unwrap().take();
+
+        // Call the callback
+        record_keyboard_event(0);
+
+        // Verify the press was counted
+        let (_, keyboard) = ACTIVITY.lock().unwrap().take();
+        assert!(
+            keyboard.has_activity(),
+            "Keyboard activity should be recorded"
+        );
+        assert_eq!(keyboard.keypresses, 1);
+    }
+
+    #[test]
+    fn test_activity_stats_aggregation() {
+        let mut stats = ActivityStats::default();
+        stats.record_mouse(0.0, 0.0, MouseEventType::Move, 0);
+        stats.record_mouse(3.0, 4.0, MouseEventType::Move, 0);
+        stats.record_mouse(3.0, 4.0, MouseEventType::LeftDown, 0);
+        stats.record_mouse(3.0, 4.0, MouseEventType::LeftUp, 0);
+        stats.record_mouse(3.0, 4.0, MouseEventType::LeftDown, 0);
+        stats.record_mouse(3.0, 4.0, MouseEventType::RightDown, 0);
+        stats.record_mouse(3.0, 4.0, MouseEventType::MiddleDown, 0);
+        stats.record_mouse(3.0, 4.0, MouseEventType::Scroll, -3);
+        stats.record_mouse(3.0, 4.0, MouseEventType::Scroll, 2);
+        stats.record_keyboard();
+        stats.record_keyboard();
+
+        let (mouse, keyboard) = stats.take();
+        assert_eq!(mouse.left_clicks, 2);
+        assert_eq!(mouse.right_clicks, 1);
+        assert_eq!(mouse.middle_clicks, 1);
+        assert_eq!(mouse.scroll_distance, 5);
+        assert_eq!(mouse.travel_distance, 5.0);
+        // Everything above happens within a second or two
+        assert!((1..=2).contains(&mouse.active_seconds));
+        assert_eq!(keyboard.keypresses, 2);
+        assert!((1..=2).contains(&keyboard.active_seconds));
+
+        // Taking resets the counters but travel continues from the last position
+        let (mouse, keyboard) = stats.take();
+        assert_eq!(mouse, MouseEvent::default());
+        assert_eq!(keyboard, KeyboardEvent::default());
+        stats.record_mouse(6.0, 8.0, MouseEventType::Move, 0);
+        let (mouse, _) = stats.take();
+        assert_eq!(mouse.travel_distance, 5.0);
+        assert_eq!(mouse.active_seconds, 1);
+    }
+
+    #[test]
+    fn test_active_seconds_count_distinct_seconds() {
+        let mut last_second = None;
+        assert!(mark_second(&mut last_second, 10));
+        assert!(!mark_second(&mut last_second, 10));
+        assert!(mark_second(&mut last_second, 11));
+        assert!(mark_second(&mut last_second, 13));
+        assert_eq!(last_second, Some(13));
+    }
+}
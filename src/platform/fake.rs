@@ -0,0 +1,1019 @@
+use super::events::send_buffered_events;
+use super::monitoring::MONITOR;
+use super::{record_keyboard_event, record_mouse_event, FocusedWindow, PlatformBackend};
+use crate::blocking::BlockList;
+use crate::{BlockableItem, MonitorError, MouseEventType, Platform};
+use std::sync::Mutex;
+use std::time::Duration;
+
+#[derive(Debug, Clone)]
+struct RunningApp {
+    app_name: String,
+    bundle_id: String,
+}
+
+#[derive(Default)]
+struct FakeState {
+    focused: Option<FocusedWindow>,
+    running_apps: Vec<RunningApp>,
+    block_list: Option<BlockList>,
+    closed_apps: Vec<String>,
+    redirected_urls: Vec<String>,
+    monitoring: bool,
+    typewriter_opacity: Option<f64>,
+    idle_time: Option<Duration>,
+    focus_event_driven: bool,
+}
+
+/// Scriptable backend for end-to-end tests. Tests push focus changes, urls,
+/// input and app launches, install it with `set_backend` and drive the real
+/// `detect_changes`. Closing and redirecting only update the fake's state,
+/// which can be inspected afterwards.
+pub struct FakeBackend {
+    platform: Platform,
+    state: Mutex<FakeState>,
+}
+
+impl Default for FakeBackend {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl FakeBackend {
+    pub fn new() -> Self {
+        Self::with_platform(Platform::Linux)
+    }
+
+    /// Platform reported in window events
+    pub fn with_platform(platform: Platform) -> Self {
+        Self {
+            platform,
+            state: Mutex::new(FakeState::default()),
+        }
+    }
+
+    /// Starts the app if it isn't running yet, without focusing it
+    pub fn launch_app(&self, app_name: &str, bundle_id: &str) {
+        let mut state = self.state.lock().unwrap();
+        if !state
+            .running_apps
+            .iter()
+            .any(|app| app.bundle_id == bundle_id)
+        {
+            state.running_apps.push(RunningApp {
+                app_name: app_name.to_string(),
+                bundle_id: bundle_id.to_string(),
+            });
+        }
+    }
+
+    /// Brings a window of the app to the front, launching it if needed
+    pub fn focus_window(&self, app_name: &str, bundle_id: &str, title: &str) {
+        self.focus(FocusedWindow {
+            app_name: app_name.to_string(),
+            title: title.to_string(),
+            bundle_id: Some(bundle_id.to_string()),
+            url: None,
+            pid: None,
+        });
+    }
+
+    pub fn focus(&self, window: FocusedWindow) {
+        if let Some(bundle_id) = &window.bundle_id {
+            self.launch_app(&window.app_name, bundle_id);
+        }
+        self.state.lock().unwrap().focused = Some(window);
+    }
+
+    /// Navigates the focused window to a url, as if it were a browser tab
+    pub fn navigate(&self, url: &str, title: &str) {
+        let mut state = self.state.lock().unwrap();
+        if let Some(window) = state.focused.as_mut() {
+            window.url = Some(url.to_string());
+            window.title = title.to_string();
+        }
+    }
+
+    pub fn clear_focus(&self) {
+        self.state.lock().unwrap().focused = None;
+    }
+
+    pub fn mouse_burst(&self, count: usize) {
+        for i in 0..count {
+            record_mouse_event(i as f64, i as f64, MouseEventType::Move, 0);
+        }
+    }
+
+    pub fn keyboard_burst(&self, count: usize) {
+        for i in 0..count {
+            record_keyboard_event(i as i32);
+        }
+    }
+
+    /// Sends the buffered mouse and keyboard activity now instead of waiting
+    /// for the flush interval
+    pub fn flush_activity(&self) {
+        let monitor = MONITOR.lock().unwrap().clone();
+        if let Some(monitor) = monitor {
+            send_buffered_events(&monitor);
+            monitor.mark_flushed();
+        }
+    }
+
+    pub fn focused_window(&self) -> Option<FocusedWindow> {
+        self.state.lock().unwrap().focused.clone()
+    }
+
+    pub fn running_apps(&self) -> Vec<String> {
+        let state = self.state.lock().unwrap();
+        state
+            .running_apps
+            .iter()
+            .map(|app| app.bundle_id.clone())
+            .collect()
+    }
+
+    /// Bundle ids of every app closed by the blocker, in order
+    pub fn closed_apps(&self) -> Vec<String> {
+        self.state.lock().unwrap().closed_apps.clone()
+    }
+
+    /// Urls the blocker redirected away from, in order
+    pub fn redirected_urls(&self) -> Vec<String> {
+        self.state.lock().unwrap().redirected_urls.clone()
+    }
+
+    pub fn is_blocking(&self) -> bool {
+        self.state.lock().unwrap().block_list.is_some()
+    }
+
+    pub fn is_monitoring(&self) -> bool {
+        self.state.lock().unwrap().monitoring
+    }
+
+    pub fn typewriter_opacity(&self) -> Option<f64> {
+        self.state.lock().unwrap().typewriter_opacity
+    }
+
+    /// Sets the OS idle time reported to idle detection. With None, idle
+    /// detection falls back to the time since the last input burst.
+    pub fn set_idle_time(&self, idle_time: Option<Duration>) {
+        self.state.lock().unwrap().idle_time = idle_time;
+    }
+
+    /// Acts like a backend that reports focus changes itself, e.g. sway:
+    /// `detect_changes` no longer polls `focused_window`, tests call
+    /// `handle_focused_window` instead
+    pub fn set_focus_event_driven(&self, event_driven: bool) {
+        self.state.lock().unwrap().focus_event_driven = event_driven;
+    }
+}
+
+fn close_running_app(state: &mut FakeState, bundle_id: &str) -> Option<RunningApp> {
+    let index = state
+        .running_apps
+        .iter()
+        .position(|app| app.bundle_id == bundle_id)?;
+    let app = state.running_apps.remove(index);
+    state.closed_apps.push(app.bundle_id.clone());
+
+    let closed_focused = state
+        .focused
+        .as_ref()
+        .is_some_and(|window| window.bundle_id.as_deref() == Some(bundle_id));
+    if closed_focused {
+        state.focused = None;
+    }
+    Some(app)
+}
+
+impl PlatformBackend for FakeBackend {
+    fn platform(&self) -> Platform {
+        self.platform
+    }
+
+    fn name(&self) -> &'static str {
+        "fake"
+    }
+
+    fn start_monitoring(&self) -> Result<(), MonitorError> {
+        self.state.lock().unwrap().monitoring = true;
+        Ok(())
+    }
+
+    fn stop_monitoring(&self) -> Result<(), MonitorError> {
+        self.state.lock().unwrap().monitoring = false;
+        Ok(())
+    }
+
+    fn focused_window(&self) -> Result<Option<FocusedWindow>, MonitorError> {
+        Ok(FakeBackend::focused_window(self))
+    }
+
+    fn is_focus_event_driven(&self) -> bool {
+        self.state.lock().unwrap().focus_event_driven
+    }
+
+    fn idle_time(&self) -> Option<Duration> {
+        self.state.lock().unwrap().idle_time
+    }
+
+    fn start_blocking(
+        &self,
+        blocked_apps: &[BlockableItem],
+        redirect_url: &str,
+        blocklist_mode: bool,
+    ) -> Result<(), MonitorError> {
+        self.state.lock().unwrap().block_list =
+            Some(BlockList::new(blocked_apps, redirect_url, blocklist_mode));
+        Ok(())
+    }
+
+    fn running_apps(&self) -> Vec<FocusedWindow> {
+        self.state
+            .lock()
+            .unwrap()
+            .running_apps
+            .iter()
+            .map(|app| FocusedWindow {
+                app_name: app.app_name.clone(),
+                title: String::new(),
+                bundle_id: Some(app.bundle_id.clone()),
+                url: None,
+                pid: None,
+            })
+            .collect()
+    }
+
+    fn stop_blocking(&self) {
+        self.state.lock().unwrap().block_list = None;
+    }
+
+    fn close_app(&self, window: &FocusedWindow) -> bool {
+        let Some(bundle_id) = &window.bundle_id else {
+            return false;
+        };
+        close_running_app(&mut self.state.lock().unwrap(), bundle_id).is_some()
+    }
+
+    /// Points the focused window at the redirect url, like a browser would
+    fn redirect_to_block_page(&self, redirect_url: &str) -> bool {
+        let mut state = self.state.lock().unwrap();
+        let Some(url) = state.focused.as_mut().and_then(|window| window.url.take()) else {
+            return false;
+        };
+
+        state.redirected_urls.push(url);
+        if let Some(window) = state.focused.as_mut() {
+            window.url = Some(redirect_url.to_string());
+        }
+        true
+    }
+
+    fn create_typewriter_window(&self, opacity: f64) {
+        self.state.lock().unwrap().typewriter_opacity = Some(opacity);
+    }
+
+    fn remove_typewriter_window(&self) {
+        self.state.lock().unwrap().typewriter_opacity = None;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::platform::{detect_changes, set_backend, start_blocking, start_monitoring};
+    use crate::platform::{stop_blocking, TEST_LOCK};
+    use crate::{
+        AppEvent, EventEnvelope, ExceptionPolicy, Monitor, MonitorHandle, RuleKind, RuleSource,
+    };
+    use std::sync::{Arc, MutexGuard};
+    use tokio::sync::broadcast::Receiver;
+
+    fn setup() -> (
+        MutexGuard<'static, ()>,
+        MonitorHandle,
+        Arc<FakeBackend>,
+        Receiver<EventEnvelope>,
+    ) {
+        let guard = TEST_LOCK.lock().unwrap_or_else(|e| e.into_inner());
+        let fake = Arc::new(FakeBackend::new());
+        set_backend(fake.clone());
+
+        // Tests drive detect_changes themselves
+        let monitor = Monitor::builder().background_polling(false).build();
+        let receiver = monitor.subscribe();
+        let handle = start_monitoring(Arc::new(monitor)).unwrap();
+        (guard, handle, fake, receiver)
+    }
+
+    fn drain(receiver: &mut Receiver<EventEnvelope>) -> Vec<AppEvent> {
+        std::iter::from_fn(|| receiver.try_recv().ok())
+            .map(|envelope| {
+                assert_eq!(envelope.source, "fake");
+                envelope.event
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_focus_changes_emit_window_events() {
+        let (_guard, _handle, fake, mut receiver) = setup();
+        assert!(fake.is_monitoring());
+
+        fake.focus_window("Editor", "org.editor", "fake_focus.rs");
+        detect_changes().unwrap();
+        // Unchanged focus doesn't repeat the event
+        detect_changes().unwrap();
+        fake.focus_window("Editor", "org.editor", "fake_focus.rs - modified");
+        detect_changes().unwrap();
+        // Nothing focused (e.g. desktop clicked) sends nothing
+        fake.clear_focus();
+        detect_changes().unwrap();
+
+        let titles: Vec<String> = drain(&mut receiver)
+            .into_iter()
+            .map(|event| match event {
+                AppEvent::Window(event) => {
+                    assert_eq!(event.platform, Platform::Linux);
+                    assert_eq!(event.bundle_id.as_deref(), Some("org.editor"));
+                    event.window_title
+                }
+                other => panic!("unexpected event {:?}", other),
+            })
+            .collect();
+        assert_eq!(titles, vec!["fake_focus.rs", "fake_focus.rs - modified"]);
+    }
+
+    #[test]
+    fn test_blocklist_redirects_blocked_site() {
+        let (_guard, _handle, fake, mut receiver) = setup();
+        start_blocking(
+            &[BlockableItem::new("news.example".to_string(), true)],
+            "https://blocked.example",
+            true,
+            &ExceptionPolicy::default(),
+        )
+        .unwrap();
+
+        fake.focus_window("Browser", "org.browser", "Fake Docs");
+        fake.navigate("docs.example/guide", "Fake Docs");
+        detect_changes().unwrap();
+        fake.navigate("news.example/today", "Fake News");
+        detect_changes().unwrap();
+
+        assert_eq!(fake.redirected_urls(), vec!["news.example/today"]);
+        assert!(fake.closed_apps().is_empty());
+        assert_eq!(
+            fake.focused_window().unwrap().url.as_deref(),
+            Some("https://blocked.example")
+        );
+
+        let events = drain(&mut receiver);
+        assert_eq!(events.len(), 3);
+        match &events[1] {
+            AppEvent::AppBlocked(event) => {
+                assert_eq!(event.blocked_apps.len(), 1);
+                assert_eq!(
+                    event.blocked_apps[0].app_external_id,
+                    "https://news.example/today"
+                );
+                assert!(event.blocked_apps[0].is_site);
+                let decision = event.blocked_apps[0].decision.as_ref().unwrap();
+                assert!(decision.blocked && decision.blocklist_mode);
+                let matched = decision.matched.as_ref().unwrap();
+                assert_eq!(matched.pattern, "news.example");
+                assert_eq!(matched.source, RuleSource::List);
+            }
+            other => panic!("expected blocked event, got {:?}", other),
+        }
+        assert!(matches!(&events[2], AppEvent::Window(event) if event.window_title == "Fake News"));
+        stop_blocking();
+    }
+
+    #[test]
+    fn test_rules_match_subdomains_and_paths() {
+        let (_guard, _handle, fake, _receiver) = setup();
+        start_blocking(
+            &[
+                BlockableItem::new("facebook.com".to_string(), true).with_rule(RuleKind::Domain),
+                BlockableItem::new("youtube.com/shorts".to_string(), true)
+                    .with_rule(RuleKind::PathPrefix),
+            ],
+            "https://blocked.example",
+            true,
+            &ExceptionPolicy::default(),
+        )
+        .unwrap();
+
+        fake.focus_window("Browser", "org.browser", "Video");
+        for url in [
+            "youtube.com/watch?v=1",
+            "m.facebook.com/home",
+            "www.youtube.com/shorts/2",
+        ] {
+            fake.navigate(url, url);
+            detect_changes().unwrap();
+        }
+
+        assert_eq!(
+            fake.redirected_urls(),
+            vec!["m.facebook.com/home", "www.youtube.com/shorts/2"]
+        );
+        stop_blocking();
+    }
+
+    #[test]
+    fn test_allowlist_closes_apps_and_keeps_exceptions() {
+        let (_guard, _handle, fake, mut receiver) = setup();
+        fake.launch_app("Slack", "com.tinyspeck.slackmacgap");
+        fake.launch_app("Finder", "com.apple.finder");
+        fake.launch_app("Chrome", "com.google.Chrome");
+        fake.launch_app("Editor", "org.editor");
+
+        start_blocking(
+            &[
+                BlockableItem::new("github.com".to_string(), true),
+                BlockableItem::new("org.editor".to_string(), false),
+            ],
+            "https://blocked.example",
+            false,
+            &ExceptionPolicy::for_platform(Platform::Mac),
+        )
+        .unwrap();
+
+        // Finder is a system exception and Chrome a browser exception because
+        // a website is allowed
+        assert_eq!(fake.closed_apps(), vec!["com.tinyspeck.slackmacgap"]);
+        assert_eq!(
+            fake.running_apps(),
+            vec!["com.apple.finder", "com.google.Chrome", "org.editor"]
+        );
+        match drain(&mut receiver).as_slice() {
+            [AppEvent::AppBlocked(event)] => {
+                assert_eq!(event.blocked_apps.len(), 1);
+                assert_eq!(event.blocked_apps[0].app_name, "Slack");
+                assert!(!event.blocked_apps[0].is_site);
+                // Blocked for not being on the allowlist
+                let decision = event.blocked_apps[0].decision.as_ref().unwrap();
+                assert!(!decision.blocklist_mode);
+                assert!(decision.matched.is_none());
+            }
+            other => panic!("expected one blocked event, got {:?}", other),
+        }
+
+        // Relaunching a blocked app closes it once it gets focus
+        fake.focus_window("Slack", "com.tinyspeck.slackmacgap", "Fake Slack");
+        detect_changes().unwrap();
+        assert_eq!(
+            fake.closed_apps(),
+            vec!["com.tinyspeck.slackmacgap", "com.tinyspeck.slackmacgap"]
+        );
+        assert!(fake.focused_window().is_none());
+        assert!(drain(&mut receiver)
+            .iter()
+            .any(|event| matches!(event, AppEvent::AppBlocked(_))));
+
+        // Allowed sites are left alone
+        fake.focus_window("Chrome", "com.google.Chrome", "Fake GitHub");
+        fake.navigate("github.com/rust-lang", "Fake GitHub");
+        detect_changes().unwrap();
+        assert!(fake.redirected_urls().is_empty());
+
+        stop_blocking();
+        assert!(!fake.is_blocking());
+        fake.focus_window("Slack", "com.tinyspeck.slackmacgap", "Fake Slack");
+        detect_changes().unwrap();
+        assert_eq!(fake.closed_apps().len(), 2);
+    }
+
+    #[test]
+    fn test_running_apps_are_matched_by_rules() {
+        let (_guard, _handle, fake, mut receiver) = setup();
+        fake.launch_app("IntelliJ", "com.jetbrains.intellij");
+        fake.launch_app("Editor", "Org.Editor");
+        fake.launch_app("Slack", "com.tinyspeck.slackmacgap");
+
+        // Allowed by a pattern and by an id that differs in case
+        start_blocking(
+            &[
+                BlockableItem::new("com.jetbrains.*".to_string(), false)
+                    .with_rule(RuleKind::AppIdPattern),
+                BlockableItem::new("org.editor".to_string(), false),
+            ],
+            "https://blocked.example",
+            false,
+            &ExceptionPolicy::none(),
+        )
+        .unwrap();
+        assert_eq!(fake.closed_apps(), vec!["com.tinyspeck.slackmacgap"]);
+        stop_blocking();
+
+        // A blocklist pattern closes apps that are already running
+        start_blocking(
+            &[BlockableItem::new("com.jetbrains.*".to_string(), false)
+                .with_rule(RuleKind::AppIdPattern)],
+            "https://blocked.example",
+            true,
+            &ExceptionPolicy::none(),
+        )
+        .unwrap();
+        assert_eq!(
+            fake.closed_apps(),
+            vec!["com.tinyspeck.slackmacgap", "com.jetbrains.intellij"]
+        );
+        assert_eq!(fake.running_apps(), vec!["Org.Editor"]);
+        let blocked: Vec<String> = drain(&mut receiver)
+            .into_iter()
+            .filter_map(|event| match event {
+                AppEvent::AppBlocked(event) => Some(event.blocked_apps[0].app_name.clone()),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(blocked, vec!["Slack", "IntelliJ"]);
+        stop_blocking();
+    }
+
+    #[test]
+    fn test_input_bursts_are_flushed() {
+        let (_guard, _handle, fake, mut receiver) = setup();
+        fake.flush_activity();
+        drain(&mut receiver);
+
+        fake.mouse_burst(5);
+        fake.flush_activity();
+        match drain(&mut receiver).as_slice() {
+            [AppEvent::Keyboard(keyboard), AppEvent::Mouse(mouse)] => {
+                assert!(!keyboard.has_activity());
+                assert!(mouse.has_activity());
+                // The burst moves diagonally one point per event
+                assert!((mouse.travel_distance - 4.0 * 2f64.sqrt()).abs() < 1e-9);
+            }
+            other => panic!("unexpected events {:?}", other),
+        }
+
+        fake.keyboard_burst(3);
+        fake.flush_activity();
+        match drain(&mut receiver).as_slice() {
+            [AppEvent::Keyboard(keyboard), AppEvent::Mouse(mouse)] => {
+                assert_eq!(keyboard.keypresses, 3);
+                assert!(!mouse.has_activity());
+            }
+            other => panic!("unexpected events {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_flush_interval_comes_from_monitor() {
+        let _guard = TEST_LOCK.lock().unwrap_or_else(|e| e.into_inner());
+        let fake = Arc::new(FakeBackend::new());
+        set_backend(fake.clone());
+
+        let monitor = Monitor::builder()
+            .flush_interval(std::time::Duration::ZERO)
+            .disable(crate::EventKind::Mouse)
+            .background_polling(false)
+            .build();
+        let mut receiver = monitor.subscribe();
+        let _handle = start_monitoring(Arc::new(monitor)).unwrap();
+
+        fake.keyboard_burst(2);
+        detect_changes().unwrap();
+        match drain(&mut receiver).as_slice() {
+            [AppEvent::Keyboard(keyboard)] => assert_eq!(keyboard.keypresses, 2),
+            other => panic!("unexpected events {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_idle_transitions() {
+        let _guard = TEST_LOCK.lock().unwrap_or_else(|e| e.into_inner());
+        let fake = Arc::new(FakeBackend::new());
+        set_backend(fake.clone());
+        let monitor = Monitor::builder()
+            .idle_threshold(Duration::from_secs(60))
+            .events(&[crate::EventKind::Idle])
+            .background_polling(false)
+            .build();
+        let mut receiver = monitor.subscribe();
+        let _handle = start_monitoring(Arc::new(monitor)).unwrap();
+
+        fake.set_idle_time(Some(Duration::from_secs(30)));
+        detect_changes().unwrap();
+        fake.set_idle_time(Some(Duration::from_secs(90)));
+        detect_changes().unwrap();
+        detect_changes().unwrap();
+        fake.set_idle_time(Some(Duration::from_secs(1)));
+        detect_changes().unwrap();
+
+        match drain(&mut receiver).as_slice() {
+            [AppEvent::Idle { since }, AppEvent::Active] => {
+                let idle_for = std::time::SystemTime::now().duration_since(*since).unwrap();
+                assert!(idle_for >= Duration::from_secs(90));
+            }
+            other => panic!("unexpected events {:?}", other),
+        }
+
+        // Without an OS idle time the input callbacks are used
+        fake.set_idle_time(None);
+        fake.keyboard_burst(1);
+        detect_changes().unwrap();
+        assert!(drain(&mut receiver).is_empty());
+    }
+
+    #[test]
+    fn test_schedule_turns_blocking_on_and_off() {
+        use crate::platform::{clear_block_schedule, schedule_blocking};
+        use crate::{BlockProfile, ManualClock, Schedule};
+        use chrono::{NaiveDate, NaiveTime, Weekday};
+
+        let _guard = TEST_LOCK.lock().unwrap_or_else(|e| e.into_inner());
+        let fake = Arc::new(FakeBackend::new());
+        set_backend(fake.clone());
+        // 2024-01-01 was a Monday
+        let monday = NaiveDate::from_ymd_opt(2024, 1, 1).unwrap();
+        let clock = Arc::new(ManualClock::new(monday.and_hms_opt(8, 59, 0).unwrap()));
+        let monitor = Monitor::builder()
+            .clock(clock.clone())
+            .events(&[crate::EventKind::Blocking])
+            .background_polling(false)
+            .build();
+        let mut receiver = monitor.subscribe();
+        let _handle = start_monitoring(Arc::new(monitor)).unwrap();
+
+        let profile = BlockProfile::new(
+            "work",
+            &[BlockableItem::new("news.example".to_string(), true)],
+            "https://blocked.example",
+            true,
+        )
+        .with_schedule(Schedule::new().weekly(
+            &[Weekday::Mon],
+            NaiveTime::from_hms_opt(9, 0, 0).unwrap(),
+            NaiveTime::from_hms_opt(17, 0, 0).unwrap(),
+        ));
+        schedule_blocking(profile).unwrap();
+        detect_changes().unwrap();
+        assert!(!fake.is_blocking());
+        assert!(drain(&mut receiver).is_empty());
+
+        // The schedule applies to the focus check on the same tick
+        clock.advance(Duration::from_secs(60));
+        fake.focus_window("Browser", "org.browser", "News");
+        fake.navigate("news.example/today", "News");
+        detect_changes().unwrap();
+        detect_changes().unwrap();
+        assert!(fake.is_blocking());
+        assert_eq!(fake.redirected_urls(), vec!["news.example/today"]);
+        match drain(&mut receiver).as_slice() {
+            [AppEvent::BlockingStarted { profile }] => assert_eq!(profile, "work"),
+            other => panic!("unexpected events {:?}", other),
+        }
+
+        clock.set(monday.and_hms_opt(17, 0, 0).unwrap());
+        detect_changes().unwrap();
+        assert!(!fake.is_blocking());
+        assert!(matches!(
+            drain(&mut receiver).as_slice(),
+            [AppEvent::BlockingStopped { .. }]
+        ));
+
+        // Clearing the schedule while it blocks stops blocking
+        clock.set(monday.and_hms_opt(10, 0, 0).unwrap());
+        detect_changes().unwrap();
+        assert!(fake.is_blocking());
+        clear_block_schedule();
+        assert!(!fake.is_blocking());
+        assert!(matches!(
+            drain(&mut receiver).as_slice(),
+            [
+                AppEvent::BlockingStarted { .. },
+                AppEvent::BlockingStopped { .. }
+            ]
+        ));
+        detect_changes().unwrap();
+        assert!(!fake.is_blocking());
+    }
+
+    #[test]
+    fn test_budget_closes_app_without_blocking() {
+        use crate::platform::{clear_budgets, set_budgets};
+        use crate::{Budgets, ManualClock};
+        use chrono::NaiveDate;
+
+        let _guard = TEST_LOCK.lock().unwrap_or_else(|e| e.into_inner());
+        let fake = Arc::new(FakeBackend::new());
+        set_backend(fake.clone());
+        stop_blocking();
+        let day = NaiveDate::from_ymd_opt(2024, 1, 1).unwrap();
+        let clock = Arc::new(ManualClock::new(day.and_hms_opt(9, 0, 0).unwrap()));
+        let monitor = Monitor::builder()
+            .clock(clock.clone())
+            .background_polling(false)
+            .build();
+        let _handle = start_monitoring(Arc::new(monitor)).unwrap();
+
+        let minute = Duration::from_secs(60);
+        set_budgets(Budgets::new("https://blocked.example").limit(
+            BlockableItem::new("com.slack.Slack".to_string(), false),
+            2 * minute,
+        ))
+        .unwrap();
+        fake.focus_window("Slack", "com.slack.Slack", "general");
+        detect_changes().unwrap();
+        for _ in 0..2 {
+            clock.advance(minute);
+            detect_changes().unwrap();
+        }
+        assert!(!fake.is_blocking());
+        assert_eq!(fake.closed_apps(), vec!["com.slack.Slack"]);
+        clear_budgets();
+    }
+
+    #[test]
+    fn test_event_driven_focus_is_checked_when_budget_runs_out() {
+        use crate::platform::{clear_budgets, handle_focused_window, set_budgets};
+        use crate::{Budgets, ManualClock};
+        use chrono::NaiveDate;
+
+        let _guard = TEST_LOCK.lock().unwrap_or_else(|e| e.into_inner());
+        let fake = Arc::new(FakeBackend::new());
+        fake.set_focus_event_driven(true);
+        set_backend(fake.clone());
+        stop_blocking();
+        let day = NaiveDate::from_ymd_opt(2024, 1, 1).unwrap();
+        let clock = Arc::new(ManualClock::new(day.and_hms_opt(9, 0, 0).unwrap()));
+        let monitor = Monitor::builder()
+            .clock(clock.clone())
+            .background_polling(false)
+            .build();
+        let _handle = start_monitoring(Arc::new(monitor)).unwrap();
+
+        let minute = Duration::from_secs(60);
+        set_budgets(Budgets::new("https://blocked.example").limit(
+            BlockableItem::new("com.slack.Slack".to_string(), false),
+            2 * minute,
+        ))
+        .unwrap();
+        // The only focus event, the window keeps focus afterwards
+        fake.focus_window("Slack", "com.slack.Slack", "general");
+        handle_focused_window(fake.as_ref(), fake.focused_window().unwrap());
+        detect_changes().unwrap();
+        for _ in 0..2 {
+            clock.advance(minute);
+            detect_changes().unwrap();
+        }
+        assert_eq!(fake.closed_apps(), vec!["com.slack.Slack"]);
+        clear_budgets();
+    }
+
+    #[test]
+    fn test_budgets_count_urls_before_url_policy() {
+        use crate::platform::{budget_usage, clear_budgets, set_budgets};
+        use crate::{Budgets, ManualClock, UrlGranularity, UrlPolicy};
+        use chrono::NaiveDate;
+
+        let _guard = TEST_LOCK.lock().unwrap_or_else(|e| e.into_inner());
+        let fake = Arc::new(FakeBackend::new());
+        set_backend(fake.clone());
+        stop_blocking();
+        let day = NaiveDate::from_ymd_opt(2024, 1, 1).unwrap();
+        let clock = Arc::new(ManualClock::new(day.and_hms_opt(9, 0, 0).unwrap()));
+        let monitor = Arc::new(
+            Monitor::builder()
+                .clock(clock.clone())
+                .url_policy(UrlPolicy::new().with_granularity(UrlGranularity::Domain))
+                .background_polling(false)
+                .build(),
+        );
+        let _handle = start_monitoring(monitor.clone()).unwrap();
+
+        let minute = Duration::from_secs(60);
+        set_budgets(
+            Budgets::new("https://blocked.example")
+                .limit(
+                    BlockableItem::new("reddit.com/r/rust".to_string(), true)
+                        .with_rule(RuleKind::PathPrefix),
+                    2 * minute,
+                )
+                .limit(
+                    BlockableItem::new("mail.google.com".to_string(), true),
+                    10 * minute,
+                ),
+        )
+        .unwrap();
+        fake.focus_window("Browser", "org.browser", "Rust");
+        fake.navigate("https://www.reddit.com/r/rust/comments", "Rust");
+        detect_changes().unwrap();
+        assert_eq!(
+            monitor.snapshot().focused_window.unwrap().url.as_deref(),
+            Some("reddit.com")
+        );
+        for _ in 0..2 {
+            clock.advance(minute);
+            detect_changes().unwrap();
+        }
+        assert_eq!(
+            fake.redirected_urls(),
+            vec!["https://www.reddit.com/r/rust/comments"]
+        );
+
+        fake.navigate("mail.google.com/mail/u/0", "Inbox");
+        detect_changes().unwrap();
+        clock.advance(minute);
+        detect_changes().unwrap();
+        assert_eq!(budget_usage()[1].remaining(), 9 * minute);
+        clear_budgets();
+    }
+
+    #[test]
+    fn test_budget_blocks_site_when_used_up() {
+        use crate::platform::{budget_usage, clear_budgets, set_budgets};
+        use crate::{Budgets, ManualClock};
+        use chrono::NaiveDate;
+
+        let _guard = TEST_LOCK.lock().unwrap_or_else(|e| e.into_inner());
+        let fake = Arc::new(FakeBackend::new());
+        set_backend(fake.clone());
+        let day = NaiveDate::from_ymd_opt(2024, 1, 1).unwrap();
+        let clock = Arc::new(ManualClock::new(day.and_hms_opt(9, 0, 0).unwrap()));
+        let monitor = Monitor::builder()
+            .clock(clock.clone())
+            .events(&[crate::EventKind::Budget, crate::EventKind::AppBlocked])
+            .background_polling(false)
+            .build();
+        let mut receiver = monitor.subscribe();
+        let _handle = start_monitoring(Arc::new(monitor)).unwrap();
+
+        let minute = Duration::from_secs(60);
+        set_budgets(
+            Budgets::new("https://blocked.example")
+                .limit(
+                    BlockableItem::new("reddit.com".to_string(), true).with_rule(RuleKind::Domain),
+                    10 * minute,
+                )
+                .warn_at(2 * minute),
+        )
+        .unwrap();
+        fake.focus_window("Browser", "org.browser", "Reddit");
+        fake.navigate("www.reddit.com/r/rust", "Reddit");
+        detect_changes().unwrap();
+
+        for _ in 0..9 {
+            clock.advance(minute);
+            detect_changes().unwrap();
+        }
+        assert!(fake.redirected_urls().is_empty());
+        assert_eq!(budget_usage()[0].remaining(), minute);
+        match drain(&mut receiver).as_slice() {
+            [AppEvent::BudgetWarning {
+                pattern,
+                remaining_seconds,
+            }] => {
+                assert_eq!(pattern, "reddit.com");
+                assert_eq!(*remaining_seconds, 120);
+            }
+            other => panic!("unexpected events {:?}", other),
+        }
+
+        // Used up and redirected on the same tick, to the budget's page
+        clock.advance(minute);
+        detect_changes().unwrap();
+        assert_eq!(fake.redirected_urls(), vec!["www.reddit.com/r/rust"]);
+        assert_eq!(
+            fake.focused_window().unwrap().url.as_deref(),
+            Some("https://blocked.example")
+        );
+        match drain(&mut receiver).as_slice() {
+            [AppEvent::BudgetExhausted { .. }, AppEvent::AppBlocked(event)] => {
+                let decision = event.blocked_apps[0].decision.as_ref().unwrap();
+                assert_eq!(
+                    decision.matched.as_ref().unwrap().source,
+                    RuleSource::Budget
+                );
+            }
+            other => panic!("unexpected events {:?}", other),
+        }
+
+        // A new day starts with a fresh budget
+        clock.set(day.succ_opt().unwrap().and_hms_opt(9, 0, 0).unwrap());
+        fake.navigate("old.reddit.com", "Reddit");
+        detect_changes().unwrap();
+        assert_eq!(fake.redirected_urls().len(), 1);
+        clear_budgets();
+        assert!(budget_usage().is_empty());
+    }
+
+    #[test]
+    fn test_unlock_exempts_site_until_expiry() {
+        use crate::platform::{confirm_unlock, request_unlock};
+        use crate::{ManualClock, UnlockFriction};
+        use chrono::NaiveDate;
+
+        let _guard = TEST_LOCK.lock().unwrap_or_else(|e| e.into_inner());
+        let fake = Arc::new(FakeBackend::new());
+        set_backend(fake.clone());
+        let start = NaiveDate::from_ymd_opt(2024, 1, 1)
+            .unwrap()
+            .and_hms_opt(9, 0, 0)
+            .unwrap();
+        let clock = Arc::new(ManualClock::new(start));
+        let monitor = Monitor::builder()
+            .clock(clock.clone())
+            .events(&[crate::EventKind::Unlock, crate::EventKind::AppBlocked])
+            .background_polling(false)
+            .build();
+        let mut receiver = monitor.subscribe();
+        let _handle = start_monitoring(Arc::new(monitor)).unwrap();
+        start_blocking(
+            &[BlockableItem::new("news.example".to_string(), true)],
+            "https://blocked.example",
+            true,
+            &ExceptionPolicy::default(),
+        )
+        .unwrap();
+
+        let id = request_unlock(
+            BlockableItem::new("news.example".to_string(), true),
+            UnlockFriction::Phrase("just this once".to_string()),
+            Duration::from_secs(5 * 60),
+        )
+        .unwrap();
+        assert!(confirm_unlock(id, "whatever").is_err());
+        confirm_unlock(id, "just this once").unwrap();
+
+        fake.focus_window("Browser", "org.browser", "News");
+        fake.navigate("news.example/today", "News");
+        detect_changes().unwrap();
+        assert!(fake.redirected_urls().is_empty());
+
+        clock.advance(Duration::from_secs(5 * 60));
+        detect_changes().unwrap();
+        assert_eq!(fake.redirected_urls(), vec!["news.example/today"]);
+        match drain(&mut receiver).as_slice() {
+            [AppEvent::UnlockRequested { .. }, AppEvent::UnlockGranted { .. }, AppEvent::UnlockExpired { id: expired, .. }, AppEvent::AppBlocked(_)] =>
+            {
+                assert_eq!(*expired, id)
+            }
+            other => panic!("unexpected events {:?}", other),
+        }
+        stop_blocking();
+    }
+
+    #[test]
+    fn test_snapshot_tracks_state() {
+        let _guard = TEST_LOCK.lock().unwrap_or_else(|e| e.into_inner());
+        let fake = Arc::new(FakeBackend::new());
+        set_backend(fake.clone());
+
+        // Blocking started before monitoring is still reported
+        start_blocking(
+            &[BlockableItem::new("news.example".to_string(), true)],
+            "https://blocked.example",
+            true,
+            &ExceptionPolicy::default(),
+        )
+        .unwrap();
+        let monitor = Arc::new(
+            Monitor::builder()
+                .background_polling(false)
+                .disable(crate::EventKind::Window)
+                .idle_threshold(Duration::from_secs(60))
+                .build(),
+        );
+        let _handle = start_monitoring(monitor.clone()).unwrap();
+
+        let snapshot = monitor.snapshot();
+        let blocking = snapshot.blocking.unwrap();
+        assert_eq!(blocking.items().len(), 1);
+        assert_eq!(blocking.redirect_url(), "https://blocked.example");
+        assert!(snapshot.focused_window.is_none());
+        assert!(snapshot.last_keyboard_input.is_none());
+
+        // Focus is tracked even with window events disabled
+        fake.focus_window("Editor", "org.editor", "notes.md");
+        fake.keyboard_burst(1);
+        fake.set_idle_time(Some(Duration::from_secs(90)));
+        detect_changes().unwrap();
+        stop_blocking();
+
+        let snapshot = monitor.snapshot();
+        let focused = snapshot.focused_window.unwrap();
+        assert_eq!(focused.window_title, "notes.md");
+        assert!(snapshot.last_window_change.is_some());
+        assert!(snapshot.last_keyboard_input.is_some());
+        assert!(snapshot.last_mouse_input.is_none());
+        assert!(snapshot.idle_since.is_some());
+        assert!(snapshot.blocking.is_none());
+    }
+
+    #[test]
+    fn test_typewriter_window() {
+        let (_guard, _handle, fake, _receiver) = setup();
+        crate::platform::create_typewriter_window(0.5);
+        assert_eq!(fake.typewriter_opacity(), Some(0.5));
+        crate::platform::remove_typewriter_window();
+        assert_eq!(fake.typewriter_opacity(), None);
+    }
+}
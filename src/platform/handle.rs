@@ -0,0 +1,213 @@
+use super::{monitoring, PlatformBackend};
+use crate::{Monitor, MonitorError};
+use std::sync::mpsc::{self, RecvTimeoutError, Sender};
+use std::sync::Arc;
+use std::thread::JoinHandle;
+
+struct Poller {
+    stop: Sender<()>,
+    thread: JoinHandle<()>,
+}
+
+impl Poller {
+    fn spawn(backend: Arc<dyn PlatformBackend>, monitor: &Monitor) -> Result<Self, MonitorError> {
+        let poll_interval = monitor.config().poll_interval;
+        let (stop, stop_receiver) = mpsc::channel();
+        let thread = std::thread::Builder::new()
+            .name("monitor-poller".into())
+            .spawn(move || {
+                // Stops on an explicit stop and when the handle is dropped
+                while let Err(RecvTimeoutError::Timeout) = stop_receiver.recv_timeout(poll_interval)
+                {
+                    if let Err(e) = monitoring::detect_changes(backend.clone()) {
+                        log::error!("detect_changes failed: {}", e);
+                    }
+                }
+            })
+            .map_err(|e| {
+                MonitorError::PlatformError(format!("Failed to spawn polling thread: {}", e))
+            })?;
+        Ok(Self { stop, thread })
+    }
+
+    fn stop(self) {
+        let _ = self.stop.send(());
+        let _ = self.thread.join();
+    }
+}
+
+/// A running monitor. Owns the backend's input hooks and the thread calling
+/// `detect_changes` every `poll_interval`. Monitoring stops when the handle is
+/// dropped.
+pub struct MonitorHandle {
+    monitor: Arc<Monitor>,
+    backend: Arc<dyn PlatformBackend>,
+    poller: Option<Poller>,
+    running: bool,
+}
+
+impl MonitorHandle {
+    pub(crate) fn new(backend: Arc<dyn PlatformBackend>, monitor: Arc<Monitor>) -> Self {
+        Self {
+            monitor,
+            backend,
+            poller: None,
+            running: false,
+        }
+    }
+
+    /// Starts monitoring again after `stop`. Fails with `AlreadyRunning` if
+    /// this or another handle is running.
+    pub fn start(&mut self) -> Result<(), MonitorError> {
+        if self.running {
+            return Err(MonitorError::AlreadyRunning);
+        }
+        monitoring::start_monitoring(&*self.backend, self.monitor.clone())?;
+
+        if self.monitor.config().background_polling {
+            match Poller::spawn(self.backend.clone(), &self.monitor) {
+                Ok(poller) => self.poller = Some(poller),
+                Err(e) => {
+                    let _ = monitoring::stop_monitoring(&*self.backend);
+                    return Err(e);
+                }
+            }
+        }
+        self.running = true;
+        Ok(())
+    }
+
+    /// Stops polling, removes the input hooks and sends the activity
+    /// gathered since the last flush
+    pub fn stop(&mut self) -> Result<(), MonitorError> {
+        if !self.running {
+            return Err(MonitorError::NotRunning);
+        }
+        self.running = false;
+        if let Some(poller) = self.poller.take() {
+            poller.stop();
+        }
+        monitoring::stop_monitoring(&*self.backend)
+    }
+
+    pub fn is_running(&self) -> bool {
+        self.running
+    }
+
+    pub fn monitor(&self) -> &Arc<Monitor> {
+        &self.monitor
+    }
+}
+
+impl Drop for MonitorHandle {
+    fn drop(&mut self) {
+        if self.running {
+            if let Err(e) = self.stop() {
+                log::error!("Failed to stop monitoring: {}", e);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::platform::fake::FakeBackend;
+    use crate::platform::{set_backend, start_monitoring, TEST_LOCK};
+    use crate::AppEvent;
+    use std::time::Duration;
+
+    fn fake_backend() -> Arc<FakeBackend> {
+        let fake = Arc::new(FakeBackend::new());
+        set_backend(fake.clone());
+        fake
+    }
+
+    #[test]
+    fn test_start_stop_restart() {
+        let _guard = TEST_LOCK.lock().unwrap_or_else(|e| e.into_inner());
+        let fake = fake_backend();
+        let monitor = Arc::new(Monitor::builder().background_polling(false).build());
+
+        let mut handle = start_monitoring(monitor.clone()).unwrap();
+        assert!(handle.is_running());
+        assert!(fake.is_monitoring());
+        assert!(matches!(handle.start(), Err(MonitorError::AlreadyRunning)));
+        assert!(matches!(
+            start_monitoring(monitor.clone()),
+            Err(MonitorError::AlreadyRunning)
+        ));
+
+        handle.stop().unwrap();
+        assert!(!handle.is_running());
+        assert!(!fake.is_monitoring());
+        assert!(matches!(handle.stop(), Err(MonitorError::NotRunning)));
+
+        handle.start().unwrap();
+        assert!(fake.is_monitoring());
+        drop(handle);
+        assert!(!fake.is_monitoring());
+
+        // The global monitor was released, a new handle can start
+        let handle = start_monitoring(monitor).unwrap();
+        assert!(handle.is_running());
+    }
+
+    #[test]
+    fn test_start_blocking_while_starting() {
+        use crate::platform::{start_blocking, stop_blocking};
+        use crate::{BlockableItem, ExceptionPolicy};
+
+        let _guard = TEST_LOCK.lock().unwrap_or_else(|e| e.into_inner());
+        fake_backend();
+        let monitor = Arc::new(Monitor::builder().background_polling(false).build());
+        let items = [BlockableItem::new("com.example.game".to_string(), false)];
+
+        // Used to deadlock when the two took BLOCKING and MONITOR in
+        // opposite orders
+        let (done, finished) = std::sync::mpsc::channel();
+        let blocker = std::thread::spawn(move || {
+            for _ in 0..500 {
+                start_blocking(
+                    &items,
+                    "https://blocked.example",
+                    true,
+                    &ExceptionPolicy::none(),
+                )
+                .unwrap();
+            }
+            let _ = done.send(());
+        });
+        for _ in 0..100 {
+            start_monitoring(monitor.clone()).unwrap().stop().unwrap();
+        }
+        finished
+            .recv_timeout(Duration::from_secs(10))
+            .expect("start_blocking deadlocked");
+        blocker.join().unwrap();
+        stop_blocking();
+    }
+
+    #[test]
+    fn test_background_polling() {
+        let _guard = TEST_LOCK.lock().unwrap_or_else(|e| e.into_inner());
+        let fake = fake_backend();
+        let monitor = Monitor::builder()
+            .poll_interval(Duration::from_millis(10))
+            .build();
+        let mut receiver = monitor.subscribe();
+
+        let mut handle = start_monitoring(Arc::new(monitor)).unwrap();
+        fake.focus_window("Editor", "org.editor", "notes.md");
+        let envelope = receiver.blocking_recv().unwrap();
+        assert!(
+            matches!(envelope.event, AppEvent::Window(event) if event.window_title == "notes.md")
+        );
+
+        handle.stop().unwrap();
+        fake.focus_window("Terminal", "org.terminal", "bash");
+        std::thread::sleep(Duration::from_millis(50));
+        assert!(std::iter::from_fn(|| receiver.try_recv().ok())
+            .all(|envelope| !matches!(envelope.event, AppEvent::Window(_))));
+    }
+}
@@ -0,0 +1,208 @@
+use super::x11::{x11_error, X11Connection};
+use crate::event::MouseEventType;
+use crate::platform::{record_keyboard_event, record_mouse_event};
+use crate::MonitorError;
+use std::thread::JoinHandle;
+use x11rb::connection::Connection;
+use x11rb::protocol::xinput::{self, ConnectionExt as _, XIEventMask};
+use x11rb::protocol::xproto::{
+    AtomEnum, ClientMessageEvent, ConnectionExt as _, CreateWindowAux, EventMask, Window,
+    WindowClass,
+};
+use x11rb::protocol::Event;
+use x11rb::{COPY_DEPTH_FROM_PARENT, COPY_FROM_PARENT};
+
+// XIAllMasterDevices
+const ALL_MASTER_DEVICES: xinput::DeviceId = 1;
+
+/// Maps an X11 core button number to the mouse event type and scroll delta
+/// reported by the native callbacks on other platforms
+fn convert_button(button: u32, pressed: bool) -> Option<(MouseEventType, i32)> {
+    match (button, pressed) {
+        (1, true) => Some((MouseEventType::LeftDown, 0)),
+        (1, false) => Some((MouseEventType::LeftUp, 0)),
+        (2, true) => Some((MouseEventType::MiddleDown, 0)),
+        (2, false) => Some((MouseEventType::MiddleUp, 0)),
+        (3, true) => Some((MouseEventType::RightDown, 0)),
+        (3, false) => Some((MouseEventType::RightUp, 0)),
+        // Buttons 4-7 are emulated scroll wheel clicks, only presses count
+        (4, true) | (6, true) => Some((MouseEventType::Scroll, 1)),
+        (5, true) | (7, true) => Some((MouseEventType::Scroll, -1)),
+        _ => None,
+    }
+}
+
+/// Raw events carry no pointer position, so the listener asks the server for
+/// it on motion and reuses the last known position for button events
+struct InputListener {
+    x11: X11Connection,
+    position: (f64, f64),
+    wake_window: Window,
+}
+
+impl InputListener {
+    fn query_position(&mut self) {
+        let reply = self
+            .x11
+            .conn()
+            .query_pointer(self.x11.root())
+            .ok()
+            .and_then(|cookie| cookie.reply().ok());
+        if let Some(reply) = reply {
+            self.position = (reply.root_x as f64, reply.root_y as f64);
+        }
+    }
+
+    /// Returns false when asked to stop
+    fn handle_event(&mut self, event: Event) -> bool {
+        let (x, y) = self.position;
+        match event {
+            Event::ClientMessage(event) if event.window == self.wake_window => return false,
+            Event::XinputRawKeyPress(event) => record_keyboard_event(event.detail as i32),
+            Event::XinputRawButtonPress(event) => {
+                if let Some((event_type, scroll_delta)) = convert_button(event.detail, true) {
+                    record_mouse_event(x, y, event_type, scroll_delta);
+                }
+            }
+            Event::XinputRawButtonRelease(event) => {
+                if let Some((event_type, scroll_delta)) = convert_button(event.detail, false) {
+                    record_mouse_event(x, y, event_type, scroll_delta);
+                }
+            }
+            Event::XinputRawMotion(_) => {
+                self.query_position();
+                let (x, y) = self.position;
+                record_mouse_event(x, y, MouseEventType::Move, 0);
+            }
+            _ => {}
+        }
+        true
+    }
+}
+
+/// A running input listener thread
+pub struct InputListenerHandle {
+    wake_window: Window,
+    thread: JoinHandle<()>,
+}
+
+impl InputListenerHandle {
+    /// Wakes the listener with a client message on its window and waits for
+    /// the thread to exit. With an empty event mask the message goes to the
+    /// client that created the window, i.e. the listener's connection.
+    pub fn stop(self, x11: &X11Connection) -> Result<(), MonitorError> {
+        let message = ClientMessageEvent::new(32, self.wake_window, AtomEnum::NONE, [0u32; 5]);
+        x11.conn()
+            .send_event(false, self.wake_window, EventMask::NO_EVENT, message)
+            .map_err(x11_error)?;
+        x11.conn().flush().map_err(x11_error)?;
+        self.thread
+            .join()
+            .map_err(|_| MonitorError::PlatformError("X11 input listener panicked".to_string()))
+    }
+}
+
+/// Selects XInput2 raw input events on the root window, which are delivered
+/// regardless of which client has focus, and forwards them to the activity
+/// callbacks from a background thread.
+pub fn start_input_listener() -> Result<InputListenerHandle, MonitorError> {
+    let x11 = X11Connection::connect(None)?;
+    let conn = x11.conn();
+
+    let version = conn
+        .xinput_xi_query_version(2, 0)
+        .map_err(x11_error)?
+        .reply()
+        .map_err(|e| MonitorError::HookInstall {
+            hook: "XInput2 listener",
+            reason: format!("XInput2 unavailable: {}", e),
+        })?;
+    log::trace!(
+        "XInput version {}.{}",
+        version.major_version,
+        version.minor_version
+    );
+
+    conn.xinput_xi_select_events(
+        x11.root(),
+        &[xinput::EventMask {
+            deviceid: ALL_MASTER_DEVICES,
+            mask: vec![
+                XIEventMask::RAW_KEY_PRESS
+                    | XIEventMask::RAW_BUTTON_PRESS
+                    | XIEventMask::RAW_BUTTON_RELEASE
+                    | XIEventMask::RAW_MOTION,
+            ],
+        }],
+    )
+    .map_err(x11_error)?
+    .check()
+    .map_err(x11_error)?;
+
+    // Only used to receive the stop message, destroyed with the connection
+    let wake_window = conn.generate_id().map_err(x11_error)?;
+    conn.create_window(
+        COPY_DEPTH_FROM_PARENT,
+        wake_window,
+        x11.root(),
+        0,
+        0,
+        1,
+        1,
+        0,
+        WindowClass::INPUT_ONLY,
+        COPY_FROM_PARENT,
+        &CreateWindowAux::new(),
+    )
+    .map_err(x11_error)?
+    .check()
+    .map_err(x11_error)?;
+
+    let mut listener = InputListener {
+        x11,
+        position: (0.0, 0.0),
+        wake_window,
+    };
+    listener.query_position();
+
+    let thread = std::thread::Builder::new()
+        .name("x11-input-listener".into())
+        .spawn(move || loop {
+            match listener.x11.conn().wait_for_event() {
+                Ok(event) => {
+                    if !listener.handle_event(event) {
+                        log::trace!("X11 input listener stopped");
+                        break;
+                    }
+                }
+                Err(e) => {
+                    log::error!("X11 input listener stopped: {}", e);
+                    break;
+                }
+            }
+        })
+        .map_err(|e| MonitorError::HookInstall {
+            hook: "XInput2 listener",
+            reason: format!("failed to spawn thread: {}", e),
+        })?;
+
+    Ok(InputListenerHandle {
+        wake_window,
+        thread,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_convert_button() {
+        assert_eq!(convert_button(1, true), Some((MouseEventType::LeftDown, 0)));
+        assert_eq!(convert_button(3, false), Some((MouseEventType::RightUp, 0)));
+        assert_eq!(convert_button(4, true), Some((MouseEventType::Scroll, 1)));
+        assert_eq!(convert_button(5, true), Some((MouseEventType::Scroll, -1)));
+        assert_eq!(convert_button(5, false), None);
+        assert_eq!(convert_button(9, true), None);
+    }
+}
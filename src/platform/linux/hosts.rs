@@ -0,0 +1,234 @@
+use crate::MonitorError;
+use std::io::ErrorKind;
+use std::path::{Path, PathBuf};
+
+pub const HOSTS_PATH: &str = "/etc/hosts";
+
+const BEGIN_MARKER: &str = "# BEGIN os-monitor blocked sites";
+const END_MARKER: &str = "# END os-monitor blocked sites";
+
+/// Blocks sites by resolving them to an unroutable address in a delimited
+/// section of the hosts file. Everything outside the section is left as it
+/// was, so removing the section restores the original file.
+#[derive(Debug, Clone)]
+pub struct HostsFile {
+    path: PathBuf,
+}
+
+/// `OS_MONITOR_HOSTS_FILE` overrides the path, e.g. to try blocking without
+/// root
+impl Default for HostsFile {
+    fn default() -> Self {
+        let path = std::env::var_os("OS_MONITOR_HOSTS_FILE").unwrap_or_else(|| HOSTS_PATH.into());
+        Self::new(path)
+    }
+}
+
+fn hosts_error(path: &Path, e: std::io::Error) -> MonitorError {
+    match e.kind() {
+        ErrorKind::PermissionDenied => MonitorError::PermissionDenied(format!(
+            "writing {} requires root: {}",
+            path.display(),
+            e
+        )),
+        _ => MonitorError::PlatformError(format!("{}: {}", path.display(), e)),
+    }
+}
+
+/// The file's lines outside our section. An unterminated section, e.g. from
+/// a write that was cut off, runs to the end of the file.
+fn without_section(contents: &str) -> Vec<&str> {
+    let mut in_section = false;
+    contents
+        .lines()
+        .filter(|line| match line.trim() {
+            BEGIN_MARKER => {
+                in_section = true;
+                false
+            }
+            END_MARKER if in_section => {
+                in_section = false;
+                false
+            }
+            _ => !in_section,
+        })
+        .collect()
+}
+
+fn section(hosts: &[String]) -> Vec<String> {
+    let mut lines = vec![BEGIN_MARKER.to_string()];
+    for host in hosts {
+        lines.push(format!("0.0.0.0 {}", host));
+        lines.push(format!(":: {}", host));
+    }
+    lines.push(END_MARKER.to_string());
+    lines
+}
+
+impl HostsFile {
+    pub fn new(path: impl Into<PathBuf>) -> Self {
+        Self { path: path.into() }
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    fn read(&self) -> Result<String, MonitorError> {
+        match std::fs::read_to_string(&self.path) {
+            Ok(contents) => Ok(contents),
+            Err(e) if e.kind() == ErrorKind::NotFound => Ok(String::new()),
+            Err(e) => Err(hosts_error(&self.path, e)),
+        }
+    }
+
+    /// Replaces the file through a temporary file next to it so a crash never
+    /// leaves it half written. Falls back to writing in place where the file
+    /// can't be replaced, e.g. a container's bind mounted /etc/hosts.
+    fn write(&self, contents: &str) -> Result<(), MonitorError> {
+        let file_name = self
+            .path
+            .file_name()
+            .map(|name| name.to_string_lossy().to_string())
+            .unwrap_or_default();
+        let temp_path = self
+            .path
+            .with_file_name(format!(".{}.os-monitor", file_name));
+        let replaced = std::fs::write(&temp_path, contents)
+            .and_then(|_| {
+                if let Ok(metadata) = std::fs::metadata(&self.path) {
+                    std::fs::set_permissions(&temp_path, metadata.permissions())?;
+                }
+                std::fs::rename(&temp_path, &self.path)
+            })
+            .map_err(|e| {
+                let _ = std::fs::remove_file(&temp_path);
+                log::debug!("Replacing {} failed: {}", self.path.display(), e);
+            });
+        if replaced.is_err() {
+            std::fs::write(&self.path, contents).map_err(|e| hosts_error(&self.path, e))?;
+        }
+        Ok(())
+    }
+
+    fn update(&self, hosts: &[String]) -> Result<(), MonitorError> {
+        let original = self.read()?;
+        let mut lines: Vec<String> = without_section(&original)
+            .into_iter()
+            .map(str::to_string)
+            .collect();
+        if !hosts.is_empty() {
+            lines.extend(section(hosts));
+        }
+        let mut contents = lines.join("\n");
+        if !contents.is_empty() {
+            contents.push('\n');
+        }
+        if contents == original {
+            return Ok(());
+        }
+        self.write(&contents)
+    }
+
+    /// Writes the section for `hosts`, replacing the previous one. Writing
+    /// the same hosts again leaves the file untouched.
+    pub fn block(&self, hosts: &[String]) -> Result<(), MonitorError> {
+        self.update(hosts)
+    }
+
+    /// Removes the section if there is one
+    pub fn unblock(&self) -> Result<(), MonitorError> {
+        self.update(&[])
+    }
+
+    pub fn has_section(&self) -> bool {
+        self.read()
+            .is_ok_and(|contents| contents.lines().any(|line| line.trim() == BEGIN_MARKER))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const ORIGINAL: &str = "127.0.0.1 localhost\n::1 localhost\n\n# custom\n10.0.0.2 nas\n";
+
+    /// Removes a test's directory when the test ends
+    struct TempDir(PathBuf);
+
+    impl Drop for TempDir {
+        fn drop(&mut self) {
+            let _ = std::fs::remove_dir_all(&self.0);
+        }
+    }
+
+    /// In a directory of its own, for the temporary file next to it
+    fn temp_hosts(name: &str) -> (HostsFile, TempDir) {
+        let dir = std::env::temp_dir().join(format!("os-monitor-{}-{}", name, std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let hosts = HostsFile::new(dir.join("hosts"));
+        std::fs::write(hosts.path(), ORIGINAL).unwrap();
+        (hosts, TempDir(dir))
+    }
+
+    fn hosts(names: &[&str]) -> Vec<String> {
+        names.iter().map(|name| name.to_string()).collect()
+    }
+
+    fn contents(hosts: &HostsFile) -> String {
+        std::fs::read_to_string(hosts.path()).unwrap()
+    }
+
+    #[test]
+    fn test_block_and_unblock() {
+        let (file, _dir) = temp_hosts("hosts");
+        file.block(&hosts(&["reddit.com", "www.reddit.com"]))
+            .unwrap();
+        let blocked = contents(&file);
+        assert!(blocked.starts_with(ORIGINAL));
+        assert!(blocked.contains("0.0.0.0 reddit.com\n:: reddit.com\n"));
+        assert!(blocked.contains("0.0.0.0 www.reddit.com\n"));
+        assert!(file.has_section());
+
+        // Idempotent, and a new list replaces the old section
+        file.block(&hosts(&["reddit.com", "www.reddit.com"]))
+            .unwrap();
+        assert_eq!(contents(&file), blocked);
+        file.block(&hosts(&["news.example"])).unwrap();
+        let replaced = contents(&file);
+        assert!(!replaced.contains("reddit.com"));
+        assert_eq!(replaced.matches(BEGIN_MARKER).count(), 1);
+
+        file.unblock().unwrap();
+        assert_eq!(contents(&file), ORIGINAL);
+        assert!(!file.has_section());
+        file.unblock().unwrap();
+        assert_eq!(contents(&file), ORIGINAL);
+    }
+
+    #[test]
+    fn test_unblock_recovers_cut_off_section() {
+        let (file, _dir) = temp_hosts("hosts-cut-off");
+        std::fs::write(
+            file.path(),
+            format!(
+                "{}{}\n0.0.0.0 reddit.com\n0.0.0.0 www.red",
+                ORIGINAL, BEGIN_MARKER
+            ),
+        )
+        .unwrap();
+        assert!(file.has_section());
+        file.unblock().unwrap();
+        assert_eq!(contents(&file), ORIGINAL);
+    }
+
+    #[test]
+    fn test_missing_file() {
+        let (file, _dir) = temp_hosts("hosts-missing");
+        std::fs::remove_file(file.path()).unwrap();
+        file.unblock().unwrap();
+        assert!(!file.path().exists());
+        file.block(&hosts(&["reddit.com"])).unwrap();
+        assert!(contents(&file).starts_with(BEGIN_MARKER));
+    }
+}
@@ -0,0 +1,184 @@
+use super::x11::{x11_error, X11Connection};
+use crate::MonitorError;
+use std::time::Duration;
+use x11rb::protocol::screensaver::ConnectionExt as _;
+use zbus::blocking::Connection;
+
+/// D-Bus services that report the session idle time, in the order they are
+/// tried: (destination, path, interface, method)
+const IDLE_SERVICES: [(&str, &str, &str, &str); 2] = [
+    (
+        "org.gnome.Mutter.IdleMonitor",
+        "/org/gnome/Mutter/IdleMonitor/Core",
+        "org.gnome.Mutter.IdleMonitor",
+        "GetIdletime",
+    ),
+    (
+        "org.freedesktop.ScreenSaver",
+        "/org/freedesktop/ScreenSaver",
+        "org.freedesktop.ScreenSaver",
+        "GetSessionIdleTime",
+    ),
+];
+
+/// Time since the last input anywhere on the X server, from the
+/// MIT-SCREEN-SAVER extension
+pub fn xscreensaver_idle_time(x11: &X11Connection) -> Result<Duration, MonitorError> {
+    let info = x11
+        .conn()
+        .screensaver_query_info(x11.root())
+        .map_err(x11_error)?
+        .reply()
+        .map_err(x11_error)?;
+    Ok(Duration::from_millis(info.ms_since_user_input as u64))
+}
+
+fn dbus_error(e: zbus::Error) -> MonitorError {
+    MonitorError::PlatformError(format!("D-Bus error: {}", e))
+}
+
+/// Reads the idle time from the compositor over the session bus. Used on
+/// Wayland, where XScreenSaver only sees input to XWayland clients.
+pub struct DbusIdleMonitor {
+    connection: Connection,
+}
+
+impl DbusIdleMonitor {
+    pub fn session() -> Result<Self, MonitorError> {
+        let connection = Connection::session().map_err(|e| MonitorError::BackendUnavailable {
+            backend: "D-Bus",
+            reason: e.to_string(),
+        })?;
+        Ok(Self::new(connection))
+    }
+
+    pub fn new(connection: Connection) -> Self {
+        Self { connection }
+    }
+
+    /// Asks Mutter's IdleMonitor, then org.freedesktop.ScreenSaver. Both
+    /// report milliseconds.
+    pub fn idle_time(&self) -> Result<Duration, MonitorError> {
+        let mut last_error = None;
+        for (destination, path, interface, method) in IDLE_SERVICES {
+            let reply =
+                self.connection
+                    .call_method(Some(destination), path, Some(interface), method, &());
+            let millis = match reply {
+                // Mutter returns a u64, ScreenSaver a u32
+                Ok(reply) => reply
+                    .body()
+                    .deserialize::<u64>()
+                    .or_else(|_| reply.body().deserialize::<u32>().map(u64::from)),
+                Err(e) => {
+                    last_error = Some(e);
+                    continue;
+                }
+            };
+            match millis {
+                Ok(millis) => return Ok(Duration::from_millis(millis)),
+                Err(e) => last_error = Some(e),
+            }
+        }
+        Err(last_error
+            .map(dbus_error)
+            .unwrap_or(MonitorError::Unsupported("idle time")))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::{BufRead, BufReader};
+    use std::process::{Child, Command, Stdio};
+
+    /// A private dbus-daemon, killed on drop
+    struct PrivateBus {
+        daemon: Child,
+        address: String,
+    }
+
+    impl PrivateBus {
+        fn start() -> Option<Self> {
+            let mut daemon = Command::new("dbus-daemon")
+                .args(["--session", "--nofork", "--print-address"])
+                .stdout(Stdio::piped())
+                .stderr(Stdio::null())
+                .spawn()
+                .ok()?;
+            let mut address = String::new();
+            BufReader::new(daemon.stdout.take()?)
+                .read_line(&mut address)
+                .ok()?;
+            Some(Self {
+                daemon,
+                address: address.trim().to_string(),
+            })
+        }
+
+        fn connect(&self) -> Connection {
+            zbus::blocking::connection::Builder::address(self.address.as_str())
+                .unwrap()
+                .build()
+                .unwrap()
+        }
+    }
+
+    impl Drop for PrivateBus {
+        fn drop(&mut self) {
+            let _ = self.daemon.kill();
+            let _ = self.daemon.wait();
+        }
+    }
+
+    struct ScreenSaver;
+
+    #[zbus::interface(name = "org.freedesktop.ScreenSaver")]
+    impl ScreenSaver {
+        #[zbus(name = "GetSessionIdleTime")]
+        fn get_session_idle_time(&self) -> u32 {
+            90_000
+        }
+    }
+
+    struct MutterIdleMonitor;
+
+    #[zbus::interface(name = "org.gnome.Mutter.IdleMonitor")]
+    impl MutterIdleMonitor {
+        #[zbus(name = "GetIdletime")]
+        fn get_idletime(&self) -> u64 {
+            1_500
+        }
+    }
+
+    #[test]
+    fn test_dbus_idle_time() {
+        let Some(bus) = PrivateBus::start() else {
+            eprintln!("dbus-daemon not available, skipping");
+            return;
+        };
+
+        let client = DbusIdleMonitor::new(bus.connect());
+        assert!(client.idle_time().is_err());
+
+        // Only the freedesktop service is running
+        let screensaver = bus.connect();
+        screensaver
+            .object_server()
+            .at("/org/freedesktop/ScreenSaver", ScreenSaver)
+            .unwrap();
+        screensaver
+            .request_name("org.freedesktop.ScreenSaver")
+            .unwrap();
+        assert_eq!(client.idle_time().unwrap(), Duration::from_secs(90));
+
+        // Mutter takes precedence when present
+        let mutter = bus.connect();
+        mutter
+            .object_server()
+            .at("/org/gnome/Mutter/IdleMonitor/Core", MutterIdleMonitor)
+            .unwrap();
+        mutter.request_name("org.gnome.Mutter.IdleMonitor").unwrap();
+        assert_eq!(client.idle_time().unwrap(), Duration::from_millis(1_500));
+    }
+}
@@ -0,0 +1,87 @@
+mod events;
+mod hosts;
+mod idle;
+mod process;
+mod sway;
+mod x11;
+
+use crate::platform::PlatformBackend;
+use std::sync::Arc;
+
+pub use sway::SwayBackend;
+pub use x11::X11Backend;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum BackendKind {
+    X11,
+    Sway,
+    #[cfg(any(test, feature = "testing"))]
+    Fake,
+}
+
+/// `OS_MONITOR_BACKEND` forces a backend (`x11`, `sway`, or `fake` with the
+/// `testing` feature), otherwise sway is used whenever its IPC socket is
+/// advertised in the environment
+fn backend_kind(requested: Option<&str>, has_sway_socket: bool) -> BackendKind {
+    match requested {
+        Some("x11") => BackendKind::X11,
+        #[cfg(any(test, feature = "testing"))]
+        Some("fake") => BackendKind::Fake,
+        Some("sway") if has_sway_socket => BackendKind::Sway,
+        Some("sway") => {
+            log::warn!("sway backend requested but SWAYSOCK/I3SOCK is not set, using X11");
+            BackendKind::X11
+        }
+        Some(other) => {
+            log::warn!("Unknown OS_MONITOR_BACKEND {:?}, detecting backend", other);
+            backend_kind(None, has_sway_socket)
+        }
+        None if has_sway_socket => BackendKind::Sway,
+        None => BackendKind::X11,
+    }
+}
+
+pub fn select_backend() -> Arc<dyn PlatformBackend> {
+    let requested = std::env::var("OS_MONITOR_BACKEND").ok();
+    let sway_socket = sway::socket_path();
+
+    match backend_kind(requested.as_deref(), sway_socket.is_some()) {
+        BackendKind::Sway => {
+            let socket_path = sway_socket.unwrap();
+            log::info!("Using sway IPC backend: {}", socket_path.display());
+            SwayBackend::new(socket_path)
+        }
+        BackendKind::X11 => {
+            log::info!("Using X11 backend");
+            Arc::new(X11Backend::default())
+        }
+        #[cfg(any(test, feature = "testing"))]
+        BackendKind::Fake => {
+            log::info!("Using fake backend");
+            Arc::new(crate::platform::fake::FakeBackend::new())
+        }
+    }
+}
+
+fn process_name(pid: u32) -> Option<String> {
+    std::fs::read_to_string(format!("/proc/{}/comm", pid))
+        .ok()
+        .map(|name| name.trim().to_string())
+        .filter(|name| !name.is_empty())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_backend_kind() {
+        assert_eq!(backend_kind(None, false), BackendKind::X11);
+        assert_eq!(backend_kind(None, true), BackendKind::Sway);
+        assert_eq!(backend_kind(Some("x11"), true), BackendKind::X11);
+        assert_eq!(backend_kind(Some("sway"), true), BackendKind::Sway);
+        assert_eq!(backend_kind(Some("sway"), false), BackendKind::X11);
+        assert_eq!(backend_kind(Some("bogus"), true), BackendKind::Sway);
+        assert_eq!(backend_kind(Some("fake"), true), BackendKind::Fake);
+    }
+}
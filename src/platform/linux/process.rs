@@ -0,0 +1,227 @@
+use once_cell::sync::Lazy;
+use std::collections::HashSet;
+use std::path::Path;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+/// How long a blocked app gets to exit after SIGTERM before it is killed
+pub const TERMINATE_GRACE: Duration = Duration::from_secs(3);
+
+const POLL_INTERVAL: Duration = Duration::from_millis(20);
+
+// Pids sent SIGTERM whose grace period hasn't ended yet
+static TERMINATING: Lazy<Mutex<HashSet<u32>>> = Lazy::new(|| Mutex::new(HashSet::new()));
+
+/// Environment variables GLib and Unity launchers set to the `.desktop`
+/// file an app was started from
+const DESKTOP_FILE_VARS: [&str; 2] = ["GIO_LAUNCHED_DESKTOP_FILE", "BAMF_DESKTOP_FILE_HINT"];
+
+fn file_name(path: &str) -> Option<String> {
+    Path::new(path)
+        .file_name()
+        .map(|name| name.to_string_lossy().to_string())
+        .filter(|name| !name.is_empty())
+}
+
+/// The executable's file name. A binary replaced by an update reads as
+/// `/usr/bin/app (deleted)`.
+fn executable(pid: u32) -> Option<String> {
+    let exe = std::fs::read_link(format!("/proc/{}/exe", pid)).ok()?;
+    let exe = exe.to_string_lossy();
+    file_name(exe.strip_suffix(" (deleted)").unwrap_or(&exe))
+}
+
+/// argv[0]'s file name, which differs from the executable for wrappers
+/// and multi-call binaries
+fn command(pid: u32) -> Option<String> {
+    let cmdline = std::fs::read(format!("/proc/{}/cmdline", pid)).ok()?;
+    let argv0 = cmdline.split(|byte| *byte == 0).next()?;
+    file_name(&String::from_utf8_lossy(argv0))
+}
+
+/// The `.desktop` id without its extension, e.g. `org.gnome.Nautilus`, from
+/// the launcher's environment or the Flatpak sandbox's metadata
+fn desktop_id(pid: u32) -> Option<String> {
+    let environ = std::fs::read(format!("/proc/{}/environ", pid)).unwrap_or_default();
+    let from_environ = environ.split(|byte| *byte == 0).find_map(|entry| {
+        let entry = String::from_utf8_lossy(entry);
+        let (name, value) = entry.split_once('=')?;
+        DESKTOP_FILE_VARS
+            .contains(&name)
+            .then(|| file_name(value))
+            .flatten()
+    });
+    let desktop_file = from_environ.or_else(|| {
+        let info = std::fs::read_to_string(format!("/proc/{}/root/.flatpak-info", pid)).ok()?;
+        info.lines()
+            .find_map(|line| line.strip_prefix("name="))
+            .map(|name| name.trim().to_string())
+    })?;
+    let id = desktop_file
+        .strip_suffix(".desktop")
+        .unwrap_or(&desktop_file);
+    Some(id.to_string()).filter(|id| !id.is_empty())
+}
+
+/// Identifiers the process can be blocked by besides its window's class:
+/// the `.desktop` id, the executable and argv[0], without duplicates
+pub fn app_ids(pid: u32) -> Vec<String> {
+    let mut ids: Vec<String> = Vec::new();
+    for id in [desktop_id(pid), executable(pid), command(pid)]
+        .into_iter()
+        .flatten()
+    {
+        if !ids.contains(&id) {
+            ids.push(id);
+        }
+    }
+    ids
+}
+
+/// The process state and start time from `/proc/<pid>/stat`. The start time
+/// tells a reused pid apart from the process that was signalled.
+fn stat(pid: u32) -> Option<(char, u64)> {
+    let stat = std::fs::read_to_string(format!("/proc/{}/stat", pid)).ok()?;
+    // The command name in parentheses may contain spaces and parentheses
+    let mut fields = stat.get(stat.rfind(')')? + 1..)?.split_whitespace();
+    let state = fields.next()?.chars().next()?;
+    // starttime is field 22, the state is field 3
+    let start_time = fields.nth(18)?.parse().ok()?;
+    Some((state, start_time))
+}
+
+/// Whether the process started at `start_time` is still running. Zombies
+/// count as exited since they are only waiting for their parent to reap them.
+fn is_running(pid: u32, start_time: u64) -> bool {
+    matches!(stat(pid), Some((state, started)) if started == start_time && !matches!(state, 'Z' | 'X'))
+}
+
+/// Polls until the process exits or `timeout` passes. Returns true if it
+/// exited.
+fn wait_for_exit(pid: u32, start_time: u64, timeout: Duration) -> bool {
+    let deadline = Instant::now() + timeout;
+    while is_running(pid, start_time) {
+        if Instant::now() >= deadline {
+            return false;
+        }
+        std::thread::sleep(POLL_INTERVAL);
+    }
+    true
+}
+
+fn signal(pid: u32, signal: libc::c_int) -> bool {
+    let Ok(pid) = libc::pid_t::try_from(pid) else {
+        return false;
+    };
+    // SAFETY: kill has no memory safety requirements
+    let result = unsafe { libc::kill(pid, signal) };
+    if result != 0 {
+        log::warn!(
+            "Failed to send signal {} to {}: {}",
+            signal,
+            pid,
+            std::io::Error::last_os_error()
+        );
+    }
+    result == 0
+}
+
+/// Sends SIGTERM and, from a background thread, SIGKILL if the process is
+/// still running after `grace`. Returns false if SIGTERM could not be sent,
+/// e.g. the process belongs to another user, or the process is already being
+/// terminated. Never signals init or the monitor itself.
+pub fn terminate(pid: u32, grace: Duration) -> bool {
+    if pid <= 1 || pid == std::process::id() {
+        log::warn!("Refusing to terminate pid {}", pid);
+        return false;
+    }
+    let Some((_, start_time)) = stat(pid) else {
+        return false;
+    };
+    let mut terminating = TERMINATING.lock().unwrap();
+    if terminating.contains(&pid) {
+        log::debug!("{} is already being terminated", pid);
+        return false;
+    }
+    if !signal(pid, libc::SIGTERM) {
+        return false;
+    }
+    terminating.insert(pid);
+    drop(terminating);
+
+    let spawned = std::thread::Builder::new()
+        .name("os-monitor-terminate".to_string())
+        .spawn(move || {
+            if !wait_for_exit(pid, start_time, grace) {
+                log::info!("{} ignored SIGTERM for {:?}, sending SIGKILL", pid, grace);
+                signal(pid, libc::SIGKILL);
+            }
+            TERMINATING.lock().unwrap().remove(&pid);
+        });
+    if let Err(e) = spawned {
+        log::error!("Failed to spawn thread to kill {}: {}", pid, e);
+        TERMINATING.lock().unwrap().remove(&pid);
+    }
+    true
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::process::{Child, Command};
+
+    fn spawn(script: &str) -> Child {
+        let child = Command::new("sh").args(["-c", script]).spawn().unwrap();
+        // Give sh time to exec and install its traps
+        std::thread::sleep(Duration::from_millis(100));
+        child
+    }
+
+    fn wait_for(child: &mut Child, timeout: Duration) -> Option<std::process::ExitStatus> {
+        let deadline = Instant::now() + timeout;
+        while Instant::now() < deadline {
+            if let Some(status) = child.try_wait().unwrap() {
+                return Some(status);
+            }
+            std::thread::sleep(POLL_INTERVAL);
+        }
+        None
+    }
+
+    #[test]
+    fn test_app_ids() {
+        let mut child = spawn("exec sleep 30");
+        let ids = app_ids(child.id());
+        assert!(ids.contains(&"sleep".to_string()), "{:?}", ids);
+        assert_eq!(ids.iter().filter(|id| *id == "sleep").count(), 1);
+        child.kill().unwrap();
+        child.wait().unwrap();
+        assert!(app_ids(child.id()).is_empty());
+    }
+
+    #[test]
+    fn test_terminate() {
+        use std::os::unix::process::ExitStatusExt;
+
+        let mut child = spawn("exec sleep 30");
+        assert!(terminate(child.id(), Duration::from_secs(5)));
+        let status = wait_for(&mut child, Duration::from_secs(2)).unwrap();
+        assert_eq!(status.signal(), Some(libc::SIGTERM));
+
+        assert!(!terminate(std::process::id(), Duration::ZERO));
+        assert!(!terminate(1, Duration::ZERO));
+    }
+
+    #[test]
+    fn test_terminate_escalates_to_kill() {
+        use std::os::unix::process::ExitStatusExt;
+
+        let mut child = spawn("trap '' TERM; while true; do sleep 1; done");
+        assert!(terminate(child.id(), Duration::from_millis(300)));
+        assert!(wait_for(&mut child, Duration::from_millis(150)).is_none());
+        // Still in its grace period, so it isn't signalled again
+        assert!(!terminate(child.id(), Duration::from_millis(300)));
+        let status = wait_for(&mut child, Duration::from_secs(3)).unwrap();
+        assert_eq!(status.signal(), Some(libc::SIGKILL));
+    }
+}
@@ -0,0 +1,814 @@
+use super::idle::DbusIdleMonitor;
+use super::process_name;
+use super::x11::X11Backend;
+use crate::platform::{handle_focused_window, FocusedWindow, PlatformBackend};
+use crate::{BlockableItem, MonitorError, Platform};
+use serde::Deserialize;
+use std::io::{Read, Write};
+use std::net::Shutdown;
+use std::os::unix::net::UnixStream;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Condvar, Mutex, OnceLock, Weak};
+use std::thread::JoinHandle;
+use std::time::Duration;
+
+// i3/sway IPC framing: magic, payload length and message type (native endian)
+const MAGIC: &[u8; 6] = b"i3-ipc";
+const HEADER_LEN: usize = MAGIC.len() + 8;
+
+const MESSAGE_SUBSCRIBE: u32 = 2;
+const MESSAGE_GET_TREE: u32 = 4;
+const EVENT_WINDOW: u32 = 0x8000_0003;
+
+// Backoff between attempts to reconnect after the IPC connection is lost,
+// e.g. when sway restarts
+const RECONNECT_MIN_DELAY: Duration = Duration::from_secs(1);
+const RECONNECT_MAX_DELAY: Duration = Duration::from_secs(30);
+
+#[derive(Debug, Deserialize)]
+struct WindowProperties {
+    class: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+struct Node {
+    name: Option<String>,
+    app_id: Option<String>,
+    pid: Option<u32>,
+    #[serde(default)]
+    focused: bool,
+    window_properties: Option<WindowProperties>,
+    #[serde(default)]
+    nodes: Vec<Node>,
+    #[serde(default)]
+    floating_nodes: Vec<Node>,
+}
+
+impl Node {
+    /// Native Wayland clients have an app_id, XWayland clients only have an
+    /// X11 class
+    fn identifier(&self) -> Option<String> {
+        self.app_id.clone().or_else(|| {
+            self.window_properties
+                .as_ref()
+                .and_then(|properties| properties.class.clone())
+        })
+    }
+
+    fn to_focused_window(&self) -> FocusedWindow {
+        let bundle_id = self.identifier();
+        let app_name = bundle_id
+            .clone()
+            .or_else(|| self.pid.and_then(process_name))
+            .unwrap_or_else(|| String::from("Unknown"));
+
+        FocusedWindow {
+            app_name,
+            title: self.name.clone().unwrap_or_default(),
+            bundle_id,
+            url: None,
+            pid: self.pid,
+        }
+    }
+
+    /// Application windows in the tree: leaves with a pid or an identifier,
+    /// which leaves out empty workspaces
+    fn windows(&self) -> Vec<&Node> {
+        let children: Vec<&Node> = self
+            .nodes
+            .iter()
+            .chain(self.floating_nodes.iter())
+            .collect();
+        if children.is_empty() {
+            let is_window = self.pid.is_some() || self.identifier().is_some();
+            return if is_window { vec![self] } else { Vec::new() };
+        }
+        children.into_iter().flat_map(Node::windows).collect()
+    }
+
+    fn find_focused(&self) -> Option<&Node> {
+        if self.focused {
+            return Some(self);
+        }
+        self.nodes
+            .iter()
+            .chain(self.floating_nodes.iter())
+            .find_map(|node| node.find_focused())
+    }
+}
+
+#[derive(Debug, Deserialize)]
+struct WindowChange {
+    change: String,
+    container: Node,
+}
+
+#[derive(Debug, Deserialize)]
+struct CommandResult {
+    success: bool,
+}
+
+/// Location of the compositor IPC socket, if running under sway or i3
+pub fn socket_path() -> Option<PathBuf> {
+    ["SWAYSOCK", "I3SOCK"]
+        .iter()
+        .filter_map(std::env::var_os)
+        .map(PathBuf::from)
+        .find(|path| !path.as_os_str().is_empty())
+}
+
+fn ipc_error<E: std::fmt::Display>(e: E) -> MonitorError {
+    MonitorError::PlatformError(format!("sway IPC error: {}", e))
+}
+
+struct SwayConnection {
+    stream: UnixStream,
+}
+
+impl SwayConnection {
+    fn connect(path: &Path) -> Result<Self, MonitorError> {
+        let stream = UnixStream::connect(path).map_err(|e| MonitorError::BackendUnavailable {
+            backend: "sway",
+            reason: format!("{}: {}", path.display(), e),
+        })?;
+        Ok(Self { stream })
+    }
+
+    fn send(&mut self, message_type: u32, payload: &[u8]) -> Result<(), MonitorError> {
+        let mut message = Vec::with_capacity(HEADER_LEN + payload.len());
+        message.extend_from_slice(MAGIC);
+        message.extend_from_slice(&(payload.len() as u32).to_ne_bytes());
+        message.extend_from_slice(&message_type.to_ne_bytes());
+        message.extend_from_slice(payload);
+        self.stream.write_all(&message).map_err(ipc_error)
+    }
+
+    fn read(&mut self) -> Result<(u32, Vec<u8>), MonitorError> {
+        let mut header = [0u8; HEADER_LEN];
+        self.stream.read_exact(&mut header).map_err(ipc_error)?;
+        if &header[..MAGIC.len()] != MAGIC {
+            return Err(ipc_error("invalid magic"));
+        }
+
+        let length = u32::from_ne_bytes(header[6..10].try_into().unwrap()) as usize;
+        let message_type = u32::from_ne_bytes(header[10..14].try_into().unwrap());
+        let mut payload = vec![0u8; length];
+        self.stream.read_exact(&mut payload).map_err(ipc_error)?;
+        Ok((message_type, payload))
+    }
+
+    fn request(&mut self, message_type: u32, payload: &[u8]) -> Result<Vec<u8>, MonitorError> {
+        self.send(message_type, payload)?;
+        let (reply_type, reply) = self.read()?;
+        if reply_type != message_type {
+            return Err(ipc_error(format!("unexpected reply type {}", reply_type)));
+        }
+        Ok(reply)
+    }
+
+    fn focused_window(&mut self) -> Result<Option<FocusedWindow>, MonitorError> {
+        let tree = self.request(MESSAGE_GET_TREE, b"")?;
+        let tree: Node = serde_json::from_slice(&tree).map_err(ipc_error)?;
+        Ok(tree.find_focused().map(Node::to_focused_window))
+    }
+
+    /// One window per app, told apart by pid and identifier
+    fn running_apps(&mut self) -> Result<Vec<FocusedWindow>, MonitorError> {
+        let tree = self.request(MESSAGE_GET_TREE, b"")?;
+        let tree: Node = serde_json::from_slice(&tree).map_err(ipc_error)?;
+        let mut apps: Vec<FocusedWindow> = Vec::new();
+        for window in tree.windows() {
+            let window = window.to_focused_window();
+            let seen = apps
+                .iter()
+                .any(|app| (app.pid, &app.bundle_id) == (window.pid, &window.bundle_id));
+            if !seen {
+                apps.push(window);
+            }
+        }
+        Ok(apps)
+    }
+
+    fn subscribe_window_events(&mut self) -> Result<(), MonitorError> {
+        let reply = self.request(MESSAGE_SUBSCRIBE, br#"["window"]"#)?;
+        let result: CommandResult = serde_json::from_slice(&reply).map_err(ipc_error)?;
+        if !result.success {
+            return Err(ipc_error("subscribe to window events rejected"));
+        }
+        Ok(())
+    }
+}
+
+/// Returns the focused window for focus changes and for title changes of the
+/// focused window. Other window events (new, close, move, ...) are ignored.
+fn parse_window_event(payload: &[u8]) -> Result<Option<FocusedWindow>, MonitorError> {
+    let event: WindowChange = serde_json::from_slice(payload).map_err(ipc_error)?;
+    let is_focus_change = match event.change.as_str() {
+        "focus" => true,
+        "title" => event.container.focused,
+        _ => false,
+    };
+    Ok(is_focus_change.then(|| event.container.to_focused_window()))
+}
+
+/// Lets another thread end a running `listen` call by shutting down the
+/// socket it is reading from
+#[derive(Default)]
+pub struct ListenerControl {
+    state: Mutex<ListenerState>,
+    stopped: Condvar,
+}
+
+#[derive(Default)]
+struct ListenerState {
+    stopped: bool,
+    // The connection `listen` is waiting on, the initial GET_TREE one or the
+    // subscription
+    stream: Option<UnixStream>,
+    subscribed: bool,
+}
+
+impl ListenerControl {
+    pub fn stop(&self) {
+        let mut state = self.state.lock().unwrap();
+        state.stopped = true;
+        if let Some(stream) = state.stream.take() {
+            let _ = stream.shutdown(Shutdown::Both);
+        }
+        self.stopped.notify_all();
+    }
+
+    fn is_stopped(&self) -> bool {
+        self.state.lock().unwrap().stopped
+    }
+
+    /// Whether window events are currently being received
+    pub fn is_connected(&self) -> bool {
+        self.state.lock().unwrap().subscribed
+    }
+
+    /// Forgets the socket after `listen` returned. Returns whether it had
+    /// subscribed.
+    fn disconnected(&self) -> bool {
+        let mut state = self.state.lock().unwrap();
+        state.stream = None;
+        std::mem::take(&mut state.subscribed)
+    }
+
+    /// Sleeps for `timeout` or until `stop` is called. Returns true if
+    /// stopped.
+    fn wait(&self, timeout: Duration) -> bool {
+        let state = self.state.lock().unwrap();
+        let (state, _) = self
+            .stopped
+            .wait_timeout_while(state, timeout, |state| !state.stopped)
+            .unwrap();
+        state.stopped
+    }
+
+    /// Registers the socket before anything is read from it, so `stop`
+    /// can interrupt the read. Returns false if `stop` was already called.
+    fn register(&self, stream: &UnixStream) -> Result<bool, MonitorError> {
+        let mut state = self.state.lock().unwrap();
+        if state.stopped {
+            return Ok(false);
+        }
+        state.stream = Some(stream.try_clone().map_err(ipc_error)?);
+        Ok(true)
+    }
+
+    fn subscribed(&self) {
+        self.state.lock().unwrap().subscribed = true;
+    }
+}
+
+/// Reports the currently focused window, then blocks reading window events
+/// from the IPC socket until the connection is closed or `control` is stopped
+pub fn listen<F>(
+    path: &Path,
+    control: &ListenerControl,
+    mut on_focus: F,
+) -> Result<(), MonitorError>
+where
+    F: FnMut(FocusedWindow),
+{
+    // Requests and events can't be interleaved on a subscribed connection,
+    // so the initial state is queried on its own connection
+    let mut tree = SwayConnection::connect(path)?;
+    if !control.register(&tree.stream)? {
+        return Ok(());
+    }
+    match tree.focused_window() {
+        Ok(Some(window)) => on_focus(window),
+        Ok(None) => {}
+        Err(_) if control.is_stopped() => return Ok(()),
+        Err(e) => return Err(e),
+    }
+
+    let mut connection = SwayConnection::connect(path)?;
+    if !control.register(&connection.stream)? {
+        return Ok(());
+    }
+    match connection.subscribe_window_events() {
+        Ok(()) => control.subscribed(),
+        Err(_) if control.is_stopped() => return Ok(()),
+        Err(e) => return Err(e),
+    }
+    loop {
+        let (message_type, payload) = match connection.read() {
+            Ok(message) => message,
+            Err(_) if control.is_stopped() => return Ok(()),
+            Err(e) => return Err(e),
+        };
+        if message_type != EVENT_WINDOW {
+            continue;
+        }
+        match parse_window_event(&payload) {
+            Ok(Some(window)) => on_focus(window),
+            Ok(None) => {}
+            Err(e) => log::warn!("Skipping malformed window event: {}", e),
+        }
+    }
+}
+
+/// Runs `listen` until `control` is stopped, reconnecting with backoff when
+/// the connection fails or is lost
+pub fn listen_with_reconnect<F>(path: &Path, control: &ListenerControl, mut on_focus: F)
+where
+    F: FnMut(FocusedWindow),
+{
+    let mut delay = RECONNECT_MIN_DELAY;
+    loop {
+        let result = listen(path, control, &mut on_focus);
+        if control.disconnected() {
+            delay = RECONNECT_MIN_DELAY;
+        }
+        match result {
+            Ok(()) => return,
+            Err(e) => log::warn!(
+                "sway IPC listener disconnected, retrying in {:?}: {}",
+                delay,
+                e
+            ),
+        }
+        if control.wait(delay) {
+            return;
+        }
+        delay = (delay * 2).min(RECONNECT_MAX_DELAY);
+    }
+}
+
+/// Focus changes are pushed over the sway/i3 IPC socket. Everything else,
+/// including input activity for XWayland clients, goes through X11.
+pub struct SwayBackend {
+    // Passed to `handle_focused_window` from the listener thread
+    this: Weak<SwayBackend>,
+    socket_path: PathBuf,
+    x11: X11Backend,
+    // Connected on first use, None if there is no session bus
+    idle_monitor: OnceLock<Option<DbusIdleMonitor>>,
+    listener: Mutex<Option<(Arc<ListenerControl>, JoinHandle<()>)>>,
+}
+
+impl SwayBackend {
+    pub fn new(socket_path: PathBuf) -> Arc<Self> {
+        Arc::new_cyclic(|this| Self {
+            this: this.clone(),
+            socket_path,
+            x11: X11Backend::default(),
+            idle_monitor: OnceLock::new(),
+            listener: Mutex::new(None),
+        })
+    }
+}
+
+impl PlatformBackend for SwayBackend {
+    fn platform(&self) -> Platform {
+        Platform::Linux
+    }
+
+    fn name(&self) -> &'static str {
+        "sway"
+    }
+
+    fn start_monitoring(&self) -> Result<(), MonitorError> {
+        let mut listener = self.listener.lock().unwrap();
+        if listener.is_some() {
+            return Err(MonitorError::AlreadyRunning);
+        }
+        // Fail early if the socket is unusable rather than inside the thread
+        SwayConnection::connect(&self.socket_path)?;
+
+        let path = self.socket_path.clone();
+        let control = Arc::new(ListenerControl::default());
+        let thread_control = control.clone();
+        let backend = self.this.clone();
+        let thread = std::thread::Builder::new()
+            .name("sway-ipc-listener".into())
+            .spawn(move || {
+                listen_with_reconnect(&path, &thread_control, |window| {
+                    if let Some(backend) = backend.upgrade() {
+                        handle_focused_window(&*backend, window)
+                    }
+                })
+            })
+            .map_err(|e| MonitorError::HookInstall {
+                hook: "sway IPC listener",
+                reason: format!("failed to spawn thread: {}", e),
+            })?;
+        *listener = Some((control, thread));
+
+        // Wayland has no global input hooks, keyboard and mouse activity is
+        // only reported for XWayland clients
+        if let Err(e) = self.x11.start_monitoring() {
+            log::warn!("Input activity is not available: {}", e);
+        }
+        Ok(())
+    }
+
+    fn stop_monitoring(&self) -> Result<(), MonitorError> {
+        if let Some((control, thread)) = self.listener.lock().unwrap().take() {
+            control.stop();
+            let _ = thread.join();
+        }
+        self.x11.stop_monitoring()
+    }
+
+    fn focused_window(&self) -> Result<Option<FocusedWindow>, MonitorError> {
+        SwayConnection::connect(&self.socket_path)?.focused_window()
+    }
+
+    /// Falls back to polling while the listener is reconnecting
+    fn is_focus_event_driven(&self) -> bool {
+        self.listener
+            .lock()
+            .unwrap()
+            .as_ref()
+            .is_some_and(|(control, _)| control.is_connected())
+    }
+
+    /// The compositor's idle time over D-Bus, falling back to XScreenSaver
+    /// which only sees input to XWayland clients
+    fn idle_time(&self) -> Option<Duration> {
+        let idle_monitor = self.idle_monitor.get_or_init(|| {
+            DbusIdleMonitor::session()
+                .map_err(|e| log::warn!("D-Bus idle time unavailable: {}", e))
+                .ok()
+        });
+        idle_monitor
+            .as_ref()
+            .and_then(|monitor| {
+                monitor
+                    .idle_time()
+                    .map_err(|e| log::trace!("D-Bus idle time failed: {}", e))
+                    .ok()
+            })
+            .or_else(|| self.x11.idle_time())
+    }
+
+    fn start_blocking(
+        &self,
+        blocked_apps: &[BlockableItem],
+        redirect_url: &str,
+        blocklist_mode: bool,
+    ) -> Result<(), MonitorError> {
+        self.x11
+            .start_blocking(blocked_apps, redirect_url, blocklist_mode)
+    }
+
+    fn stop_blocking(&self) {
+        self.x11.stop_blocking()
+    }
+
+    fn unlocks_changed(&self) {
+        self.x11.unlocks_changed()
+    }
+
+    fn app_ids(&self, window: &FocusedWindow) -> Vec<String> {
+        self.x11.app_ids(window)
+    }
+
+    fn running_apps(&self) -> Vec<FocusedWindow> {
+        SwayConnection::connect(&self.socket_path)
+            .and_then(|mut connection| connection.running_apps())
+            .unwrap_or_else(|e| {
+                log::warn!("Failed to list running apps: {}", e);
+                Vec::new()
+            })
+    }
+
+    fn close_app(&self, window: &FocusedWindow) -> bool {
+        self.x11.close_app(window)
+    }
+
+    fn redirect_to_block_page(&self, redirect_url: &str) -> bool {
+        self.x11.redirect_to_block_page(redirect_url)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::os::unix::net::UnixListener;
+    use std::sync::mpsc;
+
+    const TREE: &str = r#"{
+        "name": "root", "focused": false, "nodes": [{
+            "name": "eDP-1", "focused": false, "nodes": [{
+                "name": "1", "focused": false,
+                "nodes": [
+                    {"name": "notes.md - Editor", "app_id": "org.editor", "pid": 10, "focused": false},
+                    {"name": "Inbox", "app_id": null, "pid": 11, "focused": false,
+                     "window_properties": {"class": "Thunderbird"}}
+                ],
+                "floating_nodes": [
+                    {"name": "Terminal", "app_id": "foot", "pid": 12, "focused": true}
+                ]
+            }]
+        }]
+    }"#;
+
+    fn write_message(stream: &mut UnixStream, message_type: u32, payload: &str) {
+        let mut message = MAGIC.to_vec();
+        message.extend_from_slice(&(payload.len() as u32).to_ne_bytes());
+        message.extend_from_slice(&message_type.to_ne_bytes());
+        message.extend_from_slice(payload.as_bytes());
+        stream.write_all(&message).unwrap();
+    }
+
+    fn read_message(stream: &mut UnixStream) -> (u32, String) {
+        let mut connection = SwayConnection {
+            stream: stream.try_clone().unwrap(),
+        };
+        let (message_type, payload) = connection.read().unwrap();
+        (message_type, String::from_utf8(payload).unwrap())
+    }
+
+    fn window_event(change: &str, name: &str, app_id: &str, focused: bool) -> String {
+        format!(
+            r#"{{"change": "{}", "container": {{"name": "{}", "app_id": "{}", "pid": 20, "focused": {}}}}}"#,
+            change, name, app_id, focused
+        )
+    }
+
+    /// Fake compositor: answers GET_TREE on the first connection, then accepts
+    /// a subscription and pushes the given window events
+    fn spawn_fake_sway(path: PathBuf, events: Vec<String>) -> std::thread::JoinHandle<()> {
+        let listener = UnixListener::bind(&path).unwrap();
+        std::thread::spawn(move || {
+            let (mut tree_stream, _) = listener.accept().unwrap();
+            assert_eq!(read_message(&mut tree_stream).0, MESSAGE_GET_TREE);
+            write_message(&mut tree_stream, MESSAGE_GET_TREE, TREE);
+
+            let (mut stream, _) = listener.accept().unwrap();
+            let (message_type, payload) = read_message(&mut stream);
+            assert_eq!(message_type, MESSAGE_SUBSCRIBE);
+            assert_eq!(payload, r#"["window"]"#);
+            write_message(&mut stream, MESSAGE_SUBSCRIBE, r#"{"success": true}"#);
+
+            for event in events {
+                write_message(&mut stream, EVENT_WINDOW, &event);
+            }
+            // Closing the socket ends the listener
+        })
+    }
+
+    fn temp_socket_path(name: &str) -> PathBuf {
+        let path =
+            std::env::temp_dir().join(format!("os-monitor-{}-{}.sock", name, std::process::id()));
+        let _ = std::fs::remove_file(&path);
+        path
+    }
+
+    #[test]
+    fn test_find_focused_in_tree() {
+        let tree: Node = serde_json::from_str(TREE).unwrap();
+        let focused = tree.find_focused().unwrap().to_focused_window();
+        assert_eq!(
+            focused,
+            FocusedWindow {
+                app_name: "foot".to_string(),
+                title: "Terminal".to_string(),
+                bundle_id: Some("foot".to_string()),
+                url: None,
+                pid: Some(12),
+            }
+        );
+    }
+
+    #[test]
+    fn test_windows_in_tree() {
+        let tree: Node = serde_json::from_str(TREE).unwrap();
+        let titles: Vec<&str> = tree
+            .windows()
+            .into_iter()
+            .filter_map(|node| node.name.as_deref())
+            .collect();
+        assert_eq!(titles, vec!["notes.md - Editor", "Inbox", "Terminal"]);
+
+        let empty_workspace: Node =
+            serde_json::from_str(r#"{"name": "2", "focused": false}"#).unwrap();
+        assert!(empty_workspace.windows().is_empty());
+    }
+
+    #[test]
+    fn test_xwayland_window_uses_class() {
+        let node: Node = serde_json::from_str(
+            r#"{"name": "Inbox", "app_id": null, "focused": true,
+                "window_properties": {"class": "Thunderbird"}}"#,
+        )
+        .unwrap();
+        let window = node.to_focused_window();
+        assert_eq!(window.bundle_id.as_deref(), Some("Thunderbird"));
+        assert_eq!(window.app_name, "Thunderbird");
+    }
+
+    #[test]
+    fn test_parse_window_event() {
+        let focus = window_event("focus", "Docs", "firefox", true);
+        let window = parse_window_event(focus.as_bytes()).unwrap().unwrap();
+        assert_eq!(window.title, "Docs");
+        assert_eq!(window.bundle_id.as_deref(), Some("firefox"));
+
+        let focused_title = window_event("title", "Docs - Edited", "firefox", true);
+        assert!(parse_window_event(focused_title.as_bytes())
+            .unwrap()
+            .is_some());
+
+        let background_title = window_event("title", "Build done", "foot", false);
+        assert!(parse_window_event(background_title.as_bytes())
+            .unwrap()
+            .is_none());
+
+        let new_window = window_event("new", "Untitled", "firefox", false);
+        assert!(parse_window_event(new_window.as_bytes()).unwrap().is_none());
+
+        assert!(parse_window_event(b"not json").is_err());
+    }
+
+    #[test]
+    fn test_listen_against_fake_sway() {
+        let path = temp_socket_path("listen");
+        let server = spawn_fake_sway(
+            path.clone(),
+            vec![
+                window_event("focus", "Docs", "firefox", true),
+                window_event("title", "Build done", "foot", false),
+                window_event("title", "Docs - Edited", "firefox", true),
+                window_event("close", "Docs - Edited", "firefox", false),
+            ],
+        );
+
+        let (sender, receiver) = mpsc::channel();
+        let control = ListenerControl::default();
+        let result = listen(&path, &control, |window| sender.send(window).unwrap());
+        server.join().unwrap();
+        let _ = std::fs::remove_file(&path);
+
+        // The fake server hangs up after the scripted events
+        assert!(result.is_err());
+        let titles: Vec<String> = receiver.try_iter().map(|window| window.title).collect();
+        assert_eq!(titles, vec!["Terminal", "Docs", "Docs - Edited"]);
+    }
+
+    #[test]
+    fn test_stop_listener() {
+        let path = temp_socket_path("stop");
+        // The fake server keeps the subscription open until the listener
+        // shuts it down
+        let (done_sender, done_receiver) = mpsc::channel::<()>();
+        let listener = UnixListener::bind(&path).unwrap();
+        let server = std::thread::spawn(move || {
+            let (mut tree_stream, _) = listener.accept().unwrap();
+            read_message(&mut tree_stream);
+            write_message(&mut tree_stream, MESSAGE_GET_TREE, TREE);
+
+            let (mut stream, _) = listener.accept().unwrap();
+            read_message(&mut stream);
+            write_message(&mut stream, MESSAGE_SUBSCRIBE, r#"{"success": true}"#);
+            write_message(
+                &mut stream,
+                EVENT_WINDOW,
+                &window_event("focus", "Docs", "firefox", true),
+            );
+            let _ = done_receiver.recv();
+        });
+
+        let control = Arc::new(ListenerControl::default());
+        let (sender, receiver) = mpsc::channel();
+        let listener_control = control.clone();
+        let listener_path = path.clone();
+        let listener = std::thread::spawn(move || {
+            listen(&listener_path, &listener_control, |window| {
+                sender.send(window.title).unwrap()
+            })
+        });
+
+        assert_eq!(receiver.recv().unwrap(), "Terminal");
+        assert_eq!(receiver.recv().unwrap(), "Docs");
+        control.stop();
+        assert!(listener.join().unwrap().is_ok());
+
+        done_sender.send(()).unwrap();
+        server.join().unwrap();
+        let _ = std::fs::remove_file(&path);
+    }
+
+    #[test]
+    fn test_stop_listener_before_tree_reply() {
+        let path = temp_socket_path("stop-tree");
+        // The fake server never answers GET_TREE
+        let (requested_sender, requested_receiver) = mpsc::channel::<()>();
+        let (done_sender, done_receiver) = mpsc::channel::<()>();
+        let listener = UnixListener::bind(&path).unwrap();
+        let server = std::thread::spawn(move || {
+            let (mut tree_stream, _) = listener.accept().unwrap();
+            read_message(&mut tree_stream);
+            requested_sender.send(()).unwrap();
+            let _ = done_receiver.recv();
+        });
+
+        let control = Arc::new(ListenerControl::default());
+        let listener_control = control.clone();
+        let listener_path = path.clone();
+        let listener = std::thread::spawn(move || {
+            listen_with_reconnect(&listener_path, &listener_control, |_| {
+                panic!("no window was reported")
+            })
+        });
+
+        requested_receiver.recv().unwrap();
+        control.stop();
+        listener.join().unwrap();
+        assert!(!control.is_connected());
+
+        done_sender.send(()).unwrap();
+        server.join().unwrap();
+        let _ = std::fs::remove_file(&path);
+    }
+
+    #[test]
+    fn test_reconnect_after_disconnect() {
+        let path = temp_socket_path("reconnect");
+        // Hangs up after the first event, then serves a second session that
+        // stays open until the listener is stopped
+        let (done_sender, done_receiver) = mpsc::channel::<()>();
+        let listener = UnixListener::bind(&path).unwrap();
+        let server = std::thread::spawn(move || {
+            for title in ["Docs", "Mail"] {
+                let (mut tree_stream, _) = listener.accept().unwrap();
+                read_message(&mut tree_stream);
+                write_message(&mut tree_stream, MESSAGE_GET_TREE, TREE);
+
+                let (mut stream, _) = listener.accept().unwrap();
+                read_message(&mut stream);
+                write_message(&mut stream, MESSAGE_SUBSCRIBE, r#"{"success": true}"#);
+                write_message(
+                    &mut stream,
+                    EVENT_WINDOW,
+                    &window_event("focus", title, "firefox", true),
+                );
+                if title == "Mail" {
+                    let _ = done_receiver.recv();
+                }
+            }
+        });
+
+        let control = Arc::new(ListenerControl::default());
+        let (sender, receiver) = mpsc::channel();
+        let listener_control = control.clone();
+        let listener_path = path.clone();
+        let listener = std::thread::spawn(move || {
+            listen_with_reconnect(&listener_path, &listener_control, |window| {
+                sender.send(window.title).unwrap()
+            })
+        });
+
+        let titles: Vec<String> = receiver.iter().take(4).collect();
+        assert_eq!(titles, vec!["Terminal", "Docs", "Terminal", "Mail"]);
+        assert!(control.is_connected());
+        control.stop();
+        listener.join().unwrap();
+        assert!(!control.is_connected());
+
+        done_sender.send(()).unwrap();
+        server.join().unwrap();
+        let _ = std::fs::remove_file(&path);
+    }
+
+    #[test]
+    fn test_subscribe_rejected() {
+        let path = temp_socket_path("rejected");
+        let listener = UnixListener::bind(&path).unwrap();
+        let server = std::thread::spawn(move || {
+            let (mut stream, _) = listener.accept().unwrap();
+            read_message(&mut stream);
+            write_message(&mut stream, MESSAGE_SUBSCRIBE, r#"{"success": false}"#);
+        });
+
+        let mut connection = SwayConnection::connect(&path).unwrap();
+        assert!(connection.subscribe_window_events().is_err());
+        server.join().unwrap();
+        let _ = std::fs::remove_file(&path);
+    }
+}
@@ -0,0 +1,730 @@
+use super::events::{start_input_listener, InputListenerHandle};
+use super::hosts::HostsFile;
+use super::idle::xscreensaver_idle_time;
+use super::process::{self, TERMINATE_GRACE};
+use super::process_name;
+use crate::blocking::blocked_hosts;
+use crate::platform::{unlocks, FocusedWindow, PlatformBackend};
+use crate::{BlockableItem, MonitorError, Platform};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Mutex;
+use std::time::Duration;
+use x11rb::connection::Connection;
+use x11rb::protocol::xproto::{AtomEnum, ConnectionExt, Window};
+use x11rb::rust_connection::RustConnection;
+
+x11rb::atom_manager! {
+    pub Atoms: AtomsCookie {
+        _NET_ACTIVE_WINDOW,
+        _NET_CLIENT_LIST,
+        _NET_WM_NAME,
+        _NET_WM_PID,
+        UTF8_STRING,
+    }
+}
+
+/// Information about a top level window as reported by the window manager
+#[derive(Debug, Clone, PartialEq)]
+pub struct X11Window {
+    pub window: Window,
+    pub title: String,
+    pub instance: Option<String>,
+    pub class: Option<String>,
+    pub pid: Option<u32>,
+}
+
+impl X11Window {
+    fn into_focused_window(self) -> FocusedWindow {
+        FocusedWindow {
+            app_name: self.app_name(),
+            title: self.title,
+            bundle_id: self.class,
+            url: None,
+            pid: self.pid,
+        }
+    }
+
+    /// WM_CLASS is the closest thing X11 has to a bundle id, so the class name
+    /// doubles as the application name. Falls back to the process name.
+    pub fn app_name(&self) -> String {
+        if let Some(class) = &self.class {
+            return class.clone();
+        }
+        self.pid
+            .and_then(process_name)
+            .unwrap_or_else(|| String::from("Unknown"))
+    }
+}
+
+pub struct X11Connection {
+    conn: RustConnection,
+    root: Window,
+    atoms: Atoms,
+}
+
+impl X11Connection {
+    /// Connects to the given display, or `$DISPLAY` if none is given
+    pub fn connect(display: Option<&str>) -> Result<Self, MonitorError> {
+        let (conn, screen_num) =
+            RustConnection::connect(display).map_err(|e| MonitorError::BackendUnavailable {
+                backend: "x11",
+                reason: e.to_string(),
+            })?;
+        let root = conn.setup().roots[screen_num].root;
+        let atoms = Atoms::new(&conn)
+            .map_err(x11_error)?
+            .reply()
+            .map_err(x11_error)?;
+
+        Ok(Self { conn, root, atoms })
+    }
+
+    pub fn conn(&self) -> &RustConnection {
+        &self.conn
+    }
+
+    pub fn root(&self) -> Window {
+        self.root
+    }
+
+    /// Reads `_NET_ACTIVE_WINDOW` from the root window. Returns None when the
+    /// window manager reports no active window.
+    pub fn active_window(&self) -> Result<Option<Window>, MonitorError> {
+        let reply = self
+            .conn
+            .get_property(
+                false,
+                self.root,
+                self.atoms._NET_ACTIVE_WINDOW,
+                AtomEnum::WINDOW,
+                0,
+                1,
+            )
+            .map_err(x11_error)?
+            .reply()
+            .map_err(x11_error)?;
+
+        let window = reply.value32().and_then(|mut values| values.next());
+        Ok(window.filter(|window| *window != x11rb::NONE))
+    }
+
+    /// Reads `_NET_CLIENT_LIST`: the top level windows the window manager
+    /// manages, oldest first
+    pub fn client_windows(&self) -> Result<Vec<Window>, MonitorError> {
+        let reply = self
+            .conn
+            .get_property(
+                false,
+                self.root,
+                self.atoms._NET_CLIENT_LIST,
+                AtomEnum::WINDOW,
+                0,
+                u32::MAX / 4,
+            )
+            .map_err(x11_error)?
+            .reply()
+            .map_err(x11_error)?;
+        Ok(reply
+            .value32()
+            .map(|values| values.collect())
+            .unwrap_or_default())
+    }
+
+    pub fn window_info(&self, window: Window) -> Result<X11Window, MonitorError> {
+        let title = match self.get_string_property(
+            window,
+            self.atoms._NET_WM_NAME,
+            self.atoms.UTF8_STRING,
+        )? {
+            Some(title) => title,
+            None => self
+                .get_string_property(window, AtomEnum::WM_NAME.into(), AtomEnum::STRING.into())?
+                .unwrap_or_default(),
+        };
+
+        let (instance, class) = match self.get_property_bytes(
+            window,
+            AtomEnum::WM_CLASS.into(),
+            AtomEnum::STRING.into(),
+        )? {
+            Some(bytes) => match parse_wm_class(&bytes) {
+                Some((instance, class)) => (Some(instance), Some(class)),
+                None => (None, None),
+            },
+            None => (None, None),
+        };
+
+        let pid = self
+            .conn
+            .get_property(
+                false,
+                window,
+                self.atoms._NET_WM_PID,
+                AtomEnum::CARDINAL,
+                0,
+                1,
+            )
+            .map_err(x11_error)?
+            .reply()
+            .map_err(x11_error)?
+            .value32()
+            .and_then(|mut values| values.next());
+
+        Ok(X11Window {
+            window,
+            title,
+            instance,
+            class,
+            pid,
+        })
+    }
+
+    pub fn focused_window(&self) -> Result<Option<X11Window>, MonitorError> {
+        match self.active_window()? {
+            Some(window) => self.window_info(window).map(Some),
+            None => Ok(None),
+        }
+    }
+
+    fn get_property_bytes(
+        &self,
+        window: Window,
+        property: u32,
+        type_: u32,
+    ) -> Result<Option<Vec<u8>>, MonitorError> {
+        let reply = self
+            .conn
+            .get_property(false, window, property, type_, 0, u32::MAX / 4)
+            .map_err(x11_error)?
+            .reply()
+            .map_err(x11_error)?;
+
+        if reply.format != 8 || reply.type_ == x11rb::NONE {
+            return Ok(None);
+        }
+        Ok(Some(reply.value))
+    }
+
+    fn get_string_property(
+        &self,
+        window: Window,
+        property: u32,
+        type_: u32,
+    ) -> Result<Option<String>, MonitorError> {
+        Ok(self
+            .get_property_bytes(window, property, type_)?
+            .map(|bytes| String::from_utf8_lossy(&bytes).into_owned()))
+    }
+}
+
+/// Polls `_NET_ACTIVE_WINDOW` for focus and uses XInput2 for input activity
+#[derive(Default)]
+pub struct X11Backend {
+    // Connection used for focus queries, opened lazily and dropped on error so
+    // the next poll reconnects (e.g. after the X server restarts)
+    connection: Mutex<Option<X11Connection>>,
+    input_listener: Mutex<Option<InputListenerHandle>>,
+    hosts: HostsFile,
+    // Hosts of the active blocklist, written without the unlocked ones
+    blocked_hosts: Mutex<Vec<String>>,
+    // Whether the hosts file section was written by this process
+    blocking_hosts: AtomicBool,
+}
+
+impl X11Backend {
+    /// Removes a hosts file section this process didn't write, left over
+    /// from a monitor that exited while blocking
+    fn recover_hosts(&self) {
+        if self.blocking_hosts.load(Ordering::SeqCst) || !self.hosts.has_section() {
+            return;
+        }
+        log::info!(
+            "Removing stale blocked sites from {}",
+            self.hosts.path().display()
+        );
+        if let Err(e) = self.hosts.unblock() {
+            log::warn!("Failed to remove stale blocked sites: {}", e);
+        }
+    }
+
+    /// Writes the blocked hosts that no unlock covers, removing the section
+    /// when there are none
+    fn write_hosts(&self) {
+        let hosts: Vec<String> = self
+            .blocked_hosts
+            .lock()
+            .unwrap()
+            .iter()
+            .filter(|host| !unlocks::is_exempt(host))
+            .cloned()
+            .collect();
+        match self.hosts.block(&hosts) {
+            Ok(()) => self
+                .blocking_hosts
+                .store(!hosts.is_empty(), Ordering::SeqCst),
+            Err(e) => log::warn!("Sites are not blocked: {}", e),
+        }
+    }
+
+    fn with_connection<T>(
+        &self,
+        f: impl FnOnce(&X11Connection) -> Result<T, MonitorError>,
+    ) -> Result<T, MonitorError> {
+        let mut connection_guard = self.connection.lock().unwrap();
+        if connection_guard.is_none() {
+            *connection_guard = Some(X11Connection::connect(None)?);
+        }
+
+        let result = f(connection_guard.as_ref().unwrap());
+        if result.is_err() {
+            *connection_guard = None;
+        }
+        result
+    }
+}
+
+impl PlatformBackend for X11Backend {
+    fn platform(&self) -> Platform {
+        Platform::Linux
+    }
+
+    fn name(&self) -> &'static str {
+        "x11"
+    }
+
+    fn start_monitoring(&self) -> Result<(), MonitorError> {
+        let mut input_listener = self.input_listener.lock().unwrap();
+        if input_listener.is_some() {
+            return Err(MonitorError::AlreadyRunning);
+        }
+        self.recover_hosts();
+        *input_listener = Some(start_input_listener()?);
+        Ok(())
+    }
+
+    fn stop_monitoring(&self) -> Result<(), MonitorError> {
+        match self.input_listener.lock().unwrap().take() {
+            Some(input_listener) => self.with_connection(|x11| input_listener.stop(x11)),
+            None => Ok(()),
+        }
+    }
+
+    fn focused_window(&self) -> Result<Option<FocusedWindow>, MonitorError> {
+        let focused = self.with_connection(|x11| x11.focused_window())?;
+        Ok(focused.map(X11Window::into_focused_window))
+    }
+
+    /// One window per process from `_NET_CLIENT_LIST`
+    fn running_apps(&self) -> Vec<FocusedWindow> {
+        let windows = self.with_connection(|x11| {
+            let mut windows: Vec<X11Window> = Vec::new();
+            for window in x11.client_windows()? {
+                // Windows can close while they are listed
+                let Ok(window) = x11.window_info(window) else {
+                    continue;
+                };
+                let seen = windows
+                    .iter()
+                    .any(|other| (other.pid, &other.class) == (window.pid, &window.class));
+                if !seen {
+                    windows.push(window);
+                }
+            }
+            Ok(windows)
+        });
+        match windows {
+            Ok(windows) => windows
+                .into_iter()
+                .map(X11Window::into_focused_window)
+                .collect(),
+            Err(e) => {
+                log::warn!("Failed to list running apps: {}", e);
+                Vec::new()
+            }
+        }
+    }
+
+    fn idle_time(&self) -> Option<Duration> {
+        self.with_connection(xscreensaver_idle_time)
+            .map_err(|e| log::trace!("XScreenSaver idle time unavailable: {}", e))
+            .ok()
+    }
+
+    /// Apps are matched and closed by the shared code on each focus change.
+    /// Sites are blocked through the hosts file, which can only express a
+    /// blocklist, so allowlist mode leaves sites to the shared code. Failing
+    /// to write the hosts file (it needs root) doesn't stop app blocking.
+    fn start_blocking(
+        &self,
+        blocked_apps: &[BlockableItem],
+        _redirect_url: &str,
+        blocklist_mode: bool,
+    ) -> Result<(), MonitorError> {
+        let hosts = if blocklist_mode {
+            blocked_hosts(blocked_apps)
+        } else {
+            Vec::new()
+        };
+        log::info!(
+            "Blocking {} items (blocklist_mode: {}), {} hosts in {}",
+            blocked_apps.len(),
+            blocklist_mode,
+            hosts.len(),
+            self.hosts.path().display()
+        );
+        *self.blocked_hosts.lock().unwrap() = hosts;
+        self.write_hosts();
+        Ok(())
+    }
+
+    /// Rewrites the hosts file so unlocked sites resolve again, and are
+    /// blocked again once their unlock expires
+    fn unlocks_changed(&self) {
+        if !self.blocked_hosts.lock().unwrap().is_empty() {
+            self.write_hosts();
+        }
+    }
+
+    fn stop_blocking(&self) {
+        self.blocked_hosts.lock().unwrap().clear();
+        if let Err(e) = self.hosts.unblock() {
+            log::warn!("Failed to unblock sites: {}", e);
+        }
+        self.blocking_hosts.store(false, Ordering::SeqCst);
+    }
+
+    /// The window class plus the `.desktop` id and executable of its process
+    fn app_ids(&self, window: &FocusedWindow) -> Vec<String> {
+        let mut ids: Vec<String> = window.bundle_id.iter().cloned().collect();
+        for id in window.pid.map(process::app_ids).unwrap_or_default() {
+            if !ids.contains(&id) {
+                ids.push(id);
+            }
+        }
+        ids
+    }
+
+    /// Terminates the window's process, killing it if it ignores SIGTERM
+    fn close_app(&self, window: &FocusedWindow) -> bool {
+        match window.pid {
+            Some(pid) => process::terminate(pid, TERMINATE_GRACE),
+            None => {
+                log::warn!("No pid for {}, can't close it", window.app_name);
+                false
+            }
+        }
+    }
+
+    fn redirect_to_block_page(&self, _redirect_url: &str) -> bool {
+        false
+    }
+}
+
+pub fn x11_error<E: std::fmt::Display>(e: E) -> MonitorError {
+    MonitorError::PlatformError(format!("X11 error: {}", e))
+}
+
+/// WM_CLASS holds two consecutive null-terminated strings: instance then class
+pub fn parse_wm_class(bytes: &[u8]) -> Option<(String, String)> {
+    let mut parts = bytes
+        .split(|b| *b == 0)
+        .map(|part| String::from_utf8_lossy(part).into_owned());
+    let instance = parts.next()?;
+    let class = parts.next().filter(|class| !class.is_empty())?;
+    Some((instance, class))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use x11rb::protocol::xproto::{CreateWindowAux, PropMode, WindowClass};
+    use x11rb::wrapper::ConnectionExt as _;
+
+    #[test]
+    fn test_parse_wm_class() {
+        assert_eq!(
+            parse_wm_class(b"Navigator\0firefox\0"),
+            Some(("Navigator".to_string(), "firefox".to_string()))
+        );
+        assert_eq!(
+            parse_wm_class(b"gnome-terminal-server\0Gnome-terminal"),
+            Some((
+                "gnome-terminal-server".to_string(),
+                "Gnome-terminal".to_string()
+            ))
+        );
+        assert_eq!(parse_wm_class(b"only-instance\0"), None);
+        assert_eq!(parse_wm_class(b""), None);
+    }
+
+    #[test]
+    fn test_app_name_prefers_class() {
+        let window = X11Window {
+            window: 1,
+            title: "Title".to_string(),
+            instance: Some("Navigator".to_string()),
+            class: Some("firefox".to_string()),
+            pid: None,
+        };
+        assert_eq!(window.app_name(), "firefox");
+
+        let window = X11Window {
+            class: None,
+            pid: Some(std::process::id()),
+            ..window
+        };
+        assert!(!window.app_name().is_empty());
+        assert_ne!(window.app_name(), "Unknown");
+    }
+
+    /// Acts as a minimal scripted window manager against the display in
+    /// `$DISPLAY` (e.g. `Xvfb :99 & DISPLAY=:99 cargo test`). Skipped when no
+    /// X server is reachable.
+    #[test]
+    fn test_focused_window_with_scripted_wm() {
+        let Ok(x11) = X11Connection::connect(None) else {
+            eprintln!("skipping: no X11 display available");
+            return;
+        };
+        let conn = x11.conn();
+        let atoms = x11.atoms;
+
+        let window = conn.generate_id().unwrap();
+        conn.create_window(
+            x11rb::COPY_DEPTH_FROM_PARENT,
+            window,
+            x11.root(),
+            0,
+            0,
+            100,
+            100,
+            0,
+            WindowClass::INPUT_OUTPUT,
+            x11rb::COPY_FROM_PARENT,
+            &CreateWindowAux::new(),
+        )
+        .unwrap();
+        conn.change_property8(
+            PropMode::REPLACE,
+            window,
+            AtomEnum::WM_CLASS,
+            AtomEnum::STRING,
+            b"editor\0Editor\0",
+        )
+        .unwrap();
+        conn.change_property8(
+            PropMode::REPLACE,
+            window,
+            atoms._NET_WM_NAME,
+            atoms.UTF8_STRING,
+            "main.rs — Editor".as_bytes(),
+        )
+        .unwrap();
+        conn.change_property32(
+            PropMode::REPLACE,
+            window,
+            atoms._NET_WM_PID,
+            AtomEnum::CARDINAL,
+            &[4242],
+        )
+        .unwrap();
+        conn.change_property32(
+            PropMode::REPLACE,
+            x11.root(),
+            atoms._NET_ACTIVE_WINDOW,
+            AtomEnum::WINDOW,
+            &[window],
+        )
+        .unwrap();
+        conn.flush().unwrap();
+
+        let focused = x11.focused_window().unwrap().expect("active window");
+        assert_eq!(focused.window, window);
+        assert_eq!(focused.title, "main.rs — Editor");
+        assert_eq!(focused.class.as_deref(), Some("Editor"));
+        assert_eq!(focused.instance.as_deref(), Some("editor"));
+        assert_eq!(focused.pid, Some(4242));
+        assert_eq!(focused.app_name(), "Editor");
+
+        // Title changes are picked up on the next query
+        conn.change_property8(
+            PropMode::REPLACE,
+            window,
+            atoms._NET_WM_NAME,
+            atoms.UTF8_STRING,
+            b"lib.rs - Editor",
+        )
+        .unwrap();
+        conn.flush().unwrap();
+        let focused = x11.focused_window().unwrap().expect("active window");
+        assert_eq!(focused.title, "lib.rs - Editor");
+
+        // No active window
+        conn.change_property32(
+            PropMode::REPLACE,
+            x11.root(),
+            atoms._NET_ACTIVE_WINDOW,
+            AtomEnum::WINDOW,
+            &[x11rb::NONE],
+        )
+        .unwrap();
+        conn.flush().unwrap();
+        assert!(x11.focused_window().unwrap().is_none());
+
+        // Managed windows
+        conn.change_property32(
+            PropMode::REPLACE,
+            x11.root(),
+            atoms._NET_CLIENT_LIST,
+            AtomEnum::WINDOW,
+            &[window],
+        )
+        .unwrap();
+        conn.flush().unwrap();
+        assert_eq!(x11.client_windows().unwrap(), vec![window]);
+
+        conn.destroy_window(window).unwrap();
+        conn.flush().unwrap();
+    }
+
+    /// Removes the backend's hosts file when the test ends
+    struct TempHostsFile(std::path::PathBuf);
+
+    impl Drop for TempHostsFile {
+        fn drop(&mut self) {
+            let _ = std::fs::remove_file(&self.0);
+        }
+    }
+
+    fn backend_with_hosts_file(name: &str) -> (X11Backend, TempHostsFile) {
+        let path = std::env::temp_dir().join(format!("os-monitor-{}-{}", name, std::process::id()));
+        std::fs::write(&path, "127.0.0.1 localhost\n").unwrap();
+        let backend = X11Backend {
+            hosts: HostsFile::new(path.clone()),
+            ..Default::default()
+        };
+        (backend, TempHostsFile(path))
+    }
+
+    #[test]
+    fn test_blocked_sites_in_hosts_file() {
+        let (backend, _hosts_file) = backend_with_hosts_file("hosts-sites");
+        let hosts = || std::fs::read_to_string(backend.hosts.path()).unwrap();
+        let items = [
+            BlockableItem::new("reddit.com".to_string(), true),
+            BlockableItem::new("com.slack.Slack".to_string(), false),
+        ];
+        backend.start_blocking(&items, "", true).unwrap();
+        assert!(hosts().contains("0.0.0.0 www.reddit.com"));
+        assert!(!hosts().contains("slack"));
+
+        // A hosts file can't allow only some sites
+        backend.start_blocking(&items, "", false).unwrap();
+        assert_eq!(hosts(), "127.0.0.1 localhost\n");
+
+        backend.start_blocking(&items, "", true).unwrap();
+        backend.stop_blocking();
+        assert_eq!(hosts(), "127.0.0.1 localhost\n");
+
+        // Left behind by a monitor that crashed while blocking
+        backend.hosts.block(&["reddit.com".to_string()]).unwrap();
+        let restarted = X11Backend {
+            hosts: backend.hosts.clone(),
+            ..Default::default()
+        };
+        restarted.recover_hosts();
+        assert_eq!(hosts(), "127.0.0.1 localhost\n");
+
+        // But not while this process is blocking
+        restarted.start_blocking(&items, "", true).unwrap();
+        restarted.recover_hosts();
+        assert!(hosts().contains("reddit.com"));
+        restarted.stop_blocking();
+    }
+
+    #[test]
+    fn test_unlocked_sites_leave_hosts_file() {
+        use crate::platform::{confirm_unlock, request_unlock, set_backend, TEST_LOCK};
+        use crate::{RuleKind, UnlockFriction};
+        use std::sync::Arc;
+
+        let _guard = TEST_LOCK.lock().unwrap_or_else(|e| e.into_inner());
+        let (backend, _hosts_file) = backend_with_hosts_file("hosts-unlocks");
+        let backend = Arc::new(backend);
+        set_backend(backend.clone());
+        let hosts = || std::fs::read_to_string(backend.hosts.path()).unwrap();
+        let items = [
+            BlockableItem::new("unlocked-hosts.example".to_string(), true),
+            BlockableItem::new("news.example".to_string(), true),
+        ];
+        backend.start_blocking(&items, "", true).unwrap();
+        assert!(hosts().contains("0.0.0.0 www.unlocked-hosts.example"));
+
+        let id = request_unlock(
+            BlockableItem::new("unlocked-hosts.example".to_string(), true)
+                .with_rule(RuleKind::Domain),
+            UnlockFriction::Phrase("let me in".to_string()),
+            Duration::from_secs(600),
+        )
+        .unwrap();
+        assert!(hosts().contains("unlocked-hosts.example"));
+        confirm_unlock(id, "let me in").unwrap();
+        assert!(!hosts().contains("unlocked-hosts.example"));
+        assert!(hosts().contains("0.0.0.0 news.example"));
+
+        backend.stop_blocking();
+        assert_eq!(hosts(), "127.0.0.1 localhost\n");
+    }
+
+    #[test]
+    fn test_blocked_app_is_terminated() {
+        use crate::platform::monitoring::handle_focused_window;
+        use crate::platform::{set_backend, start_blocking, stop_blocking, TEST_LOCK};
+        use crate::ExceptionPolicy;
+        use std::process::Command;
+        use std::sync::Arc;
+
+        let _guard = TEST_LOCK.lock().unwrap_or_else(|e| e.into_inner());
+        let (backend, _hosts_file) = backend_with_hosts_file("hosts-apps");
+        let backend = Arc::new(backend);
+        set_backend(backend.clone());
+        let window = |child: &std::process::Child| FocusedWindow {
+            app_name: "Dummy".to_string(),
+            title: "Dummy".to_string(),
+            bundle_id: Some("Dummy".to_string()),
+            url: None,
+            pid: Some(child.id()),
+        };
+        let sleep = || Command::new("sleep").arg("30").spawn().unwrap();
+        let exited = |child: &mut std::process::Child| {
+            (0..100).any(|_| {
+                std::thread::sleep(Duration::from_millis(20));
+                child.try_wait().unwrap().is_some()
+            })
+        };
+        let policy = ExceptionPolicy::none();
+        const REDIRECT: &str = "https://blocked.example";
+
+        // Matched by its executable although the window class isn't listed
+        let mut blocked = sleep();
+        let items = [BlockableItem::new("sleep".to_string(), false)];
+        start_blocking(&items, REDIRECT, true, &policy).unwrap();
+        assert!(backend
+            .app_ids(&window(&blocked))
+            .contains(&"sleep".to_string()));
+        handle_focused_window(backend.as_ref(), window(&blocked));
+        assert!(exited(&mut blocked));
+
+        // Allowed by its executable although the window class isn't
+        let mut allowed = sleep();
+        start_blocking(&items, REDIRECT, false, &policy).unwrap();
+        handle_focused_window(backend.as_ref(), window(&allowed));
+        assert!(!exited(&mut allowed));
+
+        let other = [BlockableItem::new("firefox".to_string(), false)];
+        start_blocking(&other, REDIRECT, false, &policy).unwrap();
+        handle_focused_window(backend.as_ref(), window(&allowed));
+        assert!(exited(&mut allowed));
+        stop_blocking();
+    }
+}
@@ -1,7 +1,12 @@
-use super::monitoring::MONITOR;
-use crate::{BlockableItem, BlockedApp, BlockedAppEvent};
+use crate::platform::monitoring::{self, MONITOR};
+use crate::platform::FocusedWindow;
+use crate::{BlockableItem, BlockedApp, BlockedAppEvent, MonitorError};
 use std::ffi::{c_char, CStr, CString};
 
+fn c_string(identifier: &str) -> Result<CString, MonitorError> {
+    CString::new(identifier).map_err(|_| MonitorError::InvalidIdentifier(identifier.to_string()))
+}
+
 pub extern "C" fn app_blocked_callback(
     app_names: *const *const c_char,
     bundle_ids: *const *const c_char,
@@ -31,6 +36,7 @@ pub extern "C" fn app_blocked_callback(
 
                 log::info!("App blocked: {} ({})", app_name_str, bundle_id_str);
                 blocked_apps.push(BlockedApp {
+                    decision: monitoring::block_decision(&bundle_id_str),
                     app_name: app_name_str,
                     app_external_id: bundle_id_str,
                     is_site: false,
@@ -45,80 +51,35 @@ pub extern "C" fn app_blocked_callback(
     }
 }
 
-fn has_website_url(blocked_apps: &Vec<BlockableItem>) -> bool {
-    blocked_apps.iter().any(|app| app.is_browser)
-}
-
-fn get_system_exceptions() -> Vec<BlockableItem> {
-    vec![
-        BlockableItem::new("com.apple.SystemFinder".to_string(), false),
-        BlockableItem::new("com.spotify.client".to_string(), false),
-        BlockableItem::new("com.apple.ActivityMonitor".to_string(), false),
-        BlockableItem::new("com.apple.SystemPreferences".to_string(), false),
-        BlockableItem::new("com.apple.finder".to_string(), false),
-        BlockableItem::new("com.apple.Terminal".to_string(), false),
-        BlockableItem::new("com.apple.Preview".to_string(), false),
-        BlockableItem::new("com.apple.Music".to_string(), false),
-        BlockableItem::new("com.nordvpn.macos".to_string(), false),
-        BlockableItem::new("ebb.cool".to_string(), true),
-        BlockableItem::new("com.ebb.app".to_string(), true),
-    ]
-}
-
-fn get_browser_exceptions() -> Vec<BlockableItem> {
-    vec![
-        BlockableItem::new("com.google.Chrome".to_string(), false),
-        BlockableItem::new("com.google.Chrome.beta".to_string(), false),
-        BlockableItem::new("com.google.Chrome.dev".to_string(), false),
-        BlockableItem::new("com.google.Chrome.canary".to_string(), false),
-        BlockableItem::new("com.apple.Safari".to_string(), false),
-        BlockableItem::new("com.microsoft.Edge".to_string(), false),
-        BlockableItem::new("com.brave.Browser".to_string(), false),
-        BlockableItem::new("company.thebrowser.Browser".to_string(), false),
-    ]
-}
-
-fn get_exceptions(has_website_url: bool, blocklist_mode: bool) -> Vec<BlockableItem> {
-    let mut exceptions = Vec::new();
-    if blocklist_mode {
-        return exceptions;
-    }
-    if has_website_url {
-        exceptions.extend(get_browser_exceptions());
-    }
-    exceptions.extend(get_system_exceptions());
-    exceptions
-}
-
 pub fn platform_start_blocking(
-    blocked_apps: &Vec<BlockableItem>,
+    blocked_apps: &[BlockableItem],
     redirect_url: &str,
     blocklist_mode: bool,
-) -> bool {
-    let mut all_items = blocked_apps.to_vec();
-
-    let has_website_url = has_website_url(blocked_apps);
-    let exceptions = get_exceptions(has_website_url, blocklist_mode);
-    all_items.extend(exceptions);
-
-    let c_urls: Vec<CString> = all_items
+) -> Result<(), MonitorError> {
+    let c_urls = blocked_apps
         .iter()
-        .map(|app| CString::new(app.app_external_id.as_str()).unwrap())
-        .collect();
+        .map(|app| c_string(&app.app_external_id))
+        .collect::<Result<Vec<CString>, MonitorError>>()?;
 
     let c_urls_ptrs: Vec<*const c_char> = c_urls.iter().map(|url| url.as_ptr()).collect();
 
-    let c_redirect_url = CString::new(redirect_url).unwrap();
+    let c_redirect_url = c_string(redirect_url)?;
 
     log::trace!("platform_start_blocking bindings call");
-    unsafe {
+    let started = unsafe {
         crate::bindings::start_blocking(
             c_urls_ptrs.as_ptr(),
             c_urls_ptrs.len() as i32,
             c_redirect_url.as_ptr(),
             blocklist_mode,
         )
+    };
+    if !started {
+        return Err(MonitorError::PlatformError(
+            "Native blocker rejected the configuration".to_string(),
+        ));
     }
+    Ok(())
 }
 
 pub fn platform_stop_blocking() {
@@ -127,109 +88,69 @@ pub fn platform_stop_blocking() {
     }
 }
 
-pub fn platform_get_application_icon_data(bundle_id: &str) -> Option<String> {
-    unsafe {
-        let c_bundle_id = CString::new(bundle_id).ok()?;
-        let c_data = crate::bindings::get_app_icon_data(c_bundle_id.as_ptr());
-        if c_data.is_null() {
-            return None;
-        }
-
-        let data = CStr::from_ptr(c_data).to_str().ok()?.to_owned();
-        crate::bindings::free_icon_data(c_data);
-        Some(data)
-    }
+pub fn is_editing_url() -> bool {
+    unsafe { crate::bindings::is_url_element_focused() }
 }
 
-#[cfg(test)]
-mod tests {
-    use super::*;
-
-    #[test]
-    fn test_has_website_url() {
-        let blocked_apps = vec![
-            BlockableItem::new("com.example.app".to_string(), false),
-            BlockableItem::new("google.com".to_string(), true),
-        ];
-        assert!(has_website_url(&blocked_apps));
-    }
-
-    #[test]
-    fn test_has_website_url_false() {
-        let blocked_apps = vec![
-            BlockableItem::new("com.example.app".to_string(), false),
-            BlockableItem::new("google.com".to_string(), false),
-        ];
-        assert!(!has_website_url(&blocked_apps));
-    }
-
-    #[test]
-    fn test_get_browser_exceptions() {
-        let exceptions = get_browser_exceptions();
-        let contains_chrome = exceptions
-            .iter()
-            .any(|app| app.app_external_id == "com.google.Chrome");
-        assert!(contains_chrome);
-    }
-
-    #[test]
-    fn test_get_system_exceptions() {
-        let exceptions = get_system_exceptions();
-        let contains_finder = exceptions
-            .iter()
-            .any(|app| app.app_external_id == "com.apple.SystemFinder");
-        assert!(contains_finder);
-    }
+// The blocked event is sent by the shared detection code, so the native side
+// is told not to report it through app_blocked_callback
+pub fn close_app(window: &FocusedWindow) -> bool {
+    let Some(bundle_id) = &window.bundle_id else {
+        return false;
+    };
+    let c_bundle_id = CString::new(bundle_id.as_str()).unwrap_or_default();
+    unsafe { crate::bindings::close_app(c_bundle_id.as_ptr(), false) }
+}
 
-    #[test]
-    fn test_get_exceptions_allowlist_mode_with_browser() {
-        let exceptions = get_exceptions(true, false);
-        let contains_chrome = exceptions
-            .iter()
-            .any(|app| app.app_external_id == "com.google.Chrome");
-        let contains_finder = exceptions
-            .iter()
-            .any(|app| app.app_external_id == "com.apple.SystemFinder");
-        assert!(contains_chrome);
-        assert!(contains_finder);
+/// One window per regular app that is running, for the sweep at
+/// `start_blocking`
+pub fn running_apps() -> Vec<FocusedWindow> {
+    let mut count = 0;
+    unsafe {
+        let apps = crate::bindings::get_running_apps(&mut count);
+        if apps.is_null() {
+            return Vec::new();
+        }
+        let windows = (0..count as usize)
+            .map(|i| {
+                let app = &*apps.add(i);
+                FocusedWindow {
+                    app_name: CStr::from_ptr(app.app_name).to_string_lossy().into_owned(),
+                    title: String::new(),
+                    bundle_id: Some(CStr::from_ptr(app.bundle_id).to_string_lossy().into_owned()),
+                    url: None,
+                    pid: None,
+                }
+            })
+            .collect();
+        crate::bindings::free_running_apps(apps, count);
+        windows
     }
+}
 
-    #[test]
-    fn test_get_exceptions_allowlist_mode_no_browser() {
-        let exceptions = get_exceptions(false, false);
-        let contains_chrome = exceptions
-            .iter()
-            .any(|app| app.app_external_id == "com.google.Chrome");
-        let contains_finder = exceptions
-            .iter()
-            .any(|app| app.app_external_id == "com.apple.SystemFinder");
-        assert!(!contains_chrome);
-        assert!(contains_finder);
-    }
+pub fn redirect_to_block_page(redirect_url: &str) -> bool {
+    let Ok(c_redirect_url) = c_string(redirect_url) else {
+        return false;
+    };
+    unsafe { crate::bindings::redirect_to_vibes_page(c_redirect_url.as_ptr()) }
+}
 
-    #[test]
-    fn test_get_exceptions_blocklist_mode_with_browser() {
-        let exceptions = get_exceptions(true, true);
-        let contains_chrome = exceptions
-            .iter()
-            .any(|app| app.app_external_id == "com.google.Chrome");
-        let contains_finder = exceptions
-            .iter()
-            .any(|app| app.app_external_id == "com.apple.SystemFinder");
-        assert!(!contains_chrome);
-        assert!(!contains_finder);
-    }
+pub fn platform_get_application_icon_data(bundle_id: &str) -> Result<String, MonitorError> {
+    let c_bundle_id = c_string(bundle_id)?;
+    unsafe {
+        let c_data = crate::bindings::get_app_icon_data(c_bundle_id.as_ptr());
+        if c_data.is_null() {
+            return Err(MonitorError::IconNotFound(bundle_id.to_string()));
+        }
 
-    #[test]
-    fn test_get_exceptions_blocklist_mode_no_browser() {
-        let exceptions = get_exceptions(false, true);
-        let contains_chrome = exceptions
-            .iter()
-            .any(|app| app.app_external_id == "com.google.Chrome");
-        let contains_finder = exceptions
-            .iter()
-            .any(|app| app.app_external_id == "com.apple.SystemFinder");
-        assert!(!contains_chrome);
-        assert!(!contains_finder);
+        let data = CStr::from_ptr(c_data)
+            .to_str()
+            .map(str::to_owned)
+            .map_err(|source| MonitorError::FfiStringDecode {
+                field: "icon data",
+                source,
+            });
+        crate::bindings::free_icon_data(c_data);
+        data
     }
 }
@@ -3,15 +3,106 @@ mod events;
 mod monitoring;
 mod ui;
 
-pub use blocking::{
-    platform_get_application_icon_data, platform_start_blocking, platform_stop_blocking,
-};
-pub use events::platform_run_loop_cycle;
-pub use monitoring::{
-    platform_detect_changes, platform_has_accessibility_permissions,
-    platform_request_accessibility_permissions, platform_start_monitoring,
-};
-pub use ui::{
-    platform_create_typewriter_window, platform_remove_typewriter_window,
-    platform_sync_typewriter_window_order,
-};
+use crate::platform::{FocusedWindow, PlatformBackend};
+use crate::{BlockableItem, MonitorError, Platform};
+use std::sync::Mutex;
+use std::thread::JoinHandle;
+use std::time::Duration;
+
+/// Native macOS implementation backed by the Objective-C bindings
+#[derive(Default)]
+pub struct MacosBackend {
+    // Thread running the event tap's run loop while monitoring
+    event_tap_thread: Mutex<Option<JoinHandle<()>>>,
+}
+
+impl PlatformBackend for MacosBackend {
+    fn platform(&self) -> Platform {
+        Platform::Mac
+    }
+
+    fn name(&self) -> &'static str {
+        "macos"
+    }
+
+    fn start_monitoring(&self) -> Result<(), MonitorError> {
+        let mut event_tap_thread = self.event_tap_thread.lock().unwrap();
+        if event_tap_thread.is_some() {
+            return Err(MonitorError::AlreadyRunning);
+        }
+        *event_tap_thread = Some(monitoring::platform_start_monitoring()?);
+        Ok(())
+    }
+
+    fn stop_monitoring(&self) -> Result<(), MonitorError> {
+        if let Some(thread) = self.event_tap_thread.lock().unwrap().take() {
+            monitoring::platform_stop_monitoring(thread);
+        }
+        Ok(())
+    }
+
+    fn focused_window(&self) -> Result<Option<FocusedWindow>, MonitorError> {
+        monitoring::focused_window()
+    }
+
+    fn start_blocking(
+        &self,
+        blocked_apps: &[BlockableItem],
+        redirect_url: &str,
+        blocklist_mode: bool,
+    ) -> Result<(), MonitorError> {
+        blocking::platform_start_blocking(blocked_apps, redirect_url, blocklist_mode)
+    }
+
+    fn stop_blocking(&self) {
+        blocking::platform_stop_blocking()
+    }
+
+    fn is_editing_url(&self) -> bool {
+        blocking::is_editing_url()
+    }
+
+    fn close_app(&self, window: &FocusedWindow) -> bool {
+        blocking::close_app(window)
+    }
+
+    fn running_apps(&self) -> Vec<FocusedWindow> {
+        blocking::running_apps()
+    }
+
+    fn redirect_to_block_page(&self, redirect_url: &str) -> bool {
+        blocking::redirect_to_block_page(redirect_url)
+    }
+
+    fn idle_time(&self) -> Option<Duration> {
+        monitoring::platform_idle_time()
+    }
+
+    fn has_accessibility_permissions(&self) -> bool {
+        monitoring::platform_has_accessibility_permissions()
+    }
+
+    fn request_accessibility_permissions(&self) -> bool {
+        monitoring::platform_request_accessibility_permissions()
+    }
+
+    fn get_application_icon_data(&self, bundle_id: &str) -> Result<String, MonitorError> {
+        blocking::platform_get_application_icon_data(bundle_id)
+    }
+
+    fn run_loop_cycle(&self) {
+        events::platform_run_loop_cycle()
+    }
+
+    fn create_typewriter_window(&self, opacity: f64) {
+        ui::platform_create_typewriter_window(opacity)
+    }
+
+    fn sync_typewriter_window_order(&self) {
+        ui::platform_sync_typewriter_window_order()
+    }
+
+    fn remove_typewriter_window(&self) {
+        ui::platform_remove_typewriter_window()
+    }
+}
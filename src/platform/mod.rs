@@ -1,61 +1,327 @@
 #[cfg(target_os = "macos")]
 mod macos;
-use std::sync::Arc;
 
-#[cfg(target_os = "macos")]
-pub(crate) use macos::*;
+#[cfg(target_os = "linux")]
+mod linux;
 
 #[cfg(target_os = "windows")]
 mod windows;
-#[cfg(target_os = "windows")]
-pub(crate) use windows::*;
 
-use crate::{BlockableItem, Monitor, MonitorError};
+#[cfg(any(test, feature = "testing"))]
+mod fake;
+
+pub(crate) mod browser_tabs;
+mod budgets;
+mod events;
+mod handle;
+mod monitoring;
+mod scheduler;
+mod unlocks;
+
+use once_cell::sync::Lazy;
+use std::sync::{Arc, RwLock};
+use std::time::Duration;
+
+use crate::{
+    blocking, BlockDecision, BlockList, BlockProfile, BlockableItem, BudgetUsage, Budgets,
+    ExceptionPolicy, Monitor, MonitorError, Platform, RuleSource, Unlock, UnlockFriction,
+};
+
+#[cfg(any(test, feature = "testing"))]
+pub use fake::FakeBackend;
+
+// Serializes tests that touch the global monitor, backend or activity state
+#[cfg(test)]
+pub(crate) static TEST_LOCK: std::sync::Mutex<()> = std::sync::Mutex::new(());
+
+/// The window that currently has focus, as reported by a backend
+#[derive(Debug, Clone, PartialEq)]
+pub struct FocusedWindow {
+    pub app_name: String,
+    pub title: String,
+    pub bundle_id: Option<String>,
+    pub url: Option<String>,
+    pub pid: Option<u32>,
+}
+
+/// Everything the monitor needs from the operating system. The change
+/// detection, blocking decisions and event buffering are shared and only call
+/// into the backend for the platform specific primitives.
+pub trait PlatformBackend: Send + Sync {
+    /// Platform reported in window events
+    fn platform(&self) -> Platform;
+
+    /// Short backend name, e.g. "x11", used as the source of event envelopes
+    fn name(&self) -> &'static str;
+
+    /// Registers input hooks and, for event driven backends, starts listening
+    /// for focus changes. Input callbacks go to `record_mouse_event` and
+    /// `record_keyboard_event`.
+    fn start_monitoring(&self) -> Result<(), MonitorError>;
+
+    /// Removes the input hooks and stops any listener threads started by
+    /// `start_monitoring`, so monitoring can be started again later
+    fn stop_monitoring(&self) -> Result<(), MonitorError>;
+
+    /// Returns the currently focused window, if any
+    fn focused_window(&self) -> Result<Option<FocusedWindow>, MonitorError>;
+
+    /// True when focus changes are pushed through `handle_focused_window` by
+    /// the backend, so `detect_changes` does not need to poll for them
+    fn is_focus_event_driven(&self) -> bool {
+        false
+    }
+
+    /// Receives the blocked (or allowed) items with the built-in exceptions
+    /// already added, after their identifiers were validated
+    fn start_blocking(
+        &self,
+        blocked_apps: &[BlockableItem],
+        redirect_url: &str,
+        blocklist_mode: bool,
+    ) -> Result<(), MonitorError>;
+
+    fn stop_blocking(&self);
+
+    /// Called after an unlock was granted or expired, for backends that
+    /// enforce blocking outside of `detect_changes`, e.g. in the hosts file
+    fn unlocks_changed(&self) {}
+
+    /// True while the user is typing in the browser's address bar, when a
+    /// blocked url is not redirected yet
+    fn is_editing_url(&self) -> bool {
+        false
+    }
+
+    /// Identifiers the window's app is matched against the blocking rules
+    /// by. Defaults to the bundle id.
+    fn app_ids(&self, window: &FocusedWindow) -> Vec<String> {
+        window.bundle_id.iter().cloned().collect()
+    }
+
+    /// One window per app that is running, for closing the blocked ones
+    /// when blocking starts. Defaults to none, leaving blocked apps to be
+    /// closed when they get focus.
+    fn running_apps(&self) -> Vec<FocusedWindow> {
+        Vec::new()
+    }
+
+    /// Closes the app owning the window. Returns true if it was closed.
+    fn close_app(&self, window: &FocusedWindow) -> bool;
+
+    /// Navigates the focused browser tab to `redirect_url`, the block page
+    /// of the list or budget that blocked it
+    fn redirect_to_block_page(&self, redirect_url: &str) -> bool;
+
+    /// Time since the last user input as tracked by the operating system.
+    /// When None, idle detection uses the time since the last input callback.
+    fn idle_time(&self) -> Option<Duration> {
+        None
+    }
+
+    fn has_accessibility_permissions(&self) -> bool {
+        true
+    }
+
+    fn request_accessibility_permissions(&self) -> bool {
+        true
+    }
+
+    /// The app's icon as a `data:image/png;base64,...` url
+    fn get_application_icon_data(&self, _bundle_id: &str) -> Result<String, MonitorError> {
+        Err(MonitorError::Unsupported("icon lookup"))
+    }
+
+    fn run_loop_cycle(&self) {}
+
+    fn create_typewriter_window(&self, _opacity: f64) {}
 
-pub fn start_monitoring(monitor: Arc<Monitor>) {
-    platform_start_monitoring(monitor);
+    fn sync_typewriter_window_order(&self) {}
+
+    fn remove_typewriter_window(&self) {}
+}
+
+static BACKEND: Lazy<RwLock<Arc<dyn PlatformBackend>>> =
+    Lazy::new(|| RwLock::new(default_backend()));
+
+/// The backend for the current platform. On Linux this picks between X11 and
+/// sway at runtime, see `OS_MONITOR_BACKEND`.
+pub fn default_backend() -> Arc<dyn PlatformBackend> {
+    #[cfg(target_os = "macos")]
+    {
+        Arc::new(macos::MacosBackend::default())
+    }
+    #[cfg(target_os = "linux")]
+    {
+        linux::select_backend()
+    }
+}
+
+/// Replaces the backend used by all of the free functions in this module.
+/// Call before `start_monitoring`.
+pub fn set_backend(backend: Arc<dyn PlatformBackend>) {
+    *BACKEND.write().unwrap() = backend;
+}
+
+pub(crate) fn backend() -> Arc<dyn PlatformBackend> {
+    BACKEND.read().unwrap().clone()
 }
 
+pub use events::{record_keyboard_event, record_mouse_event};
+pub use handle::MonitorHandle;
+pub use monitoring::handle_focused_window;
+pub(crate) use monitoring::{handle_browser_tab, is_host_blocked, last_blocked_site};
+
+/// Why the url is blocked and how long that lasts, for the block page. The
+/// duration is None when blocking lasts until it is turned off.
+pub(crate) fn block_status(url: &str) -> Option<(BlockDecision, Option<Duration>)> {
+    let (decision, _) = monitoring::blocked_decision(url)?;
+    let (now, _) = unlocks::now();
+    let from_budget = decision
+        .matched
+        .as_ref()
+        .is_some_and(|matched| matched.source == RuleSource::Budget);
+    let ends_at = if from_budget {
+        budgets::resets_at(now)
+    } else {
+        scheduler::active_until(now)
+    };
+    let remaining = ends_at.and_then(|ends_at| (ends_at - now).to_std().ok());
+    Some((decision, remaining))
+}
+
+/// Starts monitoring with the current backend. Monitoring runs until the
+/// returned handle is stopped or dropped.
+pub fn start_monitoring(monitor: Arc<Monitor>) -> Result<MonitorHandle, MonitorError> {
+    let mut handle = MonitorHandle::new(backend(), monitor);
+    handle.start()?;
+    Ok(handle)
+}
+
+/// Blocks the listed apps and sites, or everything else in allowlist mode.
+/// Apps in `exceptions` are never blocked in allowlist mode, pass
+/// `&ExceptionPolicy::default()` for the platform's defaults.
 pub fn start_blocking(
     blocked_apps: &[BlockableItem],
     redirect_url: &str,
     blocklist_mode: bool,
-) -> bool {
-    platform_start_blocking(&mut blocked_apps.to_vec(), redirect_url, blocklist_mode)
+    exceptions: &ExceptionPolicy,
+) -> Result<(), MonitorError> {
+    blocking::validate(blocked_apps, redirect_url)?;
+    let all_items = blocking::with_exceptions(blocked_apps, blocklist_mode, exceptions);
+    // Installed first so apps the backend closes right away can be explained
+    let previous = monitoring::set_blocking(Some(monitoring::ActiveBlocking {
+        configured: BlockList::new(blocked_apps, redirect_url, blocklist_mode),
+        effective: BlockList::with_exceptions(
+            blocked_apps,
+            redirect_url,
+            blocklist_mode,
+            exceptions,
+        ),
+    }));
+    let backend = backend();
+    if let Err(e) = backend.start_blocking(&all_items, redirect_url, blocklist_mode) {
+        monitoring::set_blocking(previous);
+        return Err(e);
+    }
+    monitoring::close_blocked_apps(backend.as_ref());
+    Ok(())
 }
 
 pub fn stop_blocking() {
-    platform_stop_blocking()
+    backend().stop_blocking();
+    monitoring::set_blocking(None);
+}
+
+/// Blocks with `profile` while its schedule is active, checked on every
+/// `detect_changes` against the monitor's clock. Replaces the previous
+/// profile. Calling `start_blocking`/`stop_blocking` meanwhile overrides the
+/// schedule until its next transition.
+pub fn schedule_blocking(profile: BlockProfile) -> Result<(), MonitorError> {
+    blocking::validate(&profile.blocked_apps, &profile.redirect_url)?;
+    scheduler::set_schedule(Some(profile));
+    Ok(())
+}
+
+/// Removes the scheduled profile, stopping the blocking it turned on
+pub fn clear_block_schedule() {
+    scheduler::set_schedule(None);
+}
+
+/// Tracks focus time against daily `budgets` on every `detect_changes` and
+/// blocks items whose budget is used up, alongside any `start_blocking` list.
+/// Replaces the previous budgets and their usage.
+pub fn set_budgets(budgets: Budgets) -> Result<(), MonitorError> {
+    let items: Vec<BlockableItem> = budgets.items().cloned().collect();
+    blocking::validate(&items, budgets.redirect_url())?;
+    self::budgets::set_budgets(Some(budgets));
+    Ok(())
+}
+
+/// Stops tracking and unblocks everything blocked by a budget
+pub fn clear_budgets() {
+    budgets::set_budgets(None);
+}
+
+/// Today's usage of each budget
+pub fn budget_usage() -> Vec<BudgetUsage> {
+    budgets::usage()
+}
+
+/// Asks for `item` to be exempt from all blocking (lists, schedules and
+/// budgets) for `duration`. The exemption is granted once `friction` is
+/// overcome: a delay passes on a later `detect_changes`, or the phrase is
+/// typed back to `confirm_unlock`. Returns the id for `confirm_unlock`.
+pub fn request_unlock(
+    item: BlockableItem,
+    friction: UnlockFriction,
+    duration: Duration,
+) -> Result<u64, MonitorError> {
+    blocking::validate_item(&item)?;
+    Ok(unlocks::request(item, friction, duration))
+}
+
+/// Grants a `UnlockFriction::Phrase` unlock when `typed` matches the phrase
+pub fn confirm_unlock(id: u64, typed: &str) -> Result<(), MonitorError> {
+    unlocks::confirm(id, typed)?;
+    monitoring::blocking_changed();
+    backend().unlocks_changed();
+    Ok(())
+}
+
+/// Unlocks that are pending or granted and not expired yet
+pub fn unlocks() -> Vec<Unlock> {
+    unlocks::list()
 }
 
 pub fn detect_changes() -> Result<(), MonitorError> {
-    platform_detect_changes()
+    monitoring::detect_changes(backend())
 }
 
 pub fn has_accessibility_permissions() -> bool {
-    platform_has_accessibility_permissions()
+    backend().has_accessibility_permissions()
 }
 
 pub fn request_accessibility_permissions() -> bool {
-    platform_request_accessibility_permissions()
+    backend().request_accessibility_permissions()
 }
 
-pub fn get_application_icon_data(bundle_id: &str) -> Option<String> {
-    platform_get_application_icon_data(bundle_id)
+pub fn get_application_icon_data(bundle_id: &str) -> Result<String, MonitorError> {
+    backend().get_application_icon_data(bundle_id)
 }
 
 pub fn run_loop_cycle() {
-    platform_run_loop_cycle()
+    backend().run_loop_cycle()
 }
 
 pub fn create_typewriter_window(opacity: f64) {
-    platform_create_typewriter_window(opacity)
+    backend().create_typewriter_window(opacity)
 }
 
 pub fn sync_typewriter_window_order() {
-    platform_sync_typewriter_window_order();
+    backend().sync_typewriter_window_order();
 }
 
 pub fn remove_typewriter_window() {
-    platform_remove_typewriter_window();
+    backend().remove_typewriter_window();
 }
@@ -0,0 +1,604 @@
+use super::{browser_tabs, budgets, events, scheduler, unlocks};
+use super::{FocusedWindow, PlatformBackend};
+use crate::event::WindowEvent;
+use crate::{BlockDecision, BlockList, BlockedApp, BlockedAppEvent, Monitor, MonitorError};
+use once_cell::sync::Lazy;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+
+pub static MONITOR: Lazy<Mutex<Option<Arc<Monitor>>>> = Lazy::new(|| Mutex::new(None));
+
+pub struct WindowTitle {
+    pub app_name: String,
+    pub title: String,
+}
+
+pub static FOCUSED_WINDOW: Mutex<WindowTitle> = Mutex::new(WindowTitle {
+    app_name: String::new(),
+    title: String::new(),
+});
+
+// The window last passed to handle_focused_window, checked again when
+// blocking changes on backends that only report focus changes
+static LAST_FOCUSED: Mutex<Option<FocusedWindow>> = Mutex::new(None);
+
+// Bumped whenever what is blocked may have changed: blocking started or
+// stopped, a budget ran out or reset, an unlock was granted or expired
+static BLOCKING_GENERATION: AtomicU64 = AtomicU64::new(0);
+static CHECKED_GENERATION: AtomicU64 = AtomicU64::new(0);
+
+pub fn blocking_changed() {
+    BLOCKING_GENERATION.fetch_add(1, Ordering::SeqCst);
+}
+
+/// The configuration from the last successful `start_blocking`
+pub struct ActiveBlocking {
+    /// As passed in, reported in snapshots
+    pub configured: BlockList,
+    /// With the built-in exceptions, used for matching
+    pub effective: BlockList,
+}
+
+/// The url last redirected to a block page, shown by `BlockPageServer` when
+/// the redirect doesn't say which site was blocked
+static LAST_BLOCKED_SITE: Mutex<Option<String>> = Mutex::new(None);
+
+pub fn last_blocked_site() -> Option<String> {
+    LAST_BLOCKED_SITE.lock().unwrap().clone()
+}
+
+// Kept outside the monitor so a monitor started afterwards can report it.
+// Locked before MONITOR when both are needed.
+static BLOCKING: Mutex<Option<ActiveBlocking>> = Mutex::new(None);
+
+/// Replaces the active blocking configuration, returning the previous one
+pub fn set_blocking(blocking: Option<ActiveBlocking>) -> Option<ActiveBlocking> {
+    let mut blocking_guard = BLOCKING.lock().unwrap();
+    if let Some(monitor) = MONITOR.lock().unwrap().as_ref() {
+        monitor.set_blocking(blocking.as_ref().map(|b| b.configured.clone()));
+    }
+    blocking_changed();
+    std::mem::replace(&mut *blocking_guard, blocking)
+}
+
+/// How the active configuration treats the url or app id, None when
+/// blocking is off
+pub fn block_decision(external_app_id: &str) -> Option<BlockDecision> {
+    BLOCKING
+        .lock()
+        .unwrap()
+        .as_ref()
+        .map(|blocking| blocking.effective.decide(external_app_id))
+}
+
+/// Why the url or app id is blocked and the page to redirect it to, from
+/// the active blocking or a used up budget. None while an unlock covers it.
+pub fn blocked_decision(external_app_id: &str) -> Option<(BlockDecision, String)> {
+    if unlocks::is_exempt(external_app_id) {
+        return None;
+    }
+    let redirect_url = BLOCKING
+        .lock()
+        .unwrap()
+        .as_ref()
+        .map(|blocking| blocking.effective.redirect_url().to_string());
+    match (block_decision(external_app_id), redirect_url) {
+        (Some(decision), Some(redirect_url)) if decision.blocked => Some((decision, redirect_url)),
+        _ => budgets::exhausted(external_app_id),
+    }
+}
+
+/// Whether lookups of the host name should fail, see `DnsSinkhole`. Rules
+/// are matched by their host part, see `BlockList::is_host_blocked`. An
+/// allowlist without any sites leaves names alone, it would cut every
+/// service on the machine off.
+pub fn is_host_blocked(host: &str) -> bool {
+    if unlocks::is_exempt(host) {
+        return false;
+    }
+    let blocked = BLOCKING.lock().unwrap().as_ref().is_some_and(|blocking| {
+        let filters_sites = blocking.configured.blocklist_mode()
+            || crate::blocking::has_website_url(blocking.configured.items());
+        filters_sites && blocking.effective.is_host_blocked(host)
+    });
+    blocked || budgets::exhausted(host).is_some()
+}
+
+/// Like `blocked_decision` for an app known by several ids. A blocklist
+/// blocks it if any id matches, an allowlist only if none of them does.
+fn blocked_app_decision(app_ids: &[String]) -> Option<(BlockDecision, &str)> {
+    if app_ids.iter().any(|id| unlocks::is_exempt(id)) {
+        return None;
+    }
+    let decisions: Vec<(BlockDecision, &str)> = app_ids
+        .iter()
+        .filter_map(|id| block_decision(id).map(|decision| (decision, id.as_str())))
+        .collect();
+    let blocked = match decisions.first() {
+        Some((first, _)) if first.blocklist_mode => {
+            decisions.into_iter().find(|(decision, _)| decision.blocked)
+        }
+        Some(_) if decisions.iter().all(|(decision, _)| decision.blocked) => {
+            decisions.into_iter().next()
+        }
+        _ => None,
+    };
+    blocked.or_else(|| {
+        app_ids
+            .iter()
+            .find_map(|id| budgets::exhausted(id).map(|(decision, _)| (decision, id.as_str())))
+    })
+}
+
+fn send_blocked_app(blocked_app: BlockedApp) {
+    let monitor_guard = MONITOR.lock().unwrap();
+    if let Some(monitor) = monitor_guard.as_ref() {
+        monitor.send_app_blocked_event(BlockedAppEvent {
+            blocked_apps: vec![blocked_app],
+        });
+    }
+}
+
+/// Closes the running apps the active blocking blocks, matched by the same
+/// rules as focused apps, and reports them in a single event
+pub fn close_blocked_apps(backend: &dyn PlatformBackend) {
+    let blocked_apps: Vec<BlockedApp> = backend
+        .running_apps()
+        .into_iter()
+        .filter_map(|window| {
+            let app_ids = backend.app_ids(&window);
+            let (decision, app_id) = blocked_app_decision(&app_ids)?;
+            log::info!("App is blocked, closing running app: {:?}", app_id);
+            backend.close_app(&window).then(|| BlockedApp {
+                app_name: window.app_name.clone(),
+                app_external_id: app_id.to_string(),
+                is_site: false,
+                decision: Some(decision),
+            })
+        })
+        .collect();
+    if blocked_apps.is_empty() {
+        return;
+    }
+    if let Some(monitor) = MONITOR.lock().unwrap().as_ref() {
+        monitor.send_app_blocked_event(BlockedAppEvent { blocked_apps });
+    }
+}
+
+/// Applies blocking to the focused window and sends a window event if it
+/// changed since the last call. Polling backends get here through
+/// `detect_changes`, event driven backends call it directly.
+pub fn handle_focused_window(backend: &dyn PlatformBackend, mut window: FocusedWindow) {
+    log::trace!(
+        "  handle_focused_window window_title: {:?} {:?}",
+        window.app_name,
+        window.title
+    );
+    log::trace!("  handle_focused_window bundle_id: {:?}", window.bundle_id);
+    log::trace!("  handle_focused_window url: {:?}", window.url);
+    *LAST_FOCUSED.lock().unwrap() = Some(window.clone());
+
+    // Browsers connected through the native messaging bridge report their
+    // active tab, whose url and title fill in the window's and which is
+    // redirected through the extension
+    let tab = window.pid.and_then(browser_tabs::focused_tab);
+    if let (None, Some(tab)) = (&window.url, &tab) {
+        window.url = Some(tab.url.clone());
+        if !tab.title.is_empty() {
+            window.title = tab.title.clone();
+        }
+    }
+    if let Some(url) = &window.url {
+        if let Some((decision, redirect_url)) =
+            blocked_decision(url).filter(|_| !backend.is_editing_url())
+        {
+            log::info!("Url is blocked, redirecting to block page: {}", url);
+            *LAST_BLOCKED_SITE.lock().unwrap() = Some(url.clone());
+            let redirect_result = match (window.pid, &tab) {
+                (Some(pid), Some(tab)) if tab.url == *url => {
+                    browser_tabs::redirect(pid, tab.id, &redirect_url)
+                }
+                _ => backend.redirect_to_block_page(&redirect_url),
+            };
+            send_blocked_app(BlockedApp {
+                app_name: window.app_name.clone(),
+                app_external_id: url.clone(),
+                is_site: true,
+                decision: Some(decision),
+            });
+            log::info!("Redirect result: {}", redirect_result);
+        }
+    }
+    let app_ids = backend.app_ids(&window);
+    if let Some((decision, app_id)) = blocked_app_decision(&app_ids) {
+        log::info!("App is blocked, closing app: {:?}", app_id);
+        let close_result = backend.close_app(&window);
+        if close_result {
+            send_blocked_app(BlockedApp {
+                app_name: window.app_name.clone(),
+                app_external_id: app_id.to_string(),
+                is_site: false,
+                decision: Some(decision),
+            });
+        }
+        log::info!("Close result: {}", close_result);
+    }
+
+    let mut window_title_guard = FOCUSED_WINDOW.lock().unwrap();
+    if window.app_name != window_title_guard.app_name || window.title != window_title_guard.title {
+        let monitor_guard = MONITOR.lock().unwrap();
+        if let Some(monitor) = monitor_guard.as_ref() {
+            monitor.send_window_event(WindowEvent {
+                window_title: window.title.clone(),
+                app_name: window.app_name.clone(),
+                url: window.url,
+                bundle_id: window.bundle_id,
+                platform: backend.platform(),
+            });
+        }
+    }
+    window_title_guard.title = window.title;
+    window_title_guard.app_name = window.app_name;
+}
+
+/// Redirects a tab reported by a connected browser if its url is blocked,
+/// whether or not the browser has focus
+pub fn handle_browser_tab(pid: u32, tab_id: i64, url: &str) {
+    let Some((decision, redirect_url)) = blocked_decision(url) else {
+        return;
+    };
+    // The block page itself may match a rule
+    if url.starts_with(&redirect_url) {
+        return;
+    }
+    log::info!("Tab is blocked, redirecting to block page: {}", url);
+    *LAST_BLOCKED_SITE.lock().unwrap() = Some(url.to_string());
+    send_blocked_app(BlockedApp {
+        app_name: browser_tabs::name(pid).unwrap_or_else(|| "Browser".to_string()),
+        app_external_id: url.to_string(),
+        is_site: true,
+        decision: Some(decision),
+    });
+    let redirect_result = browser_tabs::redirect(pid, tab_id, &redirect_url);
+    log::info!("Redirect result: {}", redirect_result);
+}
+
+pub fn detect_focused_window(backend: &dyn PlatformBackend) -> Result<(), MonitorError> {
+    log::trace!("detect_focused_window start");
+    match backend.focused_window()? {
+        Some(window) => handle_focused_window(backend, window),
+        None => log::trace!("  detect_focused_window no focused window"),
+    }
+    Ok(())
+}
+
+pub trait FocusedWindowDetector {
+    fn detect_focused_window(&self) -> Result<(), MonitorError>;
+}
+
+pub trait EventSender {
+    fn send_buffered_events(&self);
+    fn should_send_events(&self) -> bool;
+    fn mark_events_sent(&self);
+}
+
+pub trait IdleDetector {
+    fn detect_idle(&self);
+}
+
+pub trait ScheduleEvaluator {
+    fn evaluate_schedule(&self);
+}
+
+pub trait UsageTracker {
+    fn track_usage(&self);
+}
+
+pub trait UnlockEvaluator {
+    fn evaluate_unlocks(&self);
+}
+
+pub struct DefaultDependencies {
+    backend: Arc<dyn PlatformBackend>,
+    monitor: Option<Arc<Monitor>>,
+}
+
+impl FocusedWindowDetector for DefaultDependencies {
+    /// Event driven backends report focus changes themselves, so only the
+    /// window that already has focus is checked again when blocking changed
+    fn detect_focused_window(&self) -> Result<(), MonitorError> {
+        let generation = BLOCKING_GENERATION.load(Ordering::SeqCst);
+        let changed = CHECKED_GENERATION.swap(generation, Ordering::SeqCst) != generation;
+        if !self.backend.is_focus_event_driven() {
+            return detect_focused_window(&*self.backend);
+        }
+        if changed {
+            let last_focused = LAST_FOCUSED.lock().unwrap().clone();
+            if let Some(window) = last_focused {
+                handle_focused_window(&*self.backend, window);
+            }
+        }
+        Ok(())
+    }
+}
+
+impl EventSender for DefaultDependencies {
+    fn send_buffered_events(&self) {
+        if let Some(monitor) = &self.monitor {
+            events::send_buffered_events(monitor)
+        }
+    }
+
+    fn should_send_events(&self) -> bool {
+        self.monitor
+            .as_ref()
+            .is_some_and(|monitor| monitor.flush_due())
+    }
+
+    fn mark_events_sent(&self) {
+        if let Some(monitor) = &self.monitor {
+            monitor.mark_flushed()
+        }
+    }
+}
+
+impl IdleDetector for DefaultDependencies {
+    /// Prefers the OS idle time, which also sees input the hooks miss
+    fn detect_idle(&self) {
+        if let Some(monitor) = &self.monitor {
+            let idle_for = self
+                .backend
+                .idle_time()
+                .unwrap_or_else(events::time_since_input);
+            monitor.update_idle(idle_for);
+        }
+    }
+}
+
+impl ScheduleEvaluator for DefaultDependencies {
+    fn evaluate_schedule(&self) {
+        if let Some(monitor) = &self.monitor {
+            scheduler::evaluate(monitor);
+        }
+    }
+}
+
+impl UsageTracker for DefaultDependencies {
+    fn track_usage(&self) {
+        if let Some(monitor) = &self.monitor {
+            budgets::track(monitor);
+        }
+    }
+}
+
+impl UnlockEvaluator for DefaultDependencies {
+    fn evaluate_unlocks(&self) {
+        if let Some(monitor) = &self.monitor {
+            if unlocks::tick(monitor) {
+                self.backend.unlocks_changed();
+            }
+        }
+    }
+}
+
+pub fn detect_changes(backend: Arc<dyn PlatformBackend>) -> Result<(), MonitorError> {
+    let monitor = MONITOR.lock().unwrap().clone();
+    detect_changes_with_deps(&DefaultDependencies { backend, monitor })
+}
+
+pub fn detect_changes_with_deps<T>(deps: &T) -> Result<(), MonitorError>
+where
+    T: FocusedWindowDetector
+        + EventSender
+        + IdleDetector
+        + ScheduleEvaluator
+        + UsageTracker
+        + UnlockEvaluator,
+{
+    log::trace!("detect_changes start");
+    // Before the focus check so a schedule that just started, a budget that
+    // just ran out or an unlock that just expired applies to it
+    deps.track_usage();
+    deps.evaluate_unlocks();
+    deps.evaluate_schedule();
+    deps.detect_focused_window()?;
+    log::trace!("detected focused window");
+    deps.detect_idle();
+
+    if deps.should_send_events() {
+        log::trace!("sending buffered events");
+        deps.send_buffered_events();
+        log::trace!("sent buffered events");
+        deps.mark_events_sent();
+    }
+
+    log::trace!("detect_changes end");
+    Ok(())
+}
+
+/// Installs the monitor and starts the backend's hooks. Only one monitor can
+/// be running at a time.
+pub fn start_monitoring(
+    backend: &dyn PlatformBackend,
+    monitor: Arc<Monitor>,
+) -> Result<(), MonitorError> {
+    log::trace!("start_monitoring start");
+    // Same lock order as set_blocking, which holds BLOCKING until the
+    // monitor has the new list
+    let blocking_guard = BLOCKING.lock().unwrap();
+    let mut monitor_guard = MONITOR.lock().unwrap();
+    if monitor_guard.is_some() {
+        return Err(MonitorError::AlreadyRunning);
+    }
+    monitor.set_source(backend.name());
+    monitor.set_blocking(
+        blocking_guard
+            .as_ref()
+            .map(|blocking| blocking.configured.clone()),
+    );
+    *monitor_guard = Some(monitor);
+    // Event driven backends report the initial focus from their own thread,
+    // which needs MONITOR
+    drop(monitor_guard);
+    drop(blocking_guard);
+
+    if let Err(e) = backend.start_monitoring() {
+        log::error!("Failed to start monitoring: {}", e);
+        MONITOR.lock().unwrap().take();
+        return Err(e);
+    }
+    log::trace!("start_monitoring end");
+    Ok(())
+}
+
+/// Stops the backend's hooks, sends the activity buffered so far and clears
+/// the monitor so monitoring can be started again
+pub fn stop_monitoring(backend: &dyn PlatformBackend) -> Result<(), MonitorError> {
+    log::trace!("stop_monitoring start");
+    let monitor = MONITOR
+        .lock()
+        .unwrap()
+        .clone()
+        .ok_or(MonitorError::NotRunning)?;
+    let result = backend.stop_monitoring();
+    MONITOR.lock().unwrap().take();
+
+    events::send_buffered_events(&monitor);
+    monitor.mark_flushed();
+    let mut focused_window = FOCUSED_WINDOW.lock().unwrap();
+    focused_window.app_name.clear();
+    focused_window.title.clear();
+    LAST_FOCUSED.lock().unwrap().take();
+    log::trace!("stop_monitoring end");
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::cell::Cell;
+    use std::rc::Rc;
+
+    struct MockDependencies {
+        focused_window_detected: Rc<Cell<bool>>,
+        idle_detected: Rc<Cell<bool>>,
+        schedule_evaluated: Rc<Cell<bool>>,
+        usage_tracked: Rc<Cell<bool>>,
+        unlocks_evaluated: Rc<Cell<bool>>,
+        should_send_events: bool,
+        events_sent: Rc<Cell<bool>>,
+    }
+
+    impl FocusedWindowDetector for MockDependencies {
+        fn detect_focused_window(&self) -> Result<(), MonitorError> {
+            self.focused_window_detected.set(true);
+            Ok(())
+        }
+    }
+
+    impl IdleDetector for MockDependencies {
+        fn detect_idle(&self) {
+            self.idle_detected.set(true);
+        }
+    }
+
+    impl ScheduleEvaluator for MockDependencies {
+        fn evaluate_schedule(&self) {
+            self.schedule_evaluated.set(true);
+        }
+    }
+
+    impl UsageTracker for MockDependencies {
+        fn track_usage(&self) {
+            self.usage_tracked.set(true);
+        }
+    }
+
+    impl UnlockEvaluator for MockDependencies {
+        fn evaluate_unlocks(&self) {
+            self.unlocks_evaluated.set(true);
+        }
+    }
+
+    impl EventSender for MockDependencies {
+        fn send_buffered_events(&self) {
+            self.events_sent.set(true);
+        }
+
+        fn should_send_events(&self) -> bool {
+            self.should_send_events
+        }
+
+        fn mark_events_sent(&self) {
+            // Nothing to do in the mock
+        }
+    }
+
+    #[test]
+    fn test_platform_detect_changes() {
+        // Setup
+        let focused_window_detected = Rc::new(Cell::new(false));
+        let events_sent = Rc::new(Cell::new(false));
+
+        let idle_detected = Rc::new(Cell::new(false));
+        let schedule_evaluated = Rc::new(Cell::new(false));
+        let usage_tracked = Rc::new(Cell::new(false));
+        let unlocks_evaluated = Rc::new(Cell::new(false));
+        let deps = MockDependencies {
+            focused_window_detected: focused_window_detected.clone(),
+            idle_detected: idle_detected.clone(),
+            schedule_evaluated: schedule_evaluated.clone(),
+            usage_tracked: usage_tracked.clone(),
+            unlocks_evaluated: unlocks_evaluated.clone(),
+            should_send_events: true,
+            events_sent: events_sent.clone(),
+        };
+
+        // Execute
+        let result = detect_changes_with_deps(&deps);
+
+        // Verify
+        assert!(result.is_ok());
+        assert!(
+            focused_window_detected.get(),
+            "detect_focused_window should be called"
+        );
+        assert!(
+            events_sent.get(),
+            "send_buffered_events should be called when should_send_events is true"
+        );
+        assert!(idle_detected.get(), "detect_idle should be called");
+        assert!(
+            schedule_evaluated.get(),
+            "evaluate_schedule should be called"
+        );
+        assert!(usage_tracked.get(), "track_usage should be called");
+        assert!(unlocks_evaluated.get(), "evaluate_unlocks should be called");
+
+        // Test when events shouldn't be sent
+        let focused_window_detected = Rc::new(Cell::new(false));
+        let events_sent = Rc::new(Cell::new(false));
+
+        let deps = MockDependencies {
+            focused_window_detected: focused_window_detected.clone(),
+            idle_detected: Rc::new(Cell::new(false)),
+            schedule_evaluated: Rc::new(Cell::new(false)),
+            usage_tracked: Rc::new(Cell::new(false)),
+            unlocks_evaluated: Rc::new(Cell::new(false)),
+            should_send_events: false,
+            events_sent: events_sent.clone(),
+        };
+
+        let result = detect_changes_with_deps(&deps);
+
+        assert!(result.is_ok());
+        assert!(
+            focused_window_detected.get(),
+            "detect_focused_window should always be called"
+        );
+        assert!(
+            !events_sent.get(),
+            "send_buffered_events should not be called when should_send_events is false"
+        );
+    }
+}
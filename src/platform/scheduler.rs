@@ -0,0 +1,88 @@
+use super::monitoring::MONITOR;
+use crate::schedule::BlockProfile;
+use crate::{AppEvent, Monitor};
+use chrono::NaiveDateTime;
+use std::sync::Mutex;
+
+struct Scheduled {
+    profile: BlockProfile,
+    // Whether this profile turned blocking on
+    active: bool,
+}
+
+/// The profile from `schedule_blocking`
+static SCHEDULED: Mutex<Option<Scheduled>> = Mutex::new(None);
+
+fn send(event: AppEvent) {
+    let monitor = MONITOR.lock().unwrap().clone();
+    if let Some(monitor) = monitor {
+        monitor.send_event(event);
+    }
+}
+
+/// Replaces the scheduled profile. Blocking the old profile turned on is
+/// stopped, the new one is evaluated on the next tick.
+pub fn set_schedule(profile: Option<BlockProfile>) {
+    let mut scheduled = SCHEDULED.lock().unwrap();
+    let previous = std::mem::replace(
+        &mut *scheduled,
+        profile.map(|profile| Scheduled {
+            profile,
+            active: false,
+        }),
+    );
+    if let Some(previous) = previous.filter(|previous| previous.active) {
+        super::stop_blocking();
+        send(AppEvent::BlockingStopped {
+            profile: previous.profile.name,
+        });
+    }
+}
+
+/// Starts or stops blocking when the schedule's state at the monitor's clock
+/// differs from the last tick. A failed start is retried on the next tick.
+pub fn evaluate(monitor: &Monitor) {
+    let mut scheduled = SCHEDULED.lock().unwrap();
+    let Some(scheduled) = scheduled.as_mut() else {
+        return;
+    };
+    let now = monitor.config().clock.now();
+    let should_block = scheduled.profile.schedule.is_active(now);
+    if should_block == scheduled.active {
+        return;
+    }
+
+    let profile = &scheduled.profile;
+    if should_block {
+        log::info!("Schedule for {} started, starting blocking", profile.name);
+        let result = super::start_blocking(
+            &profile.blocked_apps,
+            &profile.redirect_url,
+            profile.blocklist_mode,
+            &profile.exceptions,
+        );
+        if let Err(e) = result {
+            log::error!("Failed to start scheduled blocking: {}", e);
+            return;
+        }
+        monitor.send_event(AppEvent::BlockingStarted {
+            profile: profile.name.clone(),
+        });
+    } else {
+        log::info!("Schedule for {} ended, stopping blocking", profile.name);
+        super::stop_blocking();
+        monitor.send_event(AppEvent::BlockingStopped {
+            profile: profile.name.clone(),
+        });
+    }
+    scheduled.active = should_block;
+}
+
+/// When the blocking the schedule turned on ends, None unless it is on
+pub fn active_until(now: NaiveDateTime) -> Option<NaiveDateTime> {
+    let scheduled = SCHEDULED.lock().unwrap();
+    scheduled
+        .as_ref()
+        .filter(|scheduled| scheduled.active)
+        .and_then(|scheduled| scheduled.profile.schedule.active_until(now))
+}
@@ -0,0 +1,64 @@
+use super::monitoring::MONITOR;
+use crate::schedule::{Clock, SystemClock};
+use crate::unlock::{Unlock, UnlockFriction, Unlocks};
+use crate::{AppEvent, BlockableItem, Monitor, MonitorError};
+use chrono::NaiveDateTime;
+use once_cell::sync::Lazy;
+use std::sync::Mutex;
+use std::time::Duration;
+
+static UNLOCKS: Lazy<Mutex<Unlocks>> = Lazy::new(|| Mutex::new(Unlocks::default()));
+
+/// The running monitor's clock, so requests line up with its ticks
+pub fn now() -> (NaiveDateTime, Option<std::sync::Arc<Monitor>>) {
+    let monitor = MONITOR.lock().unwrap().clone();
+    let now = match &monitor {
+        Some(monitor) => monitor.config().clock.now(),
+        None => SystemClock.now(),
+    };
+    (now, monitor)
+}
+
+fn send(monitor: Option<std::sync::Arc<Monitor>>, event: AppEvent) {
+    if let Some(monitor) = monitor {
+        monitor.send_event(event);
+    }
+}
+
+pub fn request(item: BlockableItem, friction: UnlockFriction, duration: Duration) -> u64 {
+    let (now, monitor) = now();
+    let (id, event) = UNLOCKS
+        .lock()
+        .unwrap()
+        .request(item, friction, duration, now);
+    send(monitor, event);
+    id
+}
+
+pub fn confirm(id: u64, typed: &str) -> Result<(), MonitorError> {
+    let (now, monitor) = now();
+    let event = UNLOCKS.lock().unwrap().confirm(id, typed, now)?;
+    send(monitor, event);
+    Ok(())
+}
+
+/// Returns true if an unlock was granted or expired
+pub fn tick(monitor: &Monitor) -> bool {
+    let events = UNLOCKS.lock().unwrap().tick(monitor.config().clock.now());
+    let changed = !events.is_empty();
+    if changed {
+        super::monitoring::blocking_changed();
+    }
+    for event in events {
+        monitor.send_event(event);
+    }
+    changed
+}
+
+pub fn is_exempt(external_app_id: &str) -> bool {
+    UNLOCKS.lock().unwrap().is_exempt(external_app_id)
+}
+
+pub fn list() -> Vec<Unlock> {
+    UNLOCKS.lock().unwrap().list()
+}
@@ -0,0 +1,338 @@
+use crate::{EventEnvelope, Monitor};
+use std::io::{self, BufRead, Write};
+use std::path::Path;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::thread::JoinHandle;
+use std::time::Duration;
+use tokio::sync::broadcast::error::TryRecvError;
+
+/// Writes envelopes as newline-delimited JSON, one per line
+pub struct Recorder<W: Write> {
+    writer: W,
+    count: usize,
+}
+
+impl<W: Write> Recorder<W> {
+    pub fn new(writer: W) -> Self {
+        Self { writer, count: 0 }
+    }
+
+    pub fn record(&mut self, envelope: &EventEnvelope) -> io::Result<()> {
+        serde_json::to_writer(&mut self.writer, envelope)?;
+        self.writer.write_all(b"\n")?;
+        // Flush per event so a crash loses at most the event being written
+        self.writer.flush()?;
+        self.count += 1;
+        Ok(())
+    }
+
+    /// Number of events written so far
+    pub fn count(&self) -> usize {
+        self.count
+    }
+
+    pub fn into_inner(self) -> W {
+        self.writer
+    }
+}
+
+/// A recorder running on its own thread, subscribed to a monitor
+pub struct RecorderHandle {
+    stop: Arc<AtomicBool>,
+    thread: JoinHandle<io::Result<usize>>,
+}
+
+impl RecorderHandle {
+    /// Subscribes to the monitor and records every event to the writer until
+    /// stopped or the monitor is dropped
+    pub fn spawn<W: Write + Send + 'static>(monitor: &Monitor, writer: W) -> io::Result<Self> {
+        let mut receiver = monitor.subscribe();
+        let mut recorder = Recorder::new(writer);
+        let stop = Arc::new(AtomicBool::new(false));
+        let thread_stop = stop.clone();
+
+        let thread = std::thread::Builder::new()
+            .name("event-recorder".into())
+            .spawn(move || loop {
+                match receiver.try_recv() {
+                    Ok(envelope) => recorder.record(&envelope)?,
+                    Err(TryRecvError::Lagged(skipped)) => {
+                        log::warn!("Recorder lagged, {} events were not recorded", skipped);
+                    }
+                    Err(TryRecvError::Closed) => return Ok(recorder.count()),
+                    Err(TryRecvError::Empty) => {
+                        if thread_stop.load(Ordering::SeqCst) {
+                            return Ok(recorder.count());
+                        }
+                        std::thread::sleep(Duration::from_millis(10));
+                    }
+                }
+            })?;
+
+        Ok(Self { stop, thread })
+    }
+
+    /// Records to a newly created (or truncated) file
+    pub fn to_file(monitor: &Monitor, path: impl AsRef<Path>) -> io::Result<Self> {
+        let file = std::fs::File::create(path)?;
+        Self::spawn(monitor, io::BufWriter::new(file))
+    }
+
+    /// Records the events already queued, then stops. Returns the number of
+    /// events written.
+    pub fn stop(self) -> io::Result<usize> {
+        self.stop.store(true, Ordering::SeqCst);
+        self.thread
+            .join()
+            .map_err(|_| io::Error::other("recorder thread panicked"))?
+    }
+}
+
+/// Reads a recording written by `Recorder`. Blank lines are skipped.
+pub fn read_recording<R: BufRead>(reader: R) -> io::Result<Vec<EventEnvelope>> {
+    let mut events = Vec::new();
+    for (index, line) in reader.lines().enumerate() {
+        let line = line?;
+        if line.trim().is_empty() {
+            continue;
+        }
+        let envelope = serde_json::from_str(&line).map_err(|e| {
+            io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("line {}: {}", index + 1, e),
+            )
+        })?;
+        events.push(envelope);
+    }
+    Ok(events)
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ReplaySpeed {
+    /// Keep the original gaps between events
+    Original,
+    /// Divide the original gaps by the factor, e.g. 60.0 replays an hour in a
+    /// minute
+    Accelerated(f64),
+    /// Send every event immediately
+    Instant,
+}
+
+impl ReplaySpeed {
+    fn delay(&self, gap: Duration) -> Option<Duration> {
+        match self {
+            ReplaySpeed::Original => Some(gap),
+            ReplaySpeed::Accelerated(factor) if *factor > 0.0 => Some(gap.div_f64(*factor)),
+            ReplaySpeed::Accelerated(_) | ReplaySpeed::Instant => None,
+        }
+    }
+}
+
+/// Re-emits recorded envelopes through the monitor, sleeping between them
+/// according to the speed. The original timestamps, sequence numbers and
+/// sources are kept. Returns the number of events sent.
+pub fn replay(events: &[EventEnvelope], monitor: &Monitor, speed: ReplaySpeed) -> usize {
+    let mut previous = None;
+    for envelope in events {
+        if let Some(previous) = previous {
+            let gap = envelope
+                .timestamp
+                .duration_since(previous)
+                .unwrap_or_default();
+            if let Some(delay) = speed.delay(gap) {
+                std::thread::sleep(delay);
+            }
+        }
+        previous = Some(envelope.timestamp);
+        let mut envelope = envelope.clone();
+        envelope.instant = std::time::Instant::now();
+        monitor.forward(envelope);
+    }
+    events.len()
+}
+
+/// Reads a recording file and replays it through the monitor
+pub fn replay_file(
+    path: impl AsRef<Path>,
+    monitor: &Monitor,
+    speed: ReplaySpeed,
+) -> io::Result<usize> {
+    let file = std::fs::File::open(path)?;
+    let events = read_recording(io::BufReader::new(file))?;
+    Ok(replay(&events, monitor, speed))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        AppEvent, BlockedApp, BlockedAppEvent, KeyboardEvent, MouseEvent, Platform, WindowEvent,
+    };
+    use std::time::{Instant, SystemTime, UNIX_EPOCH};
+
+    fn window_event(title: &str) -> AppEvent {
+        AppEvent::Window(WindowEvent {
+            app_name: "Editor".to_string(),
+            window_title: title.to_string(),
+            bundle_id: Some("org.editor".to_string()),
+            url: None,
+            platform: Platform::Linux,
+        })
+    }
+
+    fn envelope(sequence: u64, timestamp_ms: u64, event: AppEvent) -> EventEnvelope {
+        EventEnvelope {
+            sequence,
+            timestamp: UNIX_EPOCH + Duration::from_millis(timestamp_ms),
+            instant: Instant::now(),
+            source: "fake".to_string(),
+            event,
+        }
+    }
+
+    fn drain(receiver: &mut tokio::sync::broadcast::Receiver<EventEnvelope>) -> Vec<EventEnvelope> {
+        std::iter::from_fn(|| receiver.try_recv().ok()).collect()
+    }
+
+    fn titles(events: &[EventEnvelope]) -> Vec<String> {
+        events
+            .iter()
+            .filter_map(|envelope| match &envelope.event {
+                AppEvent::Window(event) => Some(event.window_title.clone()),
+                _ => None,
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_record_and_read_back() {
+        let mut recorder = Recorder::new(Vec::new());
+        recorder
+            .record(&envelope(0, 1_000, window_event("a.rs")))
+            .unwrap();
+        recorder
+            .record(&envelope(1, 2_000, AppEvent::Mouse(MouseEvent::default())))
+            .unwrap();
+        recorder
+            .record(&envelope(
+                2,
+                3_000,
+                AppEvent::AppBlocked(BlockedAppEvent {
+                    blocked_apps: vec![BlockedApp {
+                        app_name: "Slack".to_string(),
+                        app_external_id: "com.tinyspeck.slackmacgap".to_string(),
+                        is_site: false,
+                        decision: None,
+                    }],
+                }),
+            ))
+            .unwrap();
+        assert_eq!(recorder.count(), 3);
+
+        let output = recorder.into_inner();
+        assert_eq!(output.iter().filter(|b| **b == b'\n').count(), 3);
+
+        let events = read_recording(output.as_slice()).unwrap();
+        assert_eq!(events.len(), 3);
+        assert_eq!(events[1].sequence, 1);
+        assert_eq!(
+            events[1].timestamp,
+            UNIX_EPOCH + Duration::from_millis(2_000)
+        );
+        assert_eq!(events[1].source, "fake");
+        assert!(matches!(events[1].event, AppEvent::Mouse(_)));
+        assert!(
+            matches!(&events[2].event, AppEvent::AppBlocked(event) if event.blocked_apps[0].app_name == "Slack")
+        );
+    }
+
+    #[test]
+    fn test_read_recording_reports_bad_line() {
+        let input = "\n{\"sequence\": 0, \"timestamp\": 1, \"source\": \"x11\", \"event\": {\"Keyboard\": {\"keypresses\": 1, \"active_seconds\": 1}}}\nnot json\n";
+        let error = read_recording(input.as_bytes()).unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::InvalidData);
+        assert!(error.to_string().starts_with("line 3"));
+    }
+
+    #[test]
+    fn test_replay_instant_preserves_order_and_envelopes() {
+        let events: Vec<EventEnvelope> = ["one", "two", "three"]
+            .iter()
+            .enumerate()
+            .map(|(i, title)| {
+                envelope(
+                    40 + i as u64,
+                    1_000 + i as u64 * 60_000,
+                    window_event(title),
+                )
+            })
+            .collect();
+
+        let monitor = Monitor::new();
+        let mut receiver = monitor.subscribe();
+        let started = Instant::now();
+        assert_eq!(replay(&events, &monitor, ReplaySpeed::Instant), 3);
+        assert!(started.elapsed() < Duration::from_secs(1));
+
+        let received = drain(&mut receiver);
+        assert_eq!(titles(&received), vec!["one", "two", "three"]);
+        assert_eq!(received[2].sequence, 42);
+        assert_eq!(received[2].timestamp, events[2].timestamp);
+        assert_eq!(received[2].source, "fake");
+    }
+
+    #[test]
+    fn test_replay_speed_delays() {
+        let gap = Duration::from_millis(1500);
+        assert_eq!(ReplaySpeed::Original.delay(gap), Some(gap));
+        assert_eq!(
+            ReplaySpeed::Accelerated(10.0).delay(gap),
+            Some(Duration::from_millis(150))
+        );
+        assert_eq!(ReplaySpeed::Accelerated(0.0).delay(gap), None);
+        assert_eq!(ReplaySpeed::Instant.delay(gap), None);
+
+        let events = vec![
+            envelope(0, 10_000, AppEvent::Mouse(MouseEvent::default())),
+            envelope(1, 10_500, AppEvent::Keyboard(KeyboardEvent::default())),
+        ];
+        let started = Instant::now();
+        replay(&events, &Monitor::new(), ReplaySpeed::Accelerated(10.0));
+        assert!(started.elapsed() >= Duration::from_millis(50));
+    }
+
+    #[test]
+    fn test_recorder_handle_round_trip() {
+        let path = std::env::temp_dir().join(format!(
+            "os-monitor-recording-{}.ndjson",
+            std::process::id()
+        ));
+
+        let monitor = Monitor::new();
+        monitor.set_source("fake");
+        let before = SystemTime::now() - Duration::from_millis(1);
+        let recorder = RecorderHandle::to_file(&monitor, &path).unwrap();
+        monitor.send_event(window_event("recorded.rs"));
+        monitor.send_keyboard_event(KeyboardEvent {
+            keypresses: 4,
+            active_seconds: 2,
+        });
+        assert_eq!(recorder.stop().unwrap(), 2);
+
+        let replayed = Monitor::new();
+        let mut receiver = replayed.subscribe();
+        assert_eq!(
+            replay_file(&path, &replayed, ReplaySpeed::Instant).unwrap(),
+            2
+        );
+        let _ = std::fs::remove_file(&path);
+
+        let received = drain(&mut receiver);
+        assert_eq!(titles(&received), vec!["recorded.rs"]);
+        assert!(matches!(&received[1].event, AppEvent::Keyboard(event) if event.keypresses == 4));
+        assert_eq!(received[1].sequence, 1);
+        assert_eq!(received[1].source, "fake");
+        assert!(received[0].timestamp >= before);
+    }
+}
@@ -0,0 +1,284 @@
+use crate::{BlockableItem, ExceptionPolicy};
+use chrono::{Datelike, Duration as ChronoDuration, Local, NaiveDateTime, NaiveTime, Weekday};
+use std::fmt::Debug;
+use std::sync::Mutex;
+use std::time::Duration;
+
+/// Source of the local wall clock time schedules are evaluated against. Set
+/// with `MonitorBuilder::clock`, tests use a `ManualClock`.
+pub trait Clock: Debug + Send + Sync {
+    fn now(&self) -> NaiveDateTime;
+}
+
+/// The system's local time
+#[derive(Debug, Default)]
+pub struct SystemClock;
+
+impl Clock for SystemClock {
+    fn now(&self) -> NaiveDateTime {
+        Local::now().naive_local()
+    }
+}
+
+/// A clock that only moves when told to
+#[derive(Debug)]
+pub struct ManualClock {
+    now: Mutex<NaiveDateTime>,
+}
+
+impl ManualClock {
+    pub fn new(now: NaiveDateTime) -> Self {
+        Self {
+            now: Mutex::new(now),
+        }
+    }
+
+    pub fn set(&self, now: NaiveDateTime) {
+        *self.now.lock().unwrap() = now;
+    }
+
+    pub fn advance(&self, by: Duration) {
+        let by = ChronoDuration::from_std(by).unwrap_or(ChronoDuration::MAX);
+        let mut now = self.now.lock().unwrap();
+        *now = now.checked_add_signed(by).unwrap_or(NaiveDateTime::MAX);
+    }
+}
+
+impl Clock for ManualClock {
+    fn now(&self) -> NaiveDateTime {
+        *self.now.lock().unwrap()
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum ScheduleRule {
+    /// From `start` to `end` on each of `days`. An `end` at or before `start`
+    /// runs past midnight into the next day, so Friday 22:00 to 02:00 is
+    /// still active early Saturday.
+    Weekly {
+        days: Vec<Weekday>,
+        start: NaiveTime,
+        end: NaiveTime,
+    },
+    /// A single window, e.g. a focus session
+    Once {
+        start: NaiveDateTime,
+        end: NaiveDateTime,
+    },
+}
+
+impl ScheduleRule {
+    fn is_active(&self, now: NaiveDateTime) -> bool {
+        match self {
+            ScheduleRule::Weekly { days, start, end } => {
+                let time = now.time();
+                let today = days.contains(&now.weekday());
+                if start < end {
+                    today && *start <= time && time < *end
+                } else {
+                    let yesterday = days.contains(&now.weekday().pred());
+                    (today && time >= *start) || (yesterday && time < *end)
+                }
+            }
+            ScheduleRule::Once { start, end } => *start <= now && now < *end,
+        }
+    }
+
+    /// When the window active at `now` ends
+    fn active_until(&self, now: NaiveDateTime) -> Option<NaiveDateTime> {
+        if !self.is_active(now) {
+            return None;
+        }
+        Some(match self {
+            ScheduleRule::Weekly { start, end, .. } => {
+                let end_today = now.date().and_time(*end);
+                // Started today and runs past midnight
+                if start >= end && now.time() >= *start {
+                    end_today + ChronoDuration::days(1)
+                } else {
+                    end_today
+                }
+            }
+            ScheduleRule::Once { end, .. } => *end,
+        })
+    }
+}
+
+/// When a `BlockProfile` blocks. Active whenever any of its rules is.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Schedule {
+    rules: Vec<ScheduleRule>,
+}
+
+impl Schedule {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn weekly(mut self, days: &[Weekday], start: NaiveTime, end: NaiveTime) -> Self {
+        self.rules.push(ScheduleRule::Weekly {
+            days: days.to_vec(),
+            start,
+            end,
+        });
+        self
+    }
+
+    pub fn once(mut self, start: NaiveDateTime, end: NaiveDateTime) -> Self {
+        self.rules.push(ScheduleRule::Once { start, end });
+        self
+    }
+
+    /// From `now` until the next time the clock reads `end`, e.g. "until
+    /// 17:00". After 17:00 that is 17:00 tomorrow.
+    pub fn until(self, now: NaiveDateTime, end: NaiveTime) -> Self {
+        let mut until = now.date().and_time(end);
+        if until <= now {
+            until += ChronoDuration::days(1);
+        }
+        self.once(now, until)
+    }
+
+    pub fn rules(&self) -> &[ScheduleRule] {
+        &self.rules
+    }
+
+    pub fn is_active(&self, now: NaiveDateTime) -> bool {
+        self.rules.iter().any(|rule| rule.is_active(now))
+    }
+
+    /// When the active rule that runs longest ends, None if none is active.
+    /// A rule starting right as another ends isn't followed.
+    pub fn active_until(&self, now: NaiveDateTime) -> Option<NaiveDateTime> {
+        self.rules
+            .iter()
+            .filter_map(|rule| rule.active_until(now))
+            .max()
+    }
+}
+
+/// Blocking settings that are turned on and off by a schedule, see
+/// `schedule_blocking`
+#[derive(Clone, Debug)]
+pub struct BlockProfile {
+    /// Reported in `BlockingStarted`/`BlockingStopped` events
+    pub name: String,
+    pub blocked_apps: Vec<BlockableItem>,
+    pub redirect_url: String,
+    pub blocklist_mode: bool,
+    pub exceptions: ExceptionPolicy,
+    pub schedule: Schedule,
+}
+
+impl BlockProfile {
+    /// A profile with the default exceptions and an empty schedule, which
+    /// never blocks
+    pub fn new(
+        name: &str,
+        blocked_apps: &[BlockableItem],
+        redirect_url: &str,
+        blocklist_mode: bool,
+    ) -> Self {
+        Self {
+            name: name.to_string(),
+            blocked_apps: blocked_apps.to_vec(),
+            redirect_url: redirect_url.to_string(),
+            blocklist_mode,
+            exceptions: ExceptionPolicy::default(),
+            schedule: Schedule::new(),
+        }
+    }
+
+    pub fn with_exceptions(mut self, exceptions: ExceptionPolicy) -> Self {
+        self.exceptions = exceptions;
+        self
+    }
+
+    pub fn with_schedule(mut self, schedule: Schedule) -> Self {
+        self.schedule = schedule;
+        self
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::NaiveDate;
+
+    fn at(day: u32, hour: u32, minute: u32) -> NaiveDateTime {
+        // 2024-01-01 was a Monday
+        NaiveDate::from_ymd_opt(2024, 1, day)
+            .unwrap()
+            .and_hms_opt(hour, minute, 0)
+            .unwrap()
+    }
+
+    fn time(hour: u32, minute: u32) -> NaiveTime {
+        NaiveTime::from_hms_opt(hour, minute, 0).unwrap()
+    }
+
+    #[test]
+    fn test_weekly_rule() {
+        let work_hours = Schedule::new().weekly(
+            &[Weekday::Mon, Weekday::Tue, Weekday::Wed],
+            time(9, 0),
+            time(17, 0),
+        );
+        assert!(!work_hours.is_active(at(1, 8, 59)));
+        assert!(work_hours.is_active(at(1, 9, 0)));
+        assert!(work_hours.is_active(at(3, 16, 59)));
+        assert!(!work_hours.is_active(at(3, 17, 0)));
+        // Thursday
+        assert!(!work_hours.is_active(at(4, 12, 0)));
+    }
+
+    #[test]
+    fn test_weekly_rule_past_midnight() {
+        let late_night = Schedule::new().weekly(&[Weekday::Fri], time(22, 0), time(2, 0));
+        assert!(!late_night.is_active(at(5, 21, 59)));
+        assert!(late_night.is_active(at(5, 23, 0)));
+        assert!(late_night.is_active(at(6, 1, 59)));
+        assert!(!late_night.is_active(at(6, 2, 0)));
+        // Friday early morning belongs to Thursday night
+        assert!(!late_night.is_active(at(5, 1, 0)));
+    }
+
+    #[test]
+    fn test_once_and_until() {
+        let focus = Schedule::new().once(at(2, 10, 0), at(2, 11, 30));
+        assert!(!focus.is_active(at(2, 9, 59)));
+        assert!(focus.is_active(at(2, 11, 0)));
+        assert!(!focus.is_active(at(2, 11, 30)));
+
+        let until_five = Schedule::new().until(at(2, 14, 0), time(17, 0));
+        assert!(until_five.is_active(at(2, 16, 59)));
+        assert!(!until_five.is_active(at(2, 17, 0)));
+
+        // Already past 17:00, so until tomorrow
+        let overnight = Schedule::new().until(at(2, 18, 0), time(17, 0));
+        assert!(overnight.is_active(at(3, 9, 0)));
+        assert!(!overnight.is_active(at(3, 17, 0)));
+    }
+
+    #[test]
+    fn test_active_until() {
+        let schedule = Schedule::new()
+            .weekly(&[Weekday::Mon], time(9, 0), time(12, 0))
+            .weekly(&[Weekday::Fri], time(22, 0), time(2, 0))
+            .once(at(1, 11, 0), at(1, 13, 30));
+        assert_eq!(schedule.active_until(at(1, 8, 0)), None);
+        assert_eq!(schedule.active_until(at(1, 9, 30)), Some(at(1, 12, 0)));
+        assert_eq!(schedule.active_until(at(1, 11, 30)), Some(at(1, 13, 30)));
+        assert_eq!(schedule.active_until(at(5, 23, 0)), Some(at(6, 2, 0)));
+        assert_eq!(schedule.active_until(at(6, 1, 0)), Some(at(6, 2, 0)));
+    }
+
+    #[test]
+    fn test_manual_clock() {
+        let clock = ManualClock::new(at(1, 9, 0));
+        clock.advance(Duration::from_secs(90 * 60));
+        assert_eq!(clock.now(), at(1, 10, 30));
+        clock.set(at(2, 0, 0));
+        assert_eq!(clock.now(), at(2, 0, 0));
+    }
+}
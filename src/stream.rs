@@ -0,0 +1,200 @@
+use crate::event::{EventEnvelope, EventKind, Monitor};
+use futures_core::Stream;
+use std::collections::VecDeque;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+use tokio_stream::wrappers::errors::BroadcastStreamRecvError;
+use tokio_stream::wrappers::BroadcastStream;
+
+/// An item from an `EventStream`
+#[derive(Debug, Clone)]
+pub enum StreamItem {
+    Event(EventEnvelope),
+    /// The subscriber fell more than `channel_capacity` events behind and
+    /// this many events were dropped, whatever their kind
+    Lagged(u64),
+}
+
+/// Which events a stream yields, see `Monitor::stream_with`
+#[derive(Debug, Clone, Default)]
+pub struct StreamOptions {
+    kinds: Option<Vec<EventKind>>,
+    replay: usize,
+}
+
+impl StreamOptions {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Only yield events of the given kinds
+    pub fn only(mut self, kinds: &[EventKind]) -> Self {
+        self.kinds = Some(kinds.to_vec());
+        self
+    }
+
+    /// Start with up to `count` of the most recent events. Limited by the
+    /// monitor's `replay_capacity`.
+    pub fn replay(mut self, count: usize) -> Self {
+        self.replay = count;
+        self
+    }
+
+    fn accepts(&self, envelope: &EventEnvelope) -> bool {
+        self.kinds
+            .as_ref()
+            .is_none_or(|kinds| kinds.contains(&envelope.event.kind()))
+    }
+}
+
+/// A `futures::Stream` of a monitor's events. Unlike a raw receiver, missed
+/// events are reported as `StreamItem::Lagged` instead of being skipped.
+pub struct EventStream {
+    replay: VecDeque<EventEnvelope>,
+    receiver: BroadcastStream<EventEnvelope>,
+    options: StreamOptions,
+}
+
+impl Stream for EventStream {
+    type Item = StreamItem;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<StreamItem>> {
+        if let Some(envelope) = self.replay.pop_front() {
+            return Poll::Ready(Some(StreamItem::Event(envelope)));
+        }
+        loop {
+            match Pin::new(&mut self.receiver).poll_next(cx) {
+                Poll::Ready(Some(Ok(envelope))) if self.options.accepts(&envelope) => {
+                    return Poll::Ready(Some(StreamItem::Event(envelope)))
+                }
+                Poll::Ready(Some(Ok(_))) => continue,
+                Poll::Ready(Some(Err(BroadcastStreamRecvError::Lagged(missed)))) => {
+                    return Poll::Ready(Some(StreamItem::Lagged(missed)))
+                }
+                Poll::Ready(None) => return Poll::Ready(None),
+                Poll::Pending => return Poll::Pending,
+            }
+        }
+    }
+}
+
+impl Monitor {
+    /// Stream of every event sent from now on
+    pub fn stream(&self) -> EventStream {
+        self.stream_with(StreamOptions::default())
+    }
+
+    pub fn stream_with(&self, options: StreamOptions) -> EventStream {
+        let (recent, receiver) = self.subscribe_with_replay(options.replay);
+        let replay = recent
+            .into_iter()
+            .filter(|envelope| options.accepts(envelope))
+            .collect();
+        EventStream {
+            replay,
+            receiver: BroadcastStream::new(receiver),
+            options,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{AppEvent, BlockedAppEvent, KeyboardEvent, WindowEvent};
+    use futures::executor::block_on;
+    use futures::StreamExt;
+
+    fn window_event(title: &str) -> WindowEvent {
+        WindowEvent {
+            window_title: title.to_string(),
+            app_name: "Editor".to_string(),
+            url: None,
+            bundle_id: None,
+            platform: crate::Platform::Linux,
+        }
+    }
+
+    fn keypresses(keypresses: u32) -> KeyboardEvent {
+        KeyboardEvent {
+            keypresses,
+            active_seconds: 1,
+        }
+    }
+
+    fn next_event(stream: &mut EventStream) -> AppEvent {
+        match block_on(stream.next()) {
+            Some(StreamItem::Event(envelope)) => envelope.event,
+            other => panic!("expected an event, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_stream_reports_lag() {
+        let monitor = Monitor::builder().channel_capacity(2).build();
+        let mut stream = monitor.stream();
+
+        for keypress in 1..=5 {
+            monitor.send_keyboard_event(keypresses(keypress));
+        }
+
+        assert!(matches!(
+            block_on(stream.next()),
+            Some(StreamItem::Lagged(3))
+        ));
+        assert!(matches!(next_event(&mut stream), AppEvent::Keyboard(k) if k.keypresses == 4));
+        assert!(matches!(next_event(&mut stream), AppEvent::Keyboard(k) if k.keypresses == 5));
+
+        drop(monitor);
+        assert!(block_on(stream.next()).is_none());
+    }
+
+    #[test]
+    fn test_stream_filters_kinds() {
+        let monitor = Monitor::new();
+        let mut windows = monitor.stream_with(StreamOptions::new().only(&[EventKind::Window]));
+        let mut blocked = monitor.stream_with(StreamOptions::new().only(&[EventKind::AppBlocked]));
+
+        monitor.send_keyboard_event(keypresses(1));
+        monitor.send_window_event(window_event("notes.md"));
+        monitor.send_app_blocked_event(BlockedAppEvent {
+            blocked_apps: vec![],
+        });
+        monitor.send_window_event(window_event("todo.md"));
+
+        assert!(
+            matches!(next_event(&mut windows), AppEvent::Window(w) if w.window_title == "notes.md")
+        );
+        assert!(
+            matches!(next_event(&mut windows), AppEvent::Window(w) if w.window_title == "todo.md")
+        );
+        assert!(matches!(next_event(&mut blocked), AppEvent::AppBlocked(_)));
+    }
+
+    #[test]
+    fn test_late_subscriber_replay() {
+        let monitor = Monitor::builder().replay_capacity(3).build();
+        monitor.send_window_event(window_event("first"));
+        monitor.send_keyboard_event(keypresses(1));
+        monitor.send_window_event(window_event("second"));
+        monitor.send_window_event(window_event("third"));
+
+        // Only the last three events are kept, and the filter applies to them
+        let mut late =
+            monitor.stream_with(StreamOptions::new().only(&[EventKind::Window]).replay(10));
+        monitor.send_window_event(window_event("live"));
+
+        let titles: Vec<String> = (0..3)
+            .map(|_| match next_event(&mut late) {
+                AppEvent::Window(window) => window.window_title,
+                other => panic!("unexpected event {:?}", other),
+            })
+            .collect();
+        assert_eq!(titles, vec!["second", "third", "live"]);
+
+        // Without a replay a late subscriber only sees new events
+        let mut live = monitor.stream();
+        monitor.send_keyboard_event(keypresses(2));
+        assert!(matches!(next_event(&mut live), AppEvent::Keyboard(k) if k.keypresses == 2));
+    }
+}
@@ -0,0 +1,255 @@
+use crate::blocking::BlockList;
+use crate::{AppEvent, BlockableItem, MonitorError};
+use chrono::{Duration as ChronoDuration, NaiveDateTime};
+use std::time::Duration;
+
+/// What a user has to get through before an unlock is granted
+#[derive(Clone, Debug, PartialEq)]
+pub enum UnlockFriction {
+    /// Granted automatically once this much time has passed
+    Delay(Duration),
+    /// Granted once the phrase is typed back to `confirm_unlock`
+    Phrase(String),
+}
+
+/// A temporary exemption from blocking for one item, see `request_unlock`
+#[derive(Clone, Debug)]
+pub struct Unlock {
+    pub id: u64,
+    pub item: BlockableItem,
+    pub friction: UnlockFriction,
+    /// How long the exemption lasts once granted
+    pub duration: Duration,
+    pub requested_at: NaiveDateTime,
+    pub granted_at: Option<NaiveDateTime>,
+}
+
+fn add(time: NaiveDateTime, duration: Duration) -> NaiveDateTime {
+    ChronoDuration::from_std(duration)
+        .ok()
+        .and_then(|duration| time.checked_add_signed(duration))
+        .unwrap_or(NaiveDateTime::MAX)
+}
+
+impl Unlock {
+    pub fn is_granted(&self) -> bool {
+        self.granted_at.is_some()
+    }
+
+    pub fn expires_at(&self) -> Option<NaiveDateTime> {
+        self.granted_at
+            .map(|granted_at| add(granted_at, self.duration))
+    }
+
+    fn granted_event(&self) -> AppEvent {
+        AppEvent::UnlockGranted {
+            id: self.id,
+            pattern: self.item.app_external_id.clone(),
+            duration_seconds: self.duration.as_secs(),
+        }
+    }
+}
+
+struct Entry {
+    unlock: Unlock,
+    matcher: BlockList,
+}
+
+/// Pending and granted unlocks, advanced by the monitor's ticks
+#[derive(Default)]
+pub(crate) struct Unlocks {
+    next_id: u64,
+    entries: Vec<Entry>,
+}
+
+impl Unlocks {
+    pub fn request(
+        &mut self,
+        item: BlockableItem,
+        friction: UnlockFriction,
+        duration: Duration,
+        now: NaiveDateTime,
+    ) -> (u64, AppEvent) {
+        let id = self.next_id;
+        self.next_id += 1;
+        let event = AppEvent::UnlockRequested {
+            id,
+            pattern: item.app_external_id.clone(),
+        };
+        self.entries.push(Entry {
+            matcher: BlockList::new(std::slice::from_ref(&item), "", true),
+            unlock: Unlock {
+                id,
+                item,
+                friction,
+                duration,
+                requested_at: now,
+                granted_at: None,
+            },
+        });
+        (id, event)
+    }
+
+    /// Grants a phrase unlock if `typed` matches its phrase, ignoring
+    /// surrounding whitespace
+    pub fn confirm(
+        &mut self,
+        id: u64,
+        typed: &str,
+        now: NaiveDateTime,
+    ) -> Result<AppEvent, MonitorError> {
+        let entry = self
+            .entries
+            .iter_mut()
+            .find(|entry| entry.unlock.id == id)
+            .ok_or_else(|| MonitorError::UnlockRejected(format!("no unlock request {}", id)))?;
+        let unlock = &mut entry.unlock;
+        if unlock.is_granted() {
+            return Err(MonitorError::UnlockRejected(format!(
+                "unlock {} was already granted",
+                id
+            )));
+        }
+        match &unlock.friction {
+            UnlockFriction::Phrase(phrase) if phrase.trim() == typed.trim() => {
+                unlock.granted_at = Some(now);
+                Ok(unlock.granted_event())
+            }
+            UnlockFriction::Phrase(_) => Err(MonitorError::UnlockRejected(
+                "the phrase doesn't match".to_string(),
+            )),
+            UnlockFriction::Delay(_) => Err(MonitorError::UnlockRejected(format!(
+                "unlock {} is granted after its delay",
+                id
+            ))),
+        }
+    }
+
+    /// Grants delayed unlocks whose delay has passed and removes expired
+    /// ones. Returns the grant and expiry events to send.
+    pub fn tick(&mut self, now: NaiveDateTime) -> Vec<AppEvent> {
+        let mut events = Vec::new();
+        for entry in &mut self.entries {
+            let unlock = &mut entry.unlock;
+            if let (None, UnlockFriction::Delay(delay)) = (unlock.granted_at, &unlock.friction) {
+                let granted_at = add(unlock.requested_at, *delay);
+                if now >= granted_at {
+                    unlock.granted_at = Some(granted_at);
+                    events.push(unlock.granted_event());
+                }
+            }
+        }
+        self.entries.retain(|entry| {
+            let expired = entry
+                .unlock
+                .expires_at()
+                .is_some_and(|expires_at| now >= expires_at);
+            if expired {
+                events.push(AppEvent::UnlockExpired {
+                    id: entry.unlock.id,
+                    pattern: entry.unlock.item.app_external_id.clone(),
+                });
+            }
+            !expired
+        });
+        events
+    }
+
+    /// Whether a granted unlock covers the url or app id
+    pub fn is_exempt(&self, external_app_id: &str) -> bool {
+        self.entries.iter().any(|entry| {
+            entry.unlock.is_granted() && entry.matcher.matching_item(external_app_id).is_some()
+        })
+    }
+
+    pub fn list(&self) -> Vec<Unlock> {
+        self.entries
+            .iter()
+            .map(|entry| entry.unlock.clone())
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::RuleKind;
+    use chrono::NaiveDate;
+
+    const MINUTE: Duration = Duration::from_secs(60);
+
+    fn at(hour: u32, minute: u32) -> NaiveDateTime {
+        NaiveDate::from_ymd_opt(2024, 1, 1)
+            .unwrap()
+            .and_hms_opt(hour, minute, 0)
+            .unwrap()
+    }
+
+    fn reddit() -> BlockableItem {
+        BlockableItem::new("reddit.com".to_string(), true).with_rule(RuleKind::Domain)
+    }
+
+    #[test]
+    fn test_delayed_unlock_is_granted_and_expires() {
+        let mut unlocks = Unlocks::default();
+        let (id, requested) = unlocks.request(
+            reddit(),
+            UnlockFriction::Delay(2 * MINUTE),
+            10 * MINUTE,
+            at(9, 0),
+        );
+        assert!(matches!(requested, AppEvent::UnlockRequested { id: 0, .. }));
+        assert!(unlocks.tick(at(9, 1)).is_empty());
+        assert!(!unlocks.is_exempt("www.reddit.com"));
+        assert!(unlocks.confirm(id, "", at(9, 1)).is_err());
+
+        // Granted when the delay is up, even if the tick comes late
+        match unlocks.tick(at(9, 3)).as_slice() {
+            [AppEvent::UnlockGranted {
+                duration_seconds, ..
+            }] => assert_eq!(*duration_seconds, 600),
+            other => panic!("unexpected events {:?}", other),
+        }
+        assert!(unlocks.is_exempt("www.reddit.com"));
+        assert!(!unlocks.is_exempt("news.example"));
+        assert_eq!(unlocks.list()[0].expires_at(), Some(at(9, 12)));
+
+        assert!(unlocks.tick(at(9, 11)).is_empty());
+        assert!(matches!(
+            unlocks.tick(at(9, 12)).as_slice(),
+            [AppEvent::UnlockExpired { id: 0, .. }]
+        ));
+        assert!(!unlocks.is_exempt("www.reddit.com"));
+        assert!(unlocks.list().is_empty());
+    }
+
+    #[test]
+    fn test_phrase_unlock() {
+        let mut unlocks = Unlocks::default();
+        let phrase = "I am choosing to break my focus".to_string();
+        let (id, _) = unlocks.request(
+            reddit(),
+            UnlockFriction::Phrase(phrase),
+            5 * MINUTE,
+            at(9, 0),
+        );
+        assert!(unlocks.tick(at(10, 0)).is_empty());
+        assert!(matches!(
+            unlocks.confirm(id, "i am choosing", at(10, 0)),
+            Err(MonitorError::UnlockRejected(_))
+        ));
+        assert!(matches!(
+            unlocks.confirm(id + 1, "I am choosing to break my focus", at(10, 0)),
+            Err(MonitorError::UnlockRejected(_))
+        ));
+        assert!(matches!(
+            unlocks.confirm(id, " I am choosing to break my focus\n", at(10, 0)),
+            Ok(AppEvent::UnlockGranted { .. })
+        ));
+        assert!(unlocks.is_exempt("reddit.com"));
+        assert!(unlocks
+            .confirm(id, "I am choosing to break my focus", at(10, 1))
+            .is_err());
+        assert_eq!(unlocks.tick(at(10, 5)).len(), 1);
+    }
+}